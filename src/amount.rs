@@ -26,6 +26,9 @@ impl Display for Amount {
             Symbol::Crypto(ref symbol) => {
                 f.write_fmt(format_args!("{:.2} {:}", self.amount, symbol))?;
             }
+            Symbol::OnChain(..) | Symbol::Nft(_) => {
+                f.write_fmt(format_args!("{:.2} {:}", self.amount, self.symbol))?;
+            }
         }
         Ok(())
     }
@@ -57,6 +60,26 @@ macro_rules! usd {
     };
 }
 
+#[macro_export]
+macro_rules! cad {
+    ($amount:expr) => {
+        Amount {
+            amount: stringify!($amount).parse().unwrap(),
+            symbol: Symbol::Fiat(Fiat::CAD),
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! gbp {
+    ($amount:expr) => {
+        Amount {
+            amount: stringify!($amount).parse().unwrap(),
+            symbol: Symbol::Fiat(Fiat::GBP),
+        }
+    };
+}
+
 #[macro_export]
 macro_rules! usdt {
     ($amount:expr) => {
@@ -0,0 +1,219 @@
+use std::io::{self, Write};
+
+use anyhow::Result;
+
+use crate::error::{ReportError, Warnings};
+use crate::fx::FxRates;
+use crate::jurisdiction::Jurisdiction;
+use crate::portfolio::{Migration, Trade};
+use crate::report::read_portfolio;
+use crate::symbol::Symbol;
+
+// basis_gaps lists every disposal of more than was held, which has no cost basis for the
+// shortfall, or writes a manual transaction for each to be filled in with the missing acquisition
+pub async fn basis_gaps(
+    denomination: &Symbol,
+    migrations: &[Migration],
+    events: &[Trade],
+    fx_rates: FxRates,
+    jurisdiction: &dyn Jurisdiction,
+    stubs: bool,
+) -> Result<()> {
+    let mut warnings = Warnings::new(false);
+    let portfolio = read_portfolio(
+        io::stdin(),
+        denomination,
+        migrations,
+        events,
+        fx_rates,
+        jurisdiction,
+        &mut warnings,
+    )
+    .await?;
+    warnings.summarize();
+
+    let gaps = jurisdiction.shortfalls(&portfolio, denomination);
+    if gaps.is_empty() {
+        eprintln!("Every disposal was matched against acquisitions");
+    }
+    if stubs {
+        write_stubs(&mut io::stdout(), &gaps, denomination)
+    } else {
+        write_gaps(&mut io::stdout(), &gaps)
+    }
+}
+
+// write_gaps writes a row for each disposal with a shortfall, with the transaction that made it
+fn write_gaps(output: &mut dyn Write, gaps: &[ReportError]) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(output);
+    writer.write_record(["Date", "Asset", "Shortfall", "Provider", "ID"])?;
+
+    for gap in gaps {
+        let ReportError::InsufficientLots {
+            symbol,
+            when,
+            shortfall,
+            source,
+        } = gap
+        else {
+            continue;
+        };
+        writer.write_record([
+            when.format("%Y-%m-%d").to_string(),
+            symbol.description(),
+            shortfall.to_string(),
+            source
+                .as_ref()
+                .map_or(String::new(), |s| s.provider.clone()),
+            source.as_ref().map_or(String::new(), |s| s.id.clone()),
+        ])?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+// write_stubs writes a manual transaction acquiring each shortfall, for the configuration, of the
+// same asset on the same network. They're
+// dated the day of the disposal and have no cost, both of which should be replaced with those of
+// the acquisition that's missing before exporting again.
+fn write_stubs(output: &mut dyn Write, gaps: &[ReportError], denomination: &Symbol) -> Result<()> {
+    for gap in gaps {
+        let ReportError::InsufficientLots {
+            symbol,
+            when,
+            shortfall,
+            source,
+        } = gap
+        else {
+            continue;
+        };
+        let id = match source {
+            Some(source) => format!("basis-gap-{}-{}", source.provider, source.id),
+            None => format!("basis-gap-{}-{}", symbol, when.format("%Y-%m-%d")),
+        };
+
+        writeln!(output, "# {}", gap)?;
+        writeln!(output, "[[transactions]]")?;
+        writeln!(output, "id = \"{}\"", id)?;
+        writeln!(output, "market = \"{}-{}\"", symbol, denomination.symbol())?;
+        writeln!(output, "token = \"{}\"", symbol)?;
+        writeln!(output, "amount = \"{}\"", shortfall)?;
+        writeln!(output, "rate = \"0\"")?;
+        writeln!(output, "fiat_rate = \"0\"")?;
+        writeln!(output, "fiat_amount = \"0\"")?;
+        writeln!(output, "fiat = \"{}\"", denomination.symbol())?;
+        writeln!(output, "created_at = {}", when.format("%Y-%m-%d"))?;
+        writeln!(output)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    use crate::amount::Amount;
+    use crate::config::Transaction;
+    use crate::portfolio::{Kind, Portfolio, Source};
+    use crate::symbol::{Chain, Crypto, Fiat, USD};
+    use crate::{btc, usd};
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Stubs {
+        transactions: Vec<Transaction>,
+    }
+
+    #[test]
+    fn test_basis_gaps() {
+        let mut portfolio = Portfolio::new();
+        let trades = [
+            ("a", Utc.ymd(2020, 1, 1), usd!(1000), btc!(1)),
+            ("b", Utc.ymd(2020, 3, 1), btc!(1.5), usd!(3000)),
+        ];
+        for (id, date, offered, gained) in trades {
+            portfolio.add_sourced_trade(
+                &Trade {
+                    when: date.and_hms(0, 0, 0),
+                    kind: Kind::Trade { offered, gained },
+                },
+                &Source {
+                    id: id.to_string(),
+                    provider: "coinbase".to_string(),
+                },
+            );
+        }
+        let gaps = portfolio.shortfalls(&USD);
+
+        let mut output = Vec::new();
+        write_gaps(&mut output, &gaps).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Date,Asset,Shortfall,Provider,ID\n\
+             2020-03-01,BTC,0.5,coinbase,b\n"
+        );
+
+        let mut output = Vec::new();
+        write_stubs(&mut output, &gaps, &USD).unwrap();
+        let stubs = String::from_utf8(output).unwrap();
+        assert_eq!(
+            stubs,
+            "# 0.5000 BTC more than was held was disposed of on 2020-03-01, it has no cost basis\n\
+             [[transactions]]\n\
+             id = \"basis-gap-coinbase-b\"\n\
+             market = \"BTC-USD\"\n\
+             token = \"BTC\"\n\
+             amount = \"0.5\"\n\
+             rate = \"0\"\n\
+             fiat_rate = \"0\"\n\
+             fiat_amount = \"0\"\n\
+             fiat = \"USD\"\n\
+             created_at = 2020-03-01\n\n"
+        );
+
+        let parsed: Stubs = toml::from_str(&stubs).unwrap();
+        assert_eq!(parsed.transactions.len(), 1);
+        assert_eq!(parsed.transactions[0].amount, "0.5".parse().unwrap());
+    }
+
+    #[test]
+    fn test_basis_gaps_on_chain() {
+        let usdc = |amount: u32| Amount {
+            amount: amount.into(),
+            symbol: Symbol::OnChain(Crypto::USDC, Chain::Polygon),
+        };
+        let mut portfolio = Portfolio::new();
+        portfolio.add_sourced_trade(
+            &Trade {
+                when: Utc.ymd(2021, 5, 1).and_hms(0, 0, 0),
+                kind: Kind::Trade {
+                    offered: usdc(100),
+                    gained: usd!(100),
+                },
+            },
+            &Source {
+                id: "0x1".to_string(),
+                provider: "polygonscan".to_string(),
+            },
+        );
+        let gaps = portfolio.shortfalls(&USD);
+
+        let mut output = Vec::new();
+        write_gaps(&mut output, &gaps).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Date,Asset,Shortfall,Provider,ID\n\
+             2021-05-01,USDC on Polygon,100,polygonscan,0x1\n"
+        );
+
+        // The stub acquires USDC on Polygon, rather than on no network at all
+        let mut output = Vec::new();
+        write_stubs(&mut output, &gaps, &USD).unwrap();
+        let parsed: Stubs = toml::from_str(&String::from_utf8(output).unwrap()).unwrap();
+        assert_eq!(parsed.transactions[0].market, "USDC@polygon-USD");
+        assert_eq!(parsed.transactions[0].token, "USDC@polygon");
+    }
+}
@@ -1,16 +1,43 @@
 use std::str::FromStr;
 
 use anyhow::Result;
+use bigdecimal::{BigDecimal, Zero};
 use coinbase_rs::{Private, MAIN_URL};
+use futures::future::LocalBoxFuture;
 use futures::pin_mut;
 use futures::stream::StreamExt;
+use futures::FutureExt;
 use uuid::Uuid;
 
-use crate::types::Transaction;
+use crate::provider::{after, Provider};
+use crate::types::{Balance, Category, DateTime, Transaction};
 
 const PROVIDER: &str = "coinbase";
 
-pub async fn transactions(key: &str, secret: &str) -> Result<Vec<Transaction>> {
+// Coinbase fetches the transactions and balances of every Coinbase account
+pub struct Coinbase {
+    pub key: String,
+    pub secret: String,
+}
+
+impl Provider for Coinbase {
+    fn name(&self) -> &'static str {
+        PROVIDER
+    }
+
+    fn transactions(
+        &self,
+        since: Option<DateTime>,
+    ) -> LocalBoxFuture<'_, Result<Vec<Transaction>>> {
+        async move { Ok(after(transactions(&self.key, &self.secret).await?, since)) }.boxed_local()
+    }
+
+    fn balances(&self) -> LocalBoxFuture<'_, Result<Vec<Balance>>> {
+        balances(&self.key, &self.secret).boxed_local()
+    }
+}
+
+async fn transactions(key: &str, secret: &str) -> Result<Vec<Transaction>> {
     let client: Private = Private::new(MAIN_URL, key, secret);
 
     let mut transactions = Vec::new();
@@ -27,27 +54,44 @@ pub async fn transactions(key: &str, secret: &str) -> Result<Vec<Transaction>> {
                 let code = account.currency.code;
                 while let Some(transactions_result) = transactions_stream.next().await {
                     for trade in transactions_result? {
-                        if trade.r#type != "buy" && trade.r#type != "sell" {
+                        let category = match category(&trade.r#type, &trade.amount.amount) {
+                            Some(category) => category,
+                            None => {
+                                eprintln!(
+                                    "Skipping Coinbase {} transaction {}",
+                                    trade.r#type, trade.id
+                                );
+                                continue;
+                            }
+                        };
+
+                        // Only fiat moves in and out of accounts held in the native currency
+                        let moves_fiat =
+                            matches!(category, Category::Deposit | Category::Withdrawal);
+                        if code == trade.native_amount.currency && !moves_fiat {
                             continue;
                         }
-
-                        if code == trade.native_amount.currency {
+                        if trade.amount.amount.is_zero() {
                             continue;
                         }
 
-                        let usd_amount = trade.native_amount.amount;
+                        let fiat_amount = trade.native_amount.amount;
                         let trade_amount = trade.amount.amount;
-                        let usd_rate = &usd_amount / &trade_amount;
+                        let fiat_rate = &fiat_amount / &trade_amount;
 
                         let product_id = format!("{}-{}", &code, &trade.native_amount.currency);
                         let transaction = Transaction {
                             id: trade.id.to_string(),
+                            hash: None,
                             market: product_id,
                             token: code.clone(),
                             amount: trade_amount,
-                            rate: usd_rate.clone(),
-                            usd_rate,
-                            usd_amount,
+                            rate: fiat_rate.clone(),
+                            fiat_rate,
+                            fiat_amount,
+                            fiat: trade.native_amount.currency.clone(),
+                            fee: BigDecimal::zero(),
+                            category,
                             created_at: trade.created_at,
                             provider: PROVIDER,
                         };
@@ -60,3 +104,55 @@ pub async fn transactions(key: &str, secret: &str) -> Result<Vec<Transaction>> {
 
     Ok(transactions)
 }
+
+// balances lists what's held in every Coinbase account
+async fn balances(key: &str, secret: &str) -> Result<Vec<Balance>> {
+    let client: Private = Private::new(MAIN_URL, key, secret);
+
+    let mut balances = Vec::new();
+
+    let accounts_stream = client.accounts();
+    pin_mut!(accounts_stream);
+
+    while let Some(accounts_result) = accounts_stream.next().await {
+        for account in accounts_result? {
+            balances.push(Balance {
+                token: account.currency.code,
+                amount: account.balance.amount,
+                provider: PROVIDER,
+            });
+        }
+    }
+
+    Ok(balances)
+}
+
+// category classifies a Coinbase transaction type, income is paid into the account it's earned in
+// and valued at the market price when it was paid
+fn category(r#type: &str, amount: &BigDecimal) -> Option<Category> {
+    match r#type {
+        "buy" | "sell" => Some(Category::Trade),
+        // Each side of a conversion is recorded in its own account, as a sale of one token and a
+        // purchase of the other at the same value
+        "trade" => Some(Category::Conversion),
+        "interest" => Some(Category::Interest),
+        "staking_reward" | "inflation_reward" => Some(Category::Staking),
+        // Coinbase Earn payouts and USDC rewards
+        "earn_payout" | "incentives_rewards_payout" => Some(Category::Reward),
+        "send"
+        | "transfer"
+        | "exchange_deposit"
+        | "exchange_withdrawal"
+        | "pro_deposit"
+        | "pro_withdrawal" => {
+            if amount < &BigDecimal::zero() {
+                Some(Category::TransferOut)
+            } else {
+                Some(Category::TransferIn)
+            }
+        }
+        "fiat_deposit" => Some(Category::Deposit),
+        "fiat_withdrawal" => Some(Category::Withdrawal),
+        _ => None,
+    }
+}
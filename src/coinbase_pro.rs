@@ -1,21 +1,62 @@
 use std::collections::HashSet;
 use std::error::Error;
-use std::thread;
 use std::time::Duration;
 
-use bigdecimal::{BigDecimal, FromPrimitive, Zero};
+use anyhow::anyhow;
+use bigdecimal::{BigDecimal, FromPrimitive, One, Zero};
 use coinbase_pro_rs::Uuid;
 use coinbase_pro_rs::structs::private::*;
 use coinbase_pro_rs::structs::public::*;
 use coinbase_pro_rs::{ASync, CBError, Private, MAIN_URL};
+use futures::future::LocalBoxFuture;
 use futures::pin_mut;
 use futures::stream::{Stream, StreamExt};
+use futures::FutureExt;
 
+use crate::provider::{after, Provider};
 use crate::symbol::Symbol;
-use crate::types::{DateTime, Transaction};
+use crate::types::{Balance, Category, DateTime, Transaction};
 
 const PROVIDER: &str = "coinbase-pro";
 
+// CoinbasePro fetches the trades and transfers of every Coinbase Pro account, valued in the
+// denomination
+pub struct CoinbasePro {
+    pub key: String,
+    pub secret: String,
+    pub passphrase: String,
+    pub denomination: Symbol,
+}
+
+impl Provider for CoinbasePro {
+    fn name(&self) -> &'static str {
+        PROVIDER
+    }
+
+    fn transactions(
+        &self,
+        since: Option<DateTime>,
+    ) -> LocalBoxFuture<'_, anyhow::Result<Vec<Transaction>>> {
+        async move {
+            let transactions =
+                transactions(&self.key, &self.secret, &self.passphrase, self.denomination)
+                    .await
+                    .map_err(|e| anyhow!("{}", e))?;
+            Ok(after(transactions, since))
+        }
+        .boxed_local()
+    }
+
+    fn balances(&self) -> LocalBoxFuture<'_, anyhow::Result<Vec<Balance>>> {
+        async move {
+            balances(&self.key, &self.secret, &self.passphrase)
+                .await
+                .map_err(|e| anyhow!("{}", e))
+        }
+        .boxed_local()
+    }
+}
+
 fn product_rhs(product_id: &str) -> Option<String> {
     product_id
         .split('-')
@@ -46,23 +87,26 @@ impl ThrottledClient {
         product_id: &str,
         time_of_trade: DateTime,
     ) -> Result<BigDecimal, Box<dyn Error>> {
-        thread::sleep(Duration::from_millis(350));
+        tokio::time::sleep(Duration::from_millis(350)).await;
 
         let start = Some(time_of_trade);
         let bucket = chrono::Duration::seconds(60);
-        let end = Some(time_of_trade.checked_add_signed(bucket).unwrap());
+        let end = Some(
+            time_of_trade
+                .checked_add_signed(bucket)
+                .ok_or("Invalid time of trade")?,
+        );
         let market_at_trade = self
             .client
             .public()
             .get_candles(product_id, start, end, Granularity::M1)
-            .await
-            .unwrap();
+            .await?;
 
         let mut rate = BigDecimal::zero();
         if let Some(candle) = market_at_trade.first() {
-            rate = (BigDecimal::from_f64(candle.1).unwrap()
-                + BigDecimal::from_f64(candle.2).unwrap())
-                / BigDecimal::from_f64(2.0).unwrap();
+            let low = BigDecimal::from_f64(candle.1).ok_or("Invalid rate")?;
+            let high = BigDecimal::from_f64(candle.2).ok_or("Invalid rate")?;
+            rate = (low + high) / BigDecimal::from(2);
         }
         Ok(rate)
     }
@@ -73,7 +117,7 @@ impl ThrottledClient {
         time_of_trade: DateTime,
         denomination: Symbol,
     ) -> Result<BigDecimal, Box<dyn Error>> {
-        thread::sleep(Duration::from_millis(350));
+        tokio::time::sleep(Duration::from_millis(350)).await;
 
         if let Ok(token_rate) = self.get_rate_at(product_id, time_of_trade).await {
             if let Some(product_lhs) = product_rhs(product_id) {
@@ -95,22 +139,22 @@ impl ThrottledClient {
     }
 
     async fn get_accounts(&self) -> Result<Vec<Account>, CBError> {
-        thread::sleep(Duration::from_millis(350));
+        tokio::time::sleep(Duration::from_millis(350)).await;
 
         self.client.get_accounts().await
     }
 
-    fn get_account_hist_stream<'a>(
+    async fn get_account_hist_stream<'a>(
         &'a self,
         id: Uuid,
     ) -> impl Stream<Item = Result<Vec<AccountHistory>, CBError>> + 'a {
-        thread::sleep(Duration::from_millis(350));
+        tokio::time::sleep(Duration::from_millis(350)).await;
 
         self.client.get_account_hist_stream(id)
     }
 }
 
-pub async fn transactions(
+async fn transactions(
     key: &str,
     secret: &str,
     passphrase: &str,
@@ -121,48 +165,129 @@ pub async fn transactions(
     let mut observed_transactions = HashSet::new();
     let mut transactions = Vec::new();
 
-    let accounts = client.get_accounts().await.unwrap();
+    let accounts = client.get_accounts().await?;
     for account in accounts {
-        if account.currency == denomination.symbol() {
-            continue;
-        }
+        // Fiat accounts are only paid into and out of, every trade is taken from the account of
+        // the token bought or sold
+        let fiat = matches!(
+            Symbol::from_provider(PROVIDER, &account.currency),
+            Ok(Symbol::Fiat(_))
+        );
 
-        let account_hist_stream = client.get_account_hist_stream(account.id);
+        let account_hist_stream = client.get_account_hist_stream(account.id).await;
         pin_mut!(account_hist_stream);
 
         while let Some(account_hist_result) = account_hist_stream.next().await {
             for trade in account_hist_result? {
-                if let AccountHistoryDetails::Match {
-                    product_id,
-                    trade_id,
-                    ..
-                } = trade.details
-                {
-                    if observed_transactions.contains(&trade_id) {
-                        continue;
+                match trade.details {
+                    AccountHistoryDetails::Match { .. } if fiat => {}
+                    AccountHistoryDetails::Match {
+                        product_id,
+                        trade_id,
+                        ..
+                    } => {
+                        if observed_transactions.contains(&trade_id) {
+                            continue;
+                        }
+                        observed_transactions.insert(trade_id);
+
+                        let time_of_trade = trade.created_at;
+
+                        let rate = client.get_rate_at(&product_id, time_of_trade).await?;
+                        let denomination_rate = client
+                            .get_denomination_rate(&product_id, time_of_trade, denomination)
+                            .await?;
+                        let amount = BigDecimal::from_f64(trade.amount).ok_or("Invalid amount")?;
+
+                        let transaction = Transaction {
+                            id: trade_id.to_string(),
+                            hash: None,
+                            market: product_id,
+                            token: account.currency.clone(),
+                            fiat_amount: &amount * &denomination_rate,
+                            amount,
+                            rate,
+                            fiat_rate: denomination_rate,
+                            fiat: denomination.symbol(),
+                            fee: BigDecimal::zero(),
+                            category: Category::Trade,
+                            created_at: Some(time_of_trade),
+                            provider: PROVIDER,
+                        };
+                        transactions.push(transaction);
+                    }
+                    AccountHistoryDetails::Transfer {
+                        transfer_id,
+                        transfer_type,
+                    } if fiat => {
+                        let category = match transfer_type {
+                            AccountHistoryDetailsTransferType::Deposit => Category::Deposit,
+                            AccountHistoryDetailsTransferType::Withdraw => Category::Withdrawal,
+                        };
+                        let amount = BigDecimal::from_f64(trade.amount).ok_or("Invalid amount")?;
+
+                        let transaction = Transaction {
+                            id: transfer_id.to_string(),
+                            hash: None,
+                            market: format!("{}-{}", account.currency, account.currency),
+                            token: account.currency.clone(),
+                            fiat_amount: amount.clone(),
+                            amount,
+                            rate: BigDecimal::one(),
+                            fiat_rate: BigDecimal::one(),
+                            fiat: account.currency.clone(),
+                            fee: BigDecimal::zero(),
+                            category,
+                            created_at: Some(trade.created_at),
+                            provider: PROVIDER,
+                        };
+                        transactions.push(transaction);
                     }
-                    observed_transactions.insert(trade_id);
-
-                    let time_of_trade = trade.created_at;
-
-                    let rate = client.get_rate_at(&product_id, time_of_trade).await?;
-                    let denomination_rate = client
-                        .get_denomination_rate(&product_id, time_of_trade, denomination)
-                        .await?;
-                    let amount = BigDecimal::from_f64(trade.amount).unwrap() * &denomination_rate;
-
-                    let transaction = Transaction {
-                        id: trade_id.to_string(),
-                        market: product_id,
-                        token: account.currency.clone(),
-                        amount: BigDecimal::from_f64(trade.amount).unwrap(),
-                        rate,
-                        usd_rate: denomination_rate,
-                        usd_amount: amount,
-                        created_at: Some(time_of_trade),
-                        provider: PROVIDER,
-                    };
-                    transactions.push(transaction);
+                    // Deposits and withdrawals of tokens move them to and from other accounts,
+                    // such as Coinbase, and are valued for reference only, at nothing if the
+                    // token isn't traded for the denomination
+                    AccountHistoryDetails::Transfer {
+                        transfer_id,
+                        transfer_type,
+                    } => {
+                        let category = match transfer_type {
+                            AccountHistoryDetailsTransferType::Deposit => Category::TransferIn,
+                            AccountHistoryDetailsTransferType::Withdraw => Category::TransferOut,
+                        };
+                        let product_id = format!("{}-{}", account.currency, denomination.symbol());
+                        let fiat_rate = match client
+                            .get_rate_at(&product_id, trade.created_at)
+                            .await
+                        {
+                            Ok(rate) => rate,
+                            Err(e) => {
+                                eprintln!(
+                                    "No {} rate for transfer {}, it's valued at nothing: {}",
+                                    product_id, transfer_id, e
+                                );
+                                BigDecimal::zero()
+                            }
+                        };
+                        let amount = BigDecimal::from_f64(trade.amount).ok_or("Invalid amount")?;
+
+                        let transaction = Transaction {
+                            id: transfer_id.to_string(),
+                            hash: None,
+                            market: product_id,
+                            token: account.currency.clone(),
+                            fiat_amount: &amount * &fiat_rate,
+                            amount,
+                            rate: fiat_rate.clone(),
+                            fiat_rate,
+                            fiat: denomination.symbol(),
+                            fee: BigDecimal::zero(),
+                            category,
+                            created_at: Some(trade.created_at),
+                            provider: PROVIDER,
+                        };
+                        transactions.push(transaction);
+                    }
+                    _ => {}
                 }
             }
         }
@@ -170,3 +295,23 @@ pub async fn transactions(
 
     Ok(transactions)
 }
+
+// balances lists what's held in every Coinbase Pro account, including any on hold for open orders
+async fn balances(
+    key: &str,
+    secret: &str,
+    passphrase: &str,
+) -> Result<Vec<Balance>, Box<dyn Error>> {
+    let client = ThrottledClient::new(key, secret, passphrase);
+
+    let mut balances = Vec::new();
+    for account in client.get_accounts().await? {
+        balances.push(Balance {
+            token: account.currency,
+            amount: BigDecimal::from_f64(account.balance).ok_or("Invalid balance")?,
+            provider: PROVIDER,
+        });
+    }
+
+    Ok(balances)
+}
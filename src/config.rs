@@ -4,11 +4,16 @@ use std::io;
 use std::io::prelude::*;
 use std::path::PathBuf;
 
-use bigdecimal::BigDecimal;
+use bigdecimal::{BigDecimal, Zero};
+use serde::de::{self, Deserialize, Deserializer};
 
+use crate::amount::Amount;
+use crate::fx::FxRates;
+use crate::jurisdiction::{self, Country, Jurisdiction, YearStart};
+use crate::portfolio::{self, Matching};
 use crate::report::Format;
-use crate::symbol::{Symbol, USD};
-use crate::types::{self, DateTime};
+use crate::symbol::{Chain, Symbol, USD};
+use crate::types::{self, Category, DateTime};
 
 #[derive(Clone, Deserialize, Debug, PartialEq)]
 pub struct Transaction {
@@ -17,13 +22,78 @@ pub struct Transaction {
     pub token: String,
     pub amount: BigDecimal,
     pub rate: BigDecimal,
-    pub usd_rate: BigDecimal,
-    pub usd_amount: BigDecimal,
-    pub created_at: Option<toml::value::Datetime>,
+    #[serde(alias = "usd_rate")]
+    pub fiat_rate: BigDecimal,
+    #[serde(alias = "usd_amount")]
+    pub fiat_amount: BigDecimal,
+    pub fiat: Option<String>,
+    pub category: Option<Category>,
+    #[serde(default, deserialize_with = "optional_datetime")]
+    pub created_at: Option<DateTime>,
 }
 
 impl Eq for Transaction {}
 
+#[derive(Clone, Deserialize, Debug, PartialEq)]
+pub struct FxRate {
+    pub from: String,
+    pub to: String,
+    pub rate: BigDecimal,
+    #[serde(deserialize_with = "datetime")]
+    pub date: DateTime,
+}
+
+impl Eq for FxRate {}
+
+#[derive(Clone, Deserialize, Debug, PartialEq)]
+pub struct Migration {
+    pub from: String,
+    pub to: String,
+    pub ratio: BigDecimal,
+    #[serde(deserialize_with = "datetime")]
+    pub date: DateTime,
+}
+
+impl Eq for Migration {}
+
+#[derive(Clone, Copy, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Gift,
+    GiftReceived,
+    Donation,
+    Loss,
+    Fork,
+}
+
+// ForkPolicy is how tokens credited by a fork are taxed
+#[derive(Clone, Copy, Default, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ForkPolicy {
+    // acquired for nothing, the whole of what they're later sold for is a gain
+    #[default]
+    NonIncome,
+    // income at their value when credited, which becomes their cost basis
+    Income,
+}
+
+// Event is a change in holdings that isn't a trade, e.g. a gift, with value and basis in the
+// denomination
+#[derive(Clone, Deserialize, Debug, PartialEq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub token: String,
+    pub amount: BigDecimal,
+    #[serde(deserialize_with = "datetime")]
+    pub date: DateTime,
+    pub value: Option<BigDecimal>,
+    pub basis: Option<BigDecimal>,
+    #[serde(default, deserialize_with = "optional_datetime")]
+    pub acquired: Option<DateTime>,
+}
+
+impl Eq for Event {}
+
 pub enum ConfigError {
     IoError(io::Error),
     TomlError(toml::de::Error),
@@ -55,7 +125,14 @@ impl fmt::Debug for ConfigError {
 pub struct Config {
     pub exchanges: Vec<Exchange>,
     transactions: Option<Vec<Transaction>>,
+    migrations: Option<Vec<Migration>>,
+    fx_rates: Option<Vec<FxRate>>,
+    events: Option<Vec<Event>>,
+    fork_policy: Option<ForkPolicy>,
+    jurisdiction: Option<Country>,
+    lot_matching: Option<Matching>,
     pub tax_year: u16,
+    tax_year_start: Option<String>,
     pub accounts: Option<Vec<web3::types::H160>>,
     pub denomination: Option<String>,
     pub report_format: Option<Format>,
@@ -71,18 +148,151 @@ impl Config {
             .iter()
             .map(|t| types::Transaction {
                 id: t.id.clone(),
+                hash: None,
                 market: t.market.clone(),
                 token: t.token.clone(),
                 amount: t.amount.clone(),
                 rate: t.rate.clone(),
-                usd_rate: t.usd_rate.clone(),
-                usd_amount: t.usd_amount.clone(),
-                created_at: t.created_at.clone().map(chrono_to_toml_date),
+                fiat_rate: t.fiat_rate.clone(),
+                fiat_amount: t.fiat_amount.clone(),
+                fiat: t.fiat.clone().unwrap_or_else(|| USD.symbol()),
+                fee: BigDecimal::zero(),
+                category: t.category.unwrap_or_default(),
+                created_at: t.created_at,
                 provider: PROVIDER,
             })
             .collect()
     }
 
+    pub fn migrations(&self) -> Vec<portfolio::Migration> {
+        self.migrations
+            .as_ref()
+            .unwrap_or(&Vec::new())
+            .iter()
+            .filter_map(|m| match (m.from.parse(), m.to.parse()) {
+                _ if m.ratio <= BigDecimal::zero() => {
+                    eprintln!(
+                        "Skipping migration of {} to {} at a ratio of {}, it must be more than 0",
+                        m.from, m.to, m.ratio
+                    );
+                    None
+                }
+                (Ok(from), Ok(to)) => Some(portfolio::Migration {
+                    when: m.date,
+                    from,
+                    to,
+                    ratio: m.ratio.clone(),
+                }),
+                _ => {
+                    eprintln!("Skipping migration of unknown token {} to {}", m.from, m.to);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn events(&self) -> Vec<portfolio::Trade> {
+        self.events
+            .as_ref()
+            .unwrap_or(&Vec::new())
+            .iter()
+            .filter_map(|e| {
+                let symbol = match e.token.parse() {
+                    Ok(symbol) => symbol,
+                    Err(_) => {
+                        eprintln!("Skipping {:?} of unknown token {}", e.kind, e.token);
+                        return None;
+                    }
+                };
+                let amount = Amount {
+                    amount: e.amount.clone(),
+                    symbol,
+                };
+                let when = e.date;
+                let value = || {
+                    e.value.clone().unwrap_or_else(|| {
+                        eprintln!(
+                            "No value given for {:?} of {} on {}",
+                            e.kind,
+                            e.token,
+                            e.date.format("%Y-%m-%d")
+                        );
+                        BigDecimal::zero()
+                    })
+                };
+                let kind = match e.kind {
+                    EventKind::Gift => portfolio::Kind::Gift {
+                        given: amount,
+                        value: value(),
+                    },
+                    EventKind::Donation => portfolio::Kind::Donation {
+                        given: amount,
+                        value: value(),
+                    },
+                    EventKind::Loss => portfolio::Kind::Loss { lost: amount },
+                    // A configured basis is used whatever the policy, e.g. part of the cost of
+                    // what was held before the fork
+                    EventKind::Fork => {
+                        let income = self.fork_policy.unwrap_or_default() == ForkPolicy::Income;
+                        let cost_basis = match e.basis {
+                            Some(ref basis) => basis.clone(),
+                            None if income => value(),
+                            None => BigDecimal::zero(),
+                        };
+                        portfolio::Kind::Fork {
+                            gained: amount,
+                            cost_basis,
+                            income,
+                        }
+                    }
+                    // Without the donor's date, the gift is held from when it was received
+                    EventKind::GiftReceived => portfolio::Kind::GiftReceived {
+                        gained: amount,
+                        cost_basis: e.basis.clone().unwrap_or_default(),
+                        acquired: e.acquired.unwrap_or(when),
+                    },
+                };
+                Some(portfolio::Trade { when, kind })
+            })
+            .collect()
+    }
+
+    pub fn fx_rates(&self) -> FxRates {
+        let mut fx_rates = FxRates::new();
+        for r in self.fx_rates.as_ref().unwrap_or(&Vec::new()) {
+            match (r.from.parse(), r.to.parse()) {
+                (Ok(Symbol::Fiat(from)), Ok(Symbol::Fiat(to))) => {
+                    let day = r.date.naive_utc().date();
+                    fx_rates.add_rate(from, to, day, r.rate.clone());
+                }
+                _ => eprintln!(
+                    "Skipping exchange rate of unknown currency {} to {}",
+                    r.from, r.to
+                ),
+            }
+        }
+        fx_rates
+    }
+
+    // jurisdiction is the configured country's tax rules, which may not allow the configured
+    // lot_matching
+    pub fn jurisdiction(&self) -> anyhow::Result<Box<dyn Jurisdiction>> {
+        jurisdiction::jurisdiction(
+            self.jurisdiction.unwrap_or(Country::UnitedStates),
+            self.lot_matching.unwrap_or_default(),
+        )
+    }
+
+    pub fn tax_year_start(&self) -> Option<YearStart> {
+        self.tax_year_start.as_ref().and_then(|s| match s.parse() {
+            Ok(start) => Some(start),
+            Err(_) => {
+                eprintln!("Ignoring tax_year_start of {}, expected MM-DD", s);
+                None
+            }
+        })
+    }
+
     pub fn denomination(&self) -> Symbol {
         self.denomination
             .as_ref()
@@ -91,10 +301,48 @@ impl Config {
     }
 }
 
-fn chrono_to_toml_date(value: toml::value::Datetime) -> DateTime {
-    let input = format!("{}", &value);
-    let naive_date = chrono::NaiveDate::parse_from_str(&input, "%Y-%m-%d").unwrap();
-    chrono::DateTime::from_utc(naive_date.and_hms(0, 0, 0), chrono::Utc)
+// datetime reads a TOML date, or date and time, as a time in UTC. A date alone is its midnight,
+// and a time without an offset is taken to be in UTC.
+fn datetime<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime, D::Error> {
+    let value = toml::value::Datetime::deserialize(deserializer)?;
+    toml_to_chrono(&value).map_err(de::Error::custom)
+}
+
+fn optional_datetime<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<DateTime>, D::Error> {
+    Option::<toml::value::Datetime>::deserialize(deserializer)?
+        .map(|value| toml_to_chrono(&value).map_err(de::Error::custom))
+        .transpose()
+}
+
+// optional_network reads a Bitcoin network by name, e.g. testnet
+fn optional_network<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<bitcoin::Network>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|name| name.parse().map_err(de::Error::custom))
+        .transpose()
+}
+
+fn toml_to_chrono(value: &toml::value::Datetime) -> Result<DateTime, String> {
+    let input = value.to_string();
+    if let Ok(datetime) = chrono::DateTime::parse_from_rfc3339(&input) {
+        return Ok(datetime.with_timezone(&chrono::Utc));
+    }
+    if let Ok(datetime) = chrono::NaiveDateTime::parse_from_str(&input, "%Y-%m-%dT%H:%M:%S%.f") {
+        return Ok(chrono::DateTime::from_utc(datetime, chrono::Utc));
+    }
+    match chrono::NaiveDate::parse_from_str(&input, "%Y-%m-%d") {
+        Ok(date) => Ok(chrono::DateTime::from_utc(
+            date.and_hms(0, 0, 0),
+            chrono::Utc,
+        )),
+        Err(_) => Err(format!(
+            "invalid date {}, expected a date or a date and time",
+            input
+        )),
+    }
 }
 
 #[derive(Deserialize, Debug, PartialEq, Eq)]
@@ -113,6 +361,15 @@ pub enum Exchange {
     },
     Etherscan {
         key: String,
+        chain: Option<Chain>,
+        url: Option<String>,
+    },
+    Esplora {
+        url: Option<String>,
+        addresses: Option<Vec<String>>,
+        xpubs: Option<Vec<String>>,
+        #[serde(default, deserialize_with = "optional_network")]
+        network: Option<bitcoin::Network>,
     },
 }
 
@@ -132,8 +389,10 @@ mod test {
     use std::str::FromStr;
 
     use bigdecimal::{BigDecimal, FromPrimitive};
+    use chrono::offset::TimeZone;
     use tempfile::TempDir;
-    use toml::value::Datetime;
+
+    use crate::symbol::{Crypto, Fiat};
 
     use super::*;
 
@@ -142,11 +401,19 @@ mod test {
         let project = project(
             r#"
                 tax_year = 2018
+                tax_year_start = "07-01"
+                jurisdiction = "us"
+                fork_policy = "income"
+                lot_matching = "lifo"
 
                 exchanges = [
                     { Coinbase = { key = "coinbase-key", secret = "coinbase-secret" } },
                     { CoinbasePro = { key = "coinbase-pro-key", secret = "coinbase-pro-secret", passphrase = "coinbase-pro-passphrase" } },
-                    { Ethereum = { url = "wss://ethereum.io/ws/v3/magic-token" } }
+                    { Ethereum = { url = "wss://ethereum.io/ws/v3/magic-token" } },
+                    { Etherscan = { key = "etherscan-key" } },
+                    { Etherscan = { key = "polygonscan-key", chain = "polygon", url = "https://polygonscan.local/api" } },
+                    { Esplora = { url = "http://localhost:3002", xpubs = ["zpub-key"] } },
+                    { Esplora = { addresses = ["tb1-address"], network = "testnet" } },
                 ]
 
                 accounts = [
@@ -169,9 +436,45 @@ mod test {
                 token = "BTC"
                 amount = 6572.94
                 rate = 0.257547
-                usd_rate = 0.257547
-                usd_amount = 1692.84
+                fiat_rate = 0.257547
+                fiat_amount = 1692.84
+                fiat = "EUR"
+                category = "staking"
                 created_at = 1997-08-04
+
+                [[migrations]]
+                from = "LEND"
+                to = "AAVE"
+                ratio = "0.01"
+                date = 2020-10-02
+
+                [[events]]
+                kind = "gift_received"
+                token = "ETH"
+                amount = "2"
+                date = 2021-03-01
+                basis = "400"
+                acquired = 2017-06-01
+
+                [[events]]
+                kind = "donation"
+                token = "ETH"
+                amount = "1"
+                date = 2021-12-24
+                value = "3900"
+
+                [[events]]
+                kind = "fork"
+                token = "BCH"
+                amount = "1.5"
+                date = 2017-08-01
+                value = "450"
+
+                [[fx_rates]]
+                from = "EUR"
+                to = "USD"
+                rate = "1.1"
+                date = 1997-08-04
             "#,
         )
         .unwrap();
@@ -182,6 +485,7 @@ mod test {
             config,
             Config {
                 tax_year: 2018,
+                tax_year_start: Some("07-01".to_string()),
                 exchanges: vec![
                     Exchange::Coinbase {
                         key: "coinbase-key".to_string(),
@@ -195,6 +499,28 @@ mod test {
                     Exchange::Ethereum {
                         url: "wss://ethereum.io/ws/v3/magic-token".to_string(),
                     },
+                    Exchange::Etherscan {
+                        key: "etherscan-key".to_string(),
+                        chain: None,
+                        url: None,
+                    },
+                    Exchange::Etherscan {
+                        key: "polygonscan-key".to_string(),
+                        chain: Some(Chain::Polygon),
+                        url: Some("https://polygonscan.local/api".to_string()),
+                    },
+                    Exchange::Esplora {
+                        url: Some("http://localhost:3002".to_string()),
+                        addresses: None,
+                        xpubs: Some(vec!["zpub-key".to_string()]),
+                        network: None,
+                    },
+                    Exchange::Esplora {
+                        url: None,
+                        addresses: Some(vec!["tb1-address".to_string()]),
+                        xpubs: None,
+                        network: Some(bitcoin::Network::Testnet),
+                    },
                 ],
                 transactions: Some(vec![
                     Transaction {
@@ -203,9 +529,11 @@ mod test {
                         token: "BTC".to_string(),
                         amount: BigDecimal::from_f32(1255.66).unwrap(),
                         rate: BigDecimal::from_f32(0.387690).unwrap(),
-                        usd_rate: BigDecimal::from_f32(0.387690).unwrap(),
-                        usd_amount: BigDecimal::from_f32(848.85).unwrap(),
-                        created_at: Some(Datetime::from_str("1997-02-14").unwrap()),
+                        fiat_rate: BigDecimal::from_f32(0.387690).unwrap(),
+                        fiat_amount: BigDecimal::from_f32(848.85).unwrap(),
+                        fiat: None,
+                        category: None,
+                        created_at: Some(chrono::Utc.ymd(1997, 2, 14).and_hms(0, 0, 0)),
                     },
                     Transaction {
                         id: "0x2".to_string(),
@@ -213,20 +541,188 @@ mod test {
                         token: "BTC".to_string(),
                         amount: BigDecimal::from_f32(6572.94).unwrap(),
                         rate: BigDecimal::from_f32(0.257547).unwrap(),
-                        usd_rate: BigDecimal::from_f32(0.257547).unwrap(),
-                        usd_amount: BigDecimal::from_f32(1692.84).unwrap(),
-                        created_at: Some(Datetime::from_str("1997-08-04").unwrap()),
+                        fiat_rate: BigDecimal::from_f32(0.257547).unwrap(),
+                        fiat_amount: BigDecimal::from_f32(1692.84).unwrap(),
+                        fiat: Some("EUR".to_string()),
+                        category: Some(Category::Staking),
+                        created_at: Some(chrono::Utc.ymd(1997, 8, 4).and_hms(0, 0, 0)),
+                    },
+                ]),
+                migrations: Some(vec![Migration {
+                    from: "LEND".to_string(),
+                    to: "AAVE".to_string(),
+                    ratio: "0.01".parse().unwrap(),
+                    date: chrono::Utc.ymd(2020, 10, 2).and_hms(0, 0, 0),
+                }]),
+                events: Some(vec![
+                    Event {
+                        kind: EventKind::GiftReceived,
+                        token: "ETH".to_string(),
+                        amount: BigDecimal::from(2),
+                        date: chrono::Utc.ymd(2021, 3, 1).and_hms(0, 0, 0),
+                        value: None,
+                        basis: Some(BigDecimal::from(400)),
+                        acquired: Some(chrono::Utc.ymd(2017, 6, 1).and_hms(0, 0, 0)),
+                    },
+                    Event {
+                        kind: EventKind::Donation,
+                        token: "ETH".to_string(),
+                        amount: BigDecimal::from(1),
+                        date: chrono::Utc.ymd(2021, 12, 24).and_hms(0, 0, 0),
+                        value: Some(BigDecimal::from(3900)),
+                        basis: None,
+                        acquired: None,
+                    },
+                    Event {
+                        kind: EventKind::Fork,
+                        token: "BCH".to_string(),
+                        amount: "1.5".parse().unwrap(),
+                        date: chrono::Utc.ymd(2017, 8, 1).and_hms(0, 0, 0),
+                        value: Some(BigDecimal::from(450)),
+                        basis: None,
+                        acquired: None,
                     },
                 ]),
+                fx_rates: Some(vec![FxRate {
+                    from: "EUR".to_string(),
+                    to: "USD".to_string(),
+                    rate: "1.1".parse().unwrap(),
+                    date: chrono::Utc.ymd(1997, 8, 4).and_hms(0, 0, 0),
+                }]),
                 accounts: Some(vec![web3::types::H160::from_str(
                     "ffffffffffffffffffffffffffffffffffffffff"
                 )
                 .unwrap(),]),
+                fork_policy: Some(ForkPolicy::Income),
+                jurisdiction: Some(Country::UnitedStates),
+                lot_matching: Some(Matching::Lifo),
                 denomination: None,
                 report_format: None,
             }
         );
         assert_eq!(config.denomination(), USD);
+        assert_eq!(config.tax_year_start(), Some(YearStart::new(7, 1)));
+        assert_eq!(
+            config.migrations(),
+            vec![portfolio::Migration {
+                when: chrono::Utc.ymd(2020, 10, 2).and_hms(0, 0, 0),
+                from: Symbol::Crypto(Crypto::LEND),
+                to: Symbol::Crypto(Crypto::AAVE),
+                ratio: "0.01".parse().unwrap(),
+            }]
+        );
+        assert_eq!(
+            config.events(),
+            vec![
+                portfolio::Trade {
+                    when: chrono::Utc.ymd(2021, 3, 1).and_hms(0, 0, 0),
+                    kind: portfolio::Kind::GiftReceived {
+                        gained: Amount {
+                            amount: BigDecimal::from(2),
+                            symbol: Symbol::Crypto(Crypto::ETH),
+                        },
+                        cost_basis: BigDecimal::from(400),
+                        acquired: chrono::Utc.ymd(2017, 6, 1).and_hms(0, 0, 0),
+                    },
+                },
+                portfolio::Trade {
+                    when: chrono::Utc.ymd(2021, 12, 24).and_hms(0, 0, 0),
+                    kind: portfolio::Kind::Donation {
+                        given: Amount {
+                            amount: BigDecimal::from(1),
+                            symbol: Symbol::Crypto(Crypto::ETH),
+                        },
+                        value: BigDecimal::from(3900),
+                    },
+                },
+                portfolio::Trade {
+                    when: chrono::Utc.ymd(2017, 8, 1).and_hms(0, 0, 0),
+                    kind: portfolio::Kind::Fork {
+                        gained: Amount {
+                            amount: "1.5".parse().unwrap(),
+                            symbol: Symbol::Crypto(Crypto::BCH),
+                        },
+                        cost_basis: BigDecimal::from(450),
+                        income: true,
+                    },
+                },
+            ]
+        );
+        assert_eq!(
+            config
+                .fx_rates()
+                .convert(
+                    &BigDecimal::from(10),
+                    Fiat::EUR,
+                    Fiat::USD,
+                    chrono::Utc.ymd(1997, 8, 4).and_hms(0, 0, 0)
+                )
+                .unwrap(),
+            BigDecimal::from(11)
+        );
+    }
+
+    #[test]
+    fn test_load_config_datetimes() {
+        let datetimes = project(
+            r#"
+                tax_year = 2021
+                exchanges = []
+
+                [[migrations]]
+                from = "LEND"
+                to = "AAVE"
+                ratio = "0.01"
+                date = 2020-10-02T12:00:00Z
+
+                [[events]]
+                kind = "loss"
+                token = "ETH"
+                amount = "1"
+                date = 2021-03-01T12:30:00+02:00
+
+                [[fx_rates]]
+                from = "EUR"
+                to = "USD"
+                rate = "1.1"
+                date = 2021-01-04T09:00:00
+            "#,
+        )
+        .unwrap();
+
+        let config = load_config(Some(datetimes.root.path().into())).unwrap();
+        assert_eq!(
+            config.migrations()[0].when,
+            chrono::Utc.ymd(2020, 10, 2).and_hms(12, 0, 0)
+        );
+        assert_eq!(
+            config.events()[0].when,
+            chrono::Utc.ymd(2021, 3, 1).and_hms(10, 30, 0)
+        );
+        assert!(config.fx_rates().has_rate(
+            Fiat::EUR,
+            Fiat::USD,
+            chrono::NaiveDate::from_ymd(2021, 1, 4)
+        ));
+
+        // A time alone isn't a date
+        let time_only = project(
+            r#"
+                tax_year = 2021
+                exchanges = []
+
+                [[migrations]]
+                from = "LEND"
+                to = "AAVE"
+                ratio = "0.01"
+                date = 12:00:00
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            load_config(Some(time_only.root.path().into())),
+            Err(ConfigError::TomlError(_))
+        ));
     }
 
     #[test]
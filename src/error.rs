@@ -0,0 +1,268 @@
+use std::error;
+use std::fmt;
+use std::io::Write;
+
+use anyhow::Result;
+use bigdecimal::BigDecimal;
+
+use crate::portfolio::Source;
+use crate::symbol::{Fiat, Symbol};
+use crate::types::{format_amount, DateTime};
+
+// ReportError is a problem with the transactions a report is made from. Reports work around them
+// and warn about each, unless they're strict.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ReportError {
+    // InsufficientLots is a disposal of more than was held, the shortfall has no cost basis
+    InsufficientLots {
+        symbol: Symbol,
+        when: DateTime,
+        shortfall: BigDecimal,
+        source: Option<Source>,
+    },
+    // UnknownSymbol is a token or currency that a provider names but isn't recognized, the
+    // transaction is left out
+    UnknownSymbol {
+        symbol: String,
+        source: Source,
+    },
+    // MissingPrice is an exchange that had to be valued but had no price, it's carried through to
+    // what was exchanged for instead, or left out where every exchange is valued
+    MissingPrice {
+        market: String,
+        when: DateTime,
+        source: Source,
+    },
+    // BadMarket is a market that isn't two symbols separated by a dash, the transaction is left out
+    BadMarket {
+        market: String,
+        source: Source,
+    },
+    // MissingFxRate is fiat that had to be converted into the denomination but had no exchange rate
+    // that day, the transaction is left out
+    MissingFxRate {
+        from: Fiat,
+        to: Fiat,
+        when: DateTime,
+        source: Source,
+    },
+}
+
+impl ReportError {
+    // kind names the error, for the warnings file
+    fn kind(&self) -> &'static str {
+        match self {
+            ReportError::InsufficientLots { .. } => "insufficient_lots",
+            ReportError::UnknownSymbol { .. } => "unknown_symbol",
+            ReportError::MissingPrice { .. } => "missing_price",
+            ReportError::BadMarket { .. } => "bad_market",
+            ReportError::MissingFxRate { .. } => "missing_fx_rate",
+        }
+    }
+
+    fn source(&self) -> Option<&Source> {
+        match self {
+            ReportError::InsufficientLots { source, .. } => source.as_ref(),
+            ReportError::UnknownSymbol { source, .. }
+            | ReportError::MissingPrice { source, .. }
+            | ReportError::BadMarket { source, .. }
+            | ReportError::MissingFxRate { source, .. } => Some(source),
+        }
+    }
+}
+
+impl fmt::Display for ReportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReportError::InsufficientLots {
+                symbol,
+                when,
+                shortfall,
+                ..
+            } => write!(
+                f,
+                "{} {} more than was held was disposed of on {}, it has no cost basis",
+                format_amount(shortfall),
+                symbol.symbol(),
+                when.format("%Y-%m-%d")
+            )?,
+            ReportError::UnknownSymbol { symbol, source } => write!(
+                f,
+                "Unknown symbol {} in {} transaction {}",
+                symbol, source.provider, source.id
+            )?,
+            ReportError::MissingPrice {
+                market,
+                when,
+                source,
+            } => write!(
+                f,
+                "No price for {} on {} to value {} transaction {}",
+                market,
+                when.format("%Y-%m-%d"),
+                source.provider,
+                source.id
+            )?,
+            ReportError::BadMarket { market, source } => write!(
+                f,
+                "Bad market {} in {} transaction {}",
+                market, source.provider, source.id
+            )?,
+            ReportError::MissingFxRate {
+                from,
+                to,
+                when,
+                source,
+            } => write!(
+                f,
+                "No exchange rate from {} to {} on {} to value {} transaction {}",
+                from,
+                to,
+                when.format("%Y-%m-%d"),
+                source.provider,
+                source.id
+            )?,
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for ReportError {}
+
+// Warnings collects every error found in making a report, or fails on the first when strict
+#[derive(Debug, Default)]
+pub struct Warnings {
+    strict: bool,
+    errors: Vec<ReportError>,
+}
+
+impl Warnings {
+    pub fn new(strict: bool) -> Self {
+        Warnings {
+            strict,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    // warn returns the error when strict, otherwise it's kept and reported as a warning
+    pub fn warn(&mut self, error: ReportError) -> Result<()> {
+        if self.strict {
+            return Err(error.into());
+        }
+        eprintln!("Warning: {}", error);
+        self.errors.push(error);
+        Ok(())
+    }
+
+    pub fn errors(&self) -> &[ReportError] {
+        &self.errors
+    }
+
+    // summarize prints how many warnings there were, for commands that don't list them
+    pub fn summarize(&self) {
+        if !self.errors.is_empty() {
+            eprintln!(
+                "{} warnings, the report command lists them in warnings.csv",
+                self.errors.len()
+            );
+        }
+    }
+
+    // write lists every warning as a CSV, with the transaction it was found in
+    pub fn write(&self, output: &mut dyn Write) -> Result<()> {
+        let mut writer = csv::Writer::from_writer(output);
+        writer.write_record([
+            "Warning", "Provider", "ID", "Date", "Symbol", "Amount", "Message",
+        ])?;
+
+        for error in self.errors.iter() {
+            let (when, symbol, amount) = match error {
+                ReportError::InsufficientLots {
+                    symbol,
+                    when,
+                    shortfall,
+                    ..
+                } => (Some(when), symbol.symbol(), shortfall.to_string()),
+                ReportError::UnknownSymbol { symbol, .. } => (None, symbol.clone(), String::new()),
+                ReportError::MissingPrice { market, when, .. } => {
+                    (Some(when), market.clone(), String::new())
+                }
+                ReportError::BadMarket { market, .. } => (None, market.clone(), String::new()),
+                ReportError::MissingFxRate { from, when, .. } => {
+                    (Some(when), from.symbol(), String::new())
+                }
+            };
+            writer.write_record([
+                error.kind().to_string(),
+                error.source().map_or(String::new(), |s| s.provider.clone()),
+                error.source().map_or(String::new(), |s| s.id.clone()),
+                when.map_or(String::new(), |w| w.to_rfc3339()),
+                symbol,
+                amount,
+                error.to_string(),
+            ])?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    use crate::symbol::BTC;
+
+    use super::*;
+
+    #[test]
+    fn test_warnings() {
+        let shortfall = ReportError::InsufficientLots {
+            symbol: BTC,
+            when: Utc.ymd(2021, 3, 1).and_hms(0, 0, 0),
+            shortfall: "0.5".parse().unwrap(),
+            source: Some(Source {
+                id: "3".into(),
+                provider: "coinbase".into(),
+            }),
+        };
+
+        let mut strict = Warnings::new(true);
+        assert_eq!(
+            strict
+                .warn(shortfall.clone())
+                .unwrap_err()
+                .downcast::<ReportError>()
+                .unwrap(),
+            shortfall
+        );
+        assert!(strict.errors().is_empty());
+
+        let mut lenient = Warnings::new(false);
+        lenient.warn(shortfall).unwrap();
+        lenient
+            .warn(ReportError::BadMarket {
+                market: "BTCUSD".into(),
+                source: Source {
+                    id: "4".into(),
+                    provider: "coinbase".into(),
+                },
+            })
+            .unwrap();
+
+        let mut output = Vec::new();
+        lenient.write(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Warning,Provider,ID,Date,Symbol,Amount,Message\n\
+             insufficient_lots,coinbase,3,2021-03-01T00:00:00+00:00,BTC,0.5,\"0.5000 BTC more than was held was disposed of on 2021-03-01, it has no cost basis\"\n\
+             bad_market,coinbase,4,,BTCUSD,,Bad market BTCUSD in coinbase transaction 4\n"
+        );
+    }
+}
@@ -0,0 +1,562 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+use ::bitcoin::secp256k1::{Secp256k1, Verification};
+use ::bitcoin::util::base58;
+use ::bitcoin::util::bip32::{ChildNumber, ExtendedPubKey};
+use ::bitcoin::{Address, Network};
+use anyhow::anyhow;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::prelude::*;
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+
+use crate::prices::Prices;
+use crate::provider::{after, Provider};
+use crate::symbol::{Chain, Crypto, Symbol};
+use crate::types::{Balance, Category, DateTime, Transaction};
+
+const PROVIDER: &str = "esplora";
+
+// Esplora fetches the transactions of Bitcoin addresses, and of those derived from extended public
+// keys, from an Esplora API for the network
+pub struct Esplora {
+    pub url: String,
+    pub addresses: Vec<String>,
+    pub xpubs: Vec<String>,
+    pub network: Network,
+    pub denomination: Symbol,
+}
+
+impl Provider for Esplora {
+    fn name(&self) -> &'static str {
+        PROVIDER
+    }
+
+    fn transactions(
+        &self,
+        since: Option<DateTime>,
+    ) -> LocalBoxFuture<'_, anyhow::Result<Vec<Transaction>>> {
+        async move {
+            let transactions = transactions(
+                &self.url,
+                &self.addresses,
+                &self.xpubs,
+                self.network,
+                self.denomination,
+            )
+            .await
+            .map_err(|e| anyhow!("{}", e))?;
+            Ok(after(transactions, since))
+        }
+        .boxed_local()
+    }
+
+    fn balances(&self) -> LocalBoxFuture<'_, anyhow::Result<Vec<Balance>>> {
+        async move {
+            balances(&self.url, &self.addresses, &self.xpubs, self.network)
+                .await
+                .map_err(|e| anyhow!("{}", e))
+        }
+        .boxed_local()
+    }
+}
+
+// url is the API of the usual explorer for the network, or of a local Esplora for regtest
+pub fn url(network: Network) -> &'static str {
+    match network {
+        Network::Bitcoin => Chain::Bitcoin.explorer_url(),
+        Network::Testnet => "https://blockstream.info/testnet/api",
+        Network::Signet => "https://mempool.space/signet/api",
+        Network::Regtest => "http://localhost:3002",
+    }
+}
+
+// GAP_LIMIT is the number of consecutive unused addresses derived before giving up on a chain
+const GAP_LIMIT: u32 = 20;
+
+// Esplora returns confirmed transactions for an address in pages of this size
+const PAGE_SIZE: usize = 25;
+
+const RECEIVE_CHAIN: u32 = 0;
+const CHANGE_CHAIN: u32 = 1;
+
+// Version bytes of extended public keys, which determine the kind of address derived, for mainnet
+// and for the test networks
+const XPUB_VERSION: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+const YPUB_VERSION: [u8; 4] = [0x04, 0x9d, 0x7c, 0xb2];
+const ZPUB_VERSION: [u8; 4] = [0x04, 0xb2, 0x47, 0x46];
+const TPUB_VERSION: [u8; 4] = [0x04, 0x35, 0x87, 0xcf];
+const UPUB_VERSION: [u8; 4] = [0x04, 0x4a, 0x52, 0x62];
+const VPUB_VERSION: [u8; 4] = [0x04, 0x5f, 0x1c, 0xf6];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Script {
+    P2PKH,
+    P2SHWPKH,
+    P2WPKH,
+}
+
+// ExtendedKey is an xpub, ypub or zpub, or a tpub, upub or vpub on the test networks, all of which
+// are BIP32 keys that differ only in the addresses derived from them
+struct ExtendedKey {
+    key: ExtendedPubKey,
+    script: Script,
+    network: Network,
+}
+
+impl ExtendedKey {
+    fn parse(input: &str, network: Network) -> Result<ExtendedKey, Box<dyn Error>> {
+        let mut data = base58::from_check(input)?;
+        if data.len() < XPUB_VERSION.len() {
+            return Err(format!("Invalid extended public key {}", input).into());
+        }
+
+        let (script, mainnet) = match [data[0], data[1], data[2], data[3]] {
+            XPUB_VERSION => (Script::P2PKH, true),
+            YPUB_VERSION => (Script::P2SHWPKH, true),
+            ZPUB_VERSION => (Script::P2WPKH, true),
+            TPUB_VERSION => (Script::P2PKH, false),
+            UPUB_VERSION => (Script::P2SHWPKH, false),
+            VPUB_VERSION => (Script::P2WPKH, false),
+            _ => return Err(format!("Unsupported extended public key {}", input).into()),
+        };
+        if mainnet != (network == Network::Bitcoin) {
+            return Err(format!("Extended public key {} isn't for {}", input, network).into());
+        }
+        data[..XPUB_VERSION.len()].copy_from_slice(&XPUB_VERSION);
+
+        Ok(ExtendedKey {
+            key: ExtendedPubKey::decode(&data)?,
+            script,
+            network,
+        })
+    }
+
+    // address derives the address at index on either the receive or change chain
+    fn address<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        chain: u32,
+        index: u32,
+    ) -> Result<Address, Box<dyn Error>> {
+        let path = [
+            ChildNumber::from_normal_idx(chain)?,
+            ChildNumber::from_normal_idx(index)?,
+        ];
+        let public_key = self.key.derive_pub(secp, &path)?.to_pub();
+
+        Ok(match self.script {
+            Script::P2PKH => Address::p2pkh(&public_key, self.network),
+            Script::P2SHWPKH => Address::p2shwpkh(&public_key, self.network)?,
+            Script::P2WPKH => Address::p2wpkh(&public_key, self.network)?,
+        })
+    }
+}
+
+async fn transactions(
+    url: &str,
+    addresses: &[String],
+    xpubs: &[String],
+    network: Network,
+    denomination: Symbol,
+) -> Result<Vec<Transaction>, Box<dyn Error>> {
+    let client = reqwest::Client::new();
+    let mut prices = Prices::new(denomination);
+
+    // Every address is treated as part of one wallet, so that change and transfers between
+    // addresses aren't mistaken for receives
+    let mut owned: HashSet<String> = HashSet::new();
+    let mut history: HashMap<String, Tx> = HashMap::new();
+
+    for address in addresses.iter() {
+        owned.insert(address.clone());
+        for tx in address_txs(&client, url, address).await? {
+            history.insert(tx.txid.clone(), tx);
+        }
+    }
+
+    let secp = Secp256k1::verification_only();
+    for xpub in xpubs.iter() {
+        let key = ExtendedKey::parse(xpub, network)?;
+        for chain in [RECEIVE_CHAIN, CHANGE_CHAIN] {
+            let mut unused = 0;
+            let mut index = 0;
+            while unused < GAP_LIMIT {
+                let address = key.address(&secp, chain, index)?.to_string();
+                let txs = address_txs(&client, url, &address).await?;
+                if txs.is_empty() {
+                    unused += 1;
+                } else {
+                    unused = 0;
+                }
+
+                owned.insert(address);
+                for tx in txs {
+                    history.insert(tx.txid.clone(), tx);
+                }
+                index += 1;
+            }
+        }
+    }
+
+    let mut transactions = Vec::new();
+    for tx in history.values() {
+        let Some(mut transaction) = transaction(&owned, tx, denomination)? else {
+            continue;
+        };
+        // What's received from or sent to anyone else is valued at the day's price, for when
+        // it isn't paired with a transfer into or out of another account. Coins on the test
+        // networks are worth nothing.
+        let valued = network == Network::Bitcoin && !transaction.amount.is_zero();
+        if let (true, Some(created_at)) = (valued, transaction.created_at) {
+            match prices.price_at(Crypto::BTC, created_at).await {
+                Ok(price) => {
+                    transaction.fiat_amount = &price * &transaction.amount;
+                    transaction.rate = price.clone();
+                    transaction.fiat_rate = price;
+                }
+                Err(e) => eprintln!("Unable to value Bitcoin transaction {}: {}", tx.txid, e),
+            }
+        }
+        transactions.push(transaction);
+    }
+
+    Ok(transactions)
+}
+
+// balances totals the confirmed balance of every address, and of those derived from each
+// extended public key up to the gap limit
+async fn balances(
+    url: &str,
+    addresses: &[String],
+    xpubs: &[String],
+    network: Network,
+) -> Result<Vec<Balance>, Box<dyn Error>> {
+    let client = reqwest::Client::new();
+
+    let mut owned: HashSet<String> = HashSet::new();
+    let mut total = 0;
+
+    for address in addresses.iter() {
+        if owned.insert(address.clone()) {
+            total += address_stats(&client, url, address).await?.balance();
+        }
+    }
+
+    let secp = Secp256k1::verification_only();
+    for xpub in xpubs.iter() {
+        let key = ExtendedKey::parse(xpub, network)?;
+        for chain in [RECEIVE_CHAIN, CHANGE_CHAIN] {
+            let mut unused = 0;
+            let mut index = 0;
+            while unused < GAP_LIMIT {
+                let address = key.address(&secp, chain, index)?.to_string();
+                let stats = address_stats(&client, url, &address).await?;
+                if stats.tx_count == 0 {
+                    unused += 1;
+                } else {
+                    unused = 0;
+                }
+
+                if owned.insert(address) {
+                    total += stats.balance();
+                }
+                index += 1;
+            }
+        }
+    }
+
+    Ok(vec![Balance {
+        token: format!("{}@{}", Crypto::BTC, Chain::Bitcoin),
+        amount: satoshis(total),
+        provider: PROVIDER,
+    }])
+}
+
+// transaction nets the inputs and outputs of a transaction belonging to the wallet, the wallet
+// paid the miner fee if it spent any of the inputs. Bitcoin moved into or out of the wallet is a
+// transfer, and one that only moves it between the wallet's own addresses just pays the fee.
+fn transaction(
+    owned: &HashSet<String>,
+    tx: &Tx,
+    denomination: Symbol,
+) -> Result<Option<Transaction>, Box<dyn Error>> {
+    let block_time = match tx.status.block_time {
+        Some(block_time) if tx.status.confirmed => block_time,
+        _ => return Ok(None),
+    };
+
+    let is_owned = |vout: &&Vout| {
+        vout.scriptpubkey_address
+            .as_ref()
+            .map_or(false, |a| owned.contains(a))
+    };
+    let received: i64 = tx.vout.iter().filter(is_owned).map(|v| v.value).sum();
+    let spent: i64 = tx
+        .vin
+        .iter()
+        .filter_map(|vin| vin.prevout.as_ref())
+        .filter(is_owned)
+        .map(|v| v.value)
+        .sum();
+
+    let (amount, fee) = if spent > 0 {
+        (received - spent + tx.fee, tx.fee)
+    } else {
+        (received, 0)
+    };
+    if amount == 0 && fee == 0 {
+        return Ok(None);
+    }
+
+    let timestamp = NaiveDateTime::from_timestamp_opt(block_time, 0).ok_or("Invalid block time")?;
+    let token = format!("{}@{}", Crypto::BTC, Chain::Bitcoin);
+    let category = if amount > 0 {
+        Category::TransferIn
+    } else {
+        Category::TransferOut
+    };
+
+    Ok(Some(Transaction {
+        id: tx.txid.clone(),
+        hash: Some(tx.txid.clone()),
+        market: format!("{}-{}", token, denomination),
+        token,
+        amount: satoshis(amount),
+        rate: BigDecimal::zero(),
+        fiat_rate: BigDecimal::zero(),
+        fiat_amount: BigDecimal::zero(),
+        fiat: denomination.symbol(),
+        fee: satoshis(fee),
+        category,
+        created_at: Some(DateTime::from_utc(timestamp, chrono::Utc)),
+        provider: PROVIDER,
+    }))
+}
+
+fn satoshis(amount: i64) -> BigDecimal {
+    BigDecimal::new(amount.into(), 8)
+}
+
+// address_txs fetches every confirmed transaction involving an address, oldest pages last
+async fn address_txs(
+    client: &reqwest::Client,
+    url: &str,
+    address: &str,
+) -> Result<Vec<Tx>, Box<dyn Error>> {
+    let mut txs: Vec<Tx> = Vec::new();
+
+    loop {
+        let page_url = match txs.last() {
+            Some(last) => format!("{}/address/{}/txs/chain/{}", url, address, last.txid),
+            None => format!("{}/address/{}/txs/chain", url, address),
+        };
+        let page = client
+            .get(&page_url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Vec<Tx>>()
+            .await?;
+
+        let last_page = page.len() < PAGE_SIZE;
+        txs.extend(page);
+        if last_page {
+            break;
+        }
+    }
+
+    Ok(txs)
+}
+
+// address_stats fetches the confirmed totals of an address
+async fn address_stats(
+    client: &reqwest::Client,
+    url: &str,
+    address: &str,
+) -> Result<Stats, Box<dyn Error>> {
+    let info = client
+        .get(format!("{}/address/{}", url, address))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<AddressInfo>()
+        .await?;
+    Ok(info.chain_stats)
+}
+
+#[derive(Deserialize, Debug)]
+struct AddressInfo {
+    chain_stats: Stats,
+}
+
+// Stats totals the outputs paid to an address and those of them since spent, in satoshis
+#[derive(Deserialize, Debug)]
+struct Stats {
+    funded_txo_sum: i64,
+    spent_txo_sum: i64,
+    tx_count: u64,
+}
+
+impl Stats {
+    fn balance(&self) -> i64 {
+        self.funded_txo_sum - self.spent_txo_sum
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Tx {
+    txid: String,
+    vin: Vec<Vin>,
+    vout: Vec<Vout>,
+    fee: i64,
+    status: Status,
+}
+
+// Vin is a transaction input, coinbase inputs have no previous output
+#[derive(Deserialize, Debug)]
+struct Vin {
+    prevout: Option<Vout>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Vout {
+    scriptpubkey_address: Option<String>,
+    value: i64,
+}
+
+#[derive(Deserialize, Debug)]
+struct Status {
+    confirmed: bool,
+    block_time: Option<i64>,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::symbol::USD;
+
+    use super::*;
+
+    // Account level keys derived from the BIP39 mnemonic "abandon abandon ... about", along
+    // with the first receive address given by BIP44, BIP49 and BIP84
+    const XPUB: &str = "xpub6BosfCnifzxcFwrSzQiqu2DBVTshkCXacvNsWGYJVVhhawA7d4R5WSWGFNbi8Aw6ZRc1brxMyWMzG3DSSSSoekkudhUd9yLb6qx39T9nMdj";
+    const YPUB: &str = "ypub6Ww3ibxVfGzLrAH1PNcjyAWenMTbbAosGNB6VvmSEgytSER9azLDWCxoJwW7Ke7icmizBMXrzBx9979FfaHxHcrArf3zbeJJJUZPf663zsP";
+    const ZPUB: &str = "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs";
+
+    // The same, derived for testnet by BIP84
+    const VPUB: &str = "vpub5Y6cjg78GGuNLsaPhmYsiw4gYX3HoQiRBiSwDaBXKUafCt9bNwWQiitDk5VZ5BVxYnQdwoTyXSs2JHRPAgjAvtbBrf8ZhDYe2jWAqvZVnsc";
+
+    fn first_address(xpub: &str, network: Network) -> String {
+        let secp = Secp256k1::verification_only();
+        ExtendedKey::parse(xpub, network)
+            .unwrap()
+            .address(&secp, RECEIVE_CHAIN, 0)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_derive_addresses() {
+        let mainnet = Network::Bitcoin;
+        assert_eq!(
+            first_address(XPUB, mainnet),
+            "1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA"
+        );
+        assert_eq!(
+            first_address(YPUB, mainnet),
+            "37VucYSaXLCAsxYyAPfbSi9eh4iEcbShgf"
+        );
+        assert_eq!(
+            first_address(ZPUB, mainnet),
+            "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"
+        );
+        assert!(ExtendedKey::parse("xpub", mainnet).is_err());
+    }
+
+    #[test]
+    fn test_derive_testnet_addresses() {
+        assert_eq!(
+            first_address(VPUB, Network::Testnet),
+            "tb1q6rz28mcfaxtmd6v789l9rrlrusdprr9pqcpvkl"
+        );
+
+        // Keys for one network can't be used for another
+        assert!(ExtendedKey::parse(ZPUB, Network::Testnet).is_err());
+        assert!(ExtendedKey::parse(VPUB, Network::Bitcoin).is_err());
+    }
+
+    fn tx(vin: Vec<(&str, i64)>, vout: Vec<(&str, i64)>, fee: i64) -> Tx {
+        let output = |(address, value): (&str, i64)| Vout {
+            scriptpubkey_address: Some(address.to_string()),
+            value,
+        };
+        Tx {
+            txid: "f00d".to_string(),
+            vin: vin
+                .into_iter()
+                .map(|v| Vin {
+                    prevout: Some(output(v)),
+                })
+                .collect(),
+            vout: vout.into_iter().map(output).collect(),
+            fee,
+            status: Status {
+                confirmed: true,
+                block_time: Some(1231006505),
+            },
+        }
+    }
+
+    #[test]
+    fn test_transaction_receive() {
+        let owned = HashSet::from(["mine".to_string()]);
+        let receive = tx(
+            vec![("theirs", 150_000)],
+            vec![("mine", 100_000), ("theirs", 49_000)],
+            1_000,
+        );
+
+        let transaction = transaction(&owned, &receive, USD).unwrap().unwrap();
+        assert_eq!(transaction.amount, "0.001".parse().unwrap());
+        assert_eq!(transaction.fee, BigDecimal::zero());
+        assert_eq!(transaction.token, "BTC@bitcoin");
+        assert_eq!(transaction.category, Category::TransferIn);
+    }
+
+    #[test]
+    fn test_transaction_send_with_change() {
+        let owned = HashSet::from(["mine".to_string(), "change".to_string()]);
+        let send = tx(
+            vec![("mine", 150_000)],
+            vec![("theirs", 100_000), ("change", 49_000)],
+            1_000,
+        );
+
+        let transaction = transaction(&owned, &send, USD).unwrap().unwrap();
+        assert_eq!(transaction.amount, "-0.001".parse().unwrap());
+        assert_eq!(transaction.fee, "0.00001".parse().unwrap());
+        assert_eq!(transaction.category, Category::TransferOut);
+    }
+
+    #[test]
+    fn test_transaction_between_own_addresses() {
+        let owned = HashSet::from(["mine".to_string(), "change".to_string()]);
+        let consolidate = tx(vec![("mine", 150_000)], vec![("change", 149_000)], 1_000);
+
+        let transaction = transaction(&owned, &consolidate, USD).unwrap().unwrap();
+        assert!(transaction.amount.is_zero());
+        assert_eq!(transaction.fee, "0.00001".parse().unwrap());
+        assert_eq!(transaction.category, Category::TransferOut);
+    }
+
+    #[test]
+    fn test_transaction_unconfirmed() {
+        let owned = HashSet::from(["mine".to_string()]);
+        let mut receive = tx(vec![("theirs", 2_000)], vec![("mine", 1_000)], 1_000);
+        receive.status.confirmed = false;
+        receive.status.block_time = None;
+
+        assert!(transaction(&owned, &receive, USD).unwrap().is_none());
+    }
+}
@@ -1,15 +1,54 @@
 use std::error::Error;
-use std::str::FromStr;
 
+use anyhow::anyhow;
 use bigdecimal::BigDecimal;
-use web3::types::{BlockId, BlockNumber};
-
-use crate::types::Transaction;
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+use web3::types::{BlockId, BlockNumber, U256};
+
+use crate::etherscan::{units, NATIVE_DECIMALS};
+use crate::provider::{after, Provider};
+use crate::symbol::{Chain, Crypto, USD};
+use crate::types::{Balance, Category, DateTime, Transaction};
 use chrono::prelude::*;
 
 const PROVIDER: &str = "ethereum";
 
-pub async fn transactions(
+// Ethereum fetches the ether sent and received by each account from a node
+pub struct Ethereum {
+    pub url: String,
+    pub accounts: Vec<web3::types::H160>,
+}
+
+impl Provider for Ethereum {
+    fn name(&self) -> &'static str {
+        PROVIDER
+    }
+
+    fn transactions(
+        &self,
+        since: Option<DateTime>,
+    ) -> LocalBoxFuture<'_, anyhow::Result<Vec<Transaction>>> {
+        async move {
+            let transactions = transactions(&self.url, &self.accounts)
+                .await
+                .map_err(|e| anyhow!("{}", e))?;
+            Ok(after(transactions, since))
+        }
+        .boxed_local()
+    }
+
+    fn balances(&self) -> LocalBoxFuture<'_, anyhow::Result<Vec<Balance>>> {
+        async move {
+            balances(&self.url, &self.accounts)
+                .await
+                .map_err(|e| anyhow!("{}", e))
+        }
+        .boxed_local()
+    }
+}
+
+async fn transactions(
     url: &str,
     accounts: &Vec<web3::types::H160>,
 ) -> Result<Vec<Transaction>, Box<dyn Error>> {
@@ -21,30 +60,19 @@ pub async fn transactions(
 
     for block_id in (0..current_block.as_usize()).rev() {
         let number = BlockId::Number(BlockNumber::Number(block_id.into()));
-        let block = web3.eth().block_with_txs(number).await?;
-        for transaction in block.unwrap().transactions {
-            if !transaction_related(accounts, &transaction) {
+        let block = web3
+            .eth()
+            .block_with_txs(number)
+            .await?
+            .ok_or(format!("Missing block {}", block_id))?;
+        for tx in block.transactions.iter() {
+            if !transaction_related(accounts, tx) {
                 continue;
             }
 
-            {
-                let now = Utc::now();
-                let amount = BigDecimal::from_str(&format!("{:}", transaction.value)).unwrap();
-                let transaction = Transaction {
-                    id: format!("{:}", transaction.hash),
-                    market: "ETH-USD".to_string(),
-                    token: "ETH".to_string(),
-                    amount,
-                    rate: BigDecimal::from(0),
-                    usd_rate: BigDecimal::from(0),
-                    usd_amount: BigDecimal::from(0),
-                    created_at: Some(now),
-                    provider: PROVIDER,
-                };
-                transactions.push(transaction);
-            }
+            transactions.push(transaction(accounts, tx, block.timestamp)?);
 
-            if transaction.nonce.is_zero() {
+            if tx.nonce.is_zero() {
                 break;
             }
         }
@@ -53,17 +81,96 @@ pub async fn transactions(
     Ok(transactions)
 }
 
+// transaction is the ether moved to or from an account, made at the time of the block it's in
+fn transaction(
+    accounts: &[web3::types::H160],
+    transaction: &web3::types::Transaction,
+    timestamp: U256,
+) -> Result<Transaction, Box<dyn Error>> {
+    let timestamp = NaiveDateTime::from_timestamp_opt(timestamp.as_u64() as i64, 0)
+        .ok_or("Invalid block time")?;
+    let hash = format!("{:#x}", transaction.hash);
+    let token = format!("{}@{}", Crypto::ETH, Chain::Ethereum);
+
+    let mut amount = units(&transaction.value.to_string(), NATIVE_DECIMALS)?;
+    let mut category = Category::TransferIn;
+    if transaction.from.is_some_and(|f| accounts.contains(&f)) {
+        amount = -amount;
+        category = Category::TransferOut;
+    }
+
+    Ok(Transaction {
+        id: hash.clone(),
+        hash: Some(hash),
+        market: format!("{}-{}", token, USD),
+        token,
+        amount,
+        rate: BigDecimal::from(0),
+        fiat_rate: BigDecimal::from(0),
+        fiat_amount: BigDecimal::from(0),
+        fiat: USD.symbol(),
+        fee: BigDecimal::from(0),
+        category,
+        created_at: Some(DateTime::from_utc(timestamp, chrono::Utc)),
+        provider: PROVIDER,
+    })
+}
+
+// balances fetches the ether held by each account as of the latest block
+async fn balances(
+    url: &str,
+    accounts: &[web3::types::H160],
+) -> Result<Vec<Balance>, Box<dyn Error>> {
+    let transport = web3::transports::WebSocket::new(url).await?;
+    let web3 = web3::Web3::new(transport);
+
+    let mut balances = Vec::new();
+    for account in accounts.iter() {
+        let wei = web3.eth().balance(*account, None).await?;
+        balances.push(Balance {
+            token: format!("{}@{}", Crypto::ETH, Chain::Ethereum),
+            amount: units(&wei.to_string(), NATIVE_DECIMALS)?,
+            provider: PROVIDER,
+        });
+    }
+
+    Ok(balances)
+}
+
 fn transaction_related(
     accounts: &Vec<web3::types::H160>,
     transaction: &web3::types::Transaction,
 ) -> bool {
-    transaction.from.is_some_and(|f| accounts.contains(f)) || transaction.to.map_or(false, |ref t| accounts.contains(t))
+    transaction.from.is_some_and(|f| accounts.contains(&f))
+        || transaction.to.map_or(false, |ref t| accounts.contains(t))
 }
 
 #[cfg(test)]
 mod test {
+    use std::str::FromStr;
+
     use super::*;
 
+    #[test]
+    fn test_transaction() {
+        let address =
+            web3::types::H160::from_str("4c0457c5fB35183Cb25db52C14fEA30e737fcF5e").unwrap();
+        let sent = web3::types::Transaction {
+            from: Some(address),
+            value: U256::from_dec_str("1500000000000000000").unwrap(),
+            ..Default::default()
+        };
+
+        // Wei are converted to ether, and the transaction is dated by its block
+        let transaction = transaction(&[address], &sent, 1609459200.into()).unwrap();
+        assert_eq!(transaction.amount, "-1.5".parse().unwrap());
+        assert_eq!(transaction.category, Category::TransferOut);
+        assert_eq!(
+            transaction.created_at,
+            Some(Utc.ymd(2021, 1, 1).and_hms(0, 0, 0))
+        );
+    }
+
     #[test]
     fn test_transaction_related_empty() {
         let empty_accounts: Vec<web3::types::H160> = Vec::new();
@@ -1,71 +1,606 @@
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 use std::str::FromStr;
+use std::time::Duration;
 
-use bigdecimal::BigDecimal;
+use anyhow::anyhow;
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::{BigDecimal, Zero};
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+use serde_json::Value;
+use web3::types::U256;
 
-use crate::types::{DateTime, Transaction};
+use crate::prices::Prices;
+use crate::provider::{after, Provider};
+use crate::symbol::{Chain, Crypto, Nft, Symbol, USD};
+use crate::types::{Balance, Category, DateTime, Transaction};
 use chrono::prelude::*;
 
-const PROVIDER: &str = "etherscan";
+// Etherscan fetches the native token, token and NFT transfers of each account from an Etherscan
+// compatible block explorer for a network
+pub struct Etherscan {
+    pub key: String,
+    pub chain: Chain,
+    pub url: String,
+    pub accounts: Vec<web3::types::H160>,
+    pub denomination: Symbol,
+}
+
+impl Provider for Etherscan {
+    fn name(&self) -> &'static str {
+        self.chain.explorer_name()
+    }
+
+    fn transactions(
+        &self,
+        since: Option<DateTime>,
+    ) -> LocalBoxFuture<'_, anyhow::Result<Vec<Transaction>>> {
+        async move {
+            let transactions = transactions(
+                &self.key,
+                self.chain,
+                &self.url,
+                &self.accounts,
+                self.denomination,
+            )
+            .await
+            .map_err(|e| anyhow!("{}", e))?;
+            Ok(after(transactions, since))
+        }
+        .boxed_local()
+    }
+
+    fn balances(&self) -> LocalBoxFuture<'_, anyhow::Result<Vec<Balance>>> {
+        async move {
+            balances(&self.key, self.chain, &self.url, &self.accounts)
+                .await
+                .map_err(|e| anyhow!("{}", e))
+        }
+        .boxed_local()
+    }
+}
+
+// Native tokens on every supported network are denominated in 18 decimals (wei)
+pub(crate) const NATIVE_DECIMALS: u32 = 18;
 
-pub async fn transactions(
+// Free API keys are limited to 5 requests a second, and requests over the limit are retried after
+// waiting a little longer each time
+const REQUEST_INTERVAL: Duration = Duration::from_millis(250);
+const RETRIES: u32 = 3;
+
+// Listings are cut off after this many results, so the rest are fetched from the last block listed
+const MAX_RESULTS: usize = 10000;
+
+async fn transactions(
     key: &str,
+    chain: Chain,
+    url: &str,
     accounts: &Vec<web3::types::H160>,
+    denomination: Symbol,
 ) -> Result<Vec<Transaction>, Box<dyn Error>> {
+    let mut prices = Prices::new(denomination);
     let mut transactions = Vec::new();
 
     for account in accounts.iter() {
-        let txes = txlist(key, account).await?;
+        let normal = txlist(key, url, "txlist", account).await?;
+        let internal = txlist(key, url, "txlistinternal", account).await?;
+        let tokens = txlist(key, url, "tokentx", account).await?;
+        let mut nfts = txlist(key, url, "tokennfttx", account).await?;
+        nfts.extend(txlist(key, url, "token1155tx", account).await?);
+
+        // Native or wrapped native token transfers made alongside an NFT bought or sold are the
+        // payment for it, and are accounted for in the cost or proceeds of the NFT rather than on
+        // their own
+        let mut nft_trades = HashMap::new();
+        for tx in nfts.iter() {
+            if nft_trades.contains_key(tx.hash.as_str()) {
+                continue;
+            }
+            let payment = payment(chain, account, &tx.hash, &normal, &internal, &tokens)?;
+            let gas = gas_paid(account, &tx.hash, &normal)?;
+            let received = nfts
+                .iter()
+                .any(|nft| nft.hash == tx.hash && nft.from != format!("{:#x}", account));
+            if let Some(trade) = nft_trade(chain, payment, gas, received) {
+                nft_trades.insert(tx.hash.as_str(), trade);
+            }
+        }
+
+        // Transactions that only paid gas, such as token transfers and approvals, spend it from
+        // the native token
+        let native_token = format!("{}@{}", chain.native_token(), chain);
+        for tx in normal.iter() {
+            if paid_in(&nft_trades, &tx.hash, chain.native_token()) {
+                continue;
+            }
+            // Token transfers share the hash of the transaction that made them, so native
+            // transfers are distinguished by their token
+            let id = id(account, tx, &chain.native_token().symbol());
+            let mut native = transaction(chain, id, account, tx, &native_token, NATIVE_DECIMALS)?;
+            if tx.from == format!("{:#x}", account) {
+                native.fee = gas(tx)?;
+            }
+            if native.amount.is_zero() && native.fee.is_zero() {
+                continue;
+            }
+            transactions.push(native);
+        }
+
+        transactions.extend(internal_transactions(
+            chain,
+            account,
+            &internal,
+            &nft_trades,
+        )?);
 
-        for tx in txes.iter() {
-            let timestamp = NaiveDateTime::parse_from_str(&tx.time_stamp, "%s")?;
+        for tx in tokens.iter() {
+            if wrapped_native(chain, tx).is_some_and(|token| paid_in(&nft_trades, &tx.hash, token))
+            {
+                continue;
+            }
+            let token = format!("{}@{}", tx.token_symbol, chain);
             let token_decimal: u32 = tx.token_decimal.parse()?;
-            let divisor = 10_u64.pow(token_decimal);
-            let amount = BigDecimal::from_str(&tx.value)? / BigDecimal::from(divisor);
-            let transaction = Transaction {
-                id: tx.hash.clone(),
-                market: "LINK-USD".to_string(),
-                token: tx.token_symbol.clone(),
-                amount,
-                rate: BigDecimal::from(0),
-                usd_rate: BigDecimal::from(0),
-                usd_amount: BigDecimal::from(0),
-                created_at: Some(DateTime::from_utc(timestamp, chrono::Utc)),
-                provider: PROVIDER,
+            transactions.push(transaction(
+                chain,
+                id(account, tx, &tx.log_index),
+                account,
+                tx,
+                &token,
+                token_decimal,
+            )?);
+        }
+
+        for tx in nfts.iter() {
+            // Several NFTs bought or sold together share the payment and gas equally. What's
+            // bought costs the gas on top, and what's sold has it taken out of the proceeds.
+            let rate = match nft_trades.get(tx.hash.as_str()) {
+                Some(trade) => {
+                    let mut units = BigDecimal::zero();
+                    for nft in nfts.iter().filter(|nft| nft.hash == tx.hash) {
+                        units += nft_units(nft)?;
+                    }
+                    if tx.from == format!("{:#x}", account) {
+                        Some((trade.token, (&trade.payment - &trade.gas) / units))
+                    } else {
+                        Some((trade.token, (&trade.gas - &trade.payment) / units))
+                    }
+                }
+                None => None,
             };
-            transactions.push(transaction);
+            transactions.push(nft_transaction(chain, account, tx, rate, &mut prices).await?);
         }
     }
 
     Ok(transactions)
 }
 
-async fn txlist(api_key: &str, account: &web3::types::H160) -> Result<Vec<Tx>, Box<dyn Error>> {
-    let query = vec![
-        "module=account",
-        "action=tokentx",
-        &format!("address={:#x}", account),
-        "startblock=0",
-        "endblock=999999999",
-        "sort=asc",
-        &format!("apiKey={}", api_key),
-    ]
-    .join("&");
-    let url = format!("https://api.etherscan.io/api?{}", query);
+// balances fetches the native token held by each account as of the latest block, and every
+// ERC-20 token it has ever transferred
+async fn balances(
+    key: &str,
+    chain: Chain,
+    url: &str,
+    accounts: &[web3::types::H160],
+) -> Result<Vec<Balance>, Box<dyn Error>> {
+    let mut balances = Vec::new();
+
+    for account in accounts.iter() {
+        let query = format!("action=balance&address={:#x}", account);
+        balances.push(Balance {
+            token: format!("{}@{}", chain.native_token(), chain),
+            amount: balance(key, url, &query, NATIVE_DECIMALS).await?,
+            provider: chain.explorer_name(),
+        });
+
+        let tokens = txlist(key, url, "tokentx", account).await?;
+        let contracts: BTreeMap<&str, &Tx> = tokens
+            .iter()
+            .map(|tx| (tx.contract_address.as_str(), tx))
+            .collect();
+        for (contract, tx) in contracts {
+            let query = format!(
+                "action=tokenbalance&contractaddress={}&address={:#x}",
+                contract, account
+            );
+            balances.push(Balance {
+                token: format!("{}@{}", tx.token_symbol, chain),
+                amount: balance(key, url, &query, tx.token_decimal.parse()?).await?,
+                provider: chain.explorer_name(),
+            });
+        }
+    }
+
+    Ok(balances)
+}
+
+// id names a transfer by the transaction that made it, which of its transfers it was, and the
+// account it moved tokens to or from, as every account it moved tokens between lists the same one
+fn id(account: &web3::types::H160, tx: &Tx, transfer: &str) -> String {
+    format!("{}-{}-{:#x}", tx.hash, transfer, account)
+}
+
+fn transaction(
+    chain: Chain,
+    id: String,
+    account: &web3::types::H160,
+    tx: &Tx,
+    token: &str,
+    decimals: u32,
+) -> Result<Transaction, Box<dyn Error>> {
+    let timestamp = NaiveDateTime::parse_from_str(&tx.time_stamp, "%s")?;
+    // Tokens moved on-chain are transfers, paired with whichever account they moved between, or
+    // else received from or spent with someone else
+    let mut amount = units(value(tx), decimals)?;
+    let mut category = Category::TransferIn;
+    if tx.from == format!("{:#x}", account) {
+        amount = -amount;
+        category = Category::TransferOut;
+    }
+
+    Ok(Transaction {
+        id,
+        hash: Some(tx.hash.clone()),
+        market: format!("{}-{}", token, USD),
+        token: token.to_string(),
+        amount,
+        rate: BigDecimal::from(0),
+        fiat_rate: BigDecimal::from(0),
+        fiat_amount: BigDecimal::from(0),
+        fiat: USD.symbol(),
+        fee: BigDecimal::zero(),
+        category,
+        created_at: Some(DateTime::from_utc(timestamp, chrono::Utc)),
+        provider: chain.explorer_name(),
+    })
+}
+
+// NftTrade is what was paid for the NFTs bought or sold in a transaction, and in which token,
+// with the gas paid for it when that's accounted for in their cost or proceeds
+struct NftTrade {
+    token: Crypto,
+    payment: BigDecimal,
+    gas: BigDecimal,
+}
+
+// paid_in returns true if the transaction traded NFTs for the token
+fn paid_in(nft_trades: &HashMap<&str, NftTrade>, hash: &str, token: Crypto) -> bool {
+    nft_trades
+        .get(hash)
+        .is_some_and(|trade| trade.token == token)
+}
+
+// internal_transactions are the native token moved to or from the account by contracts, such as
+// the proceeds of swapping a token for it or a refund, but for payments for NFTs
+fn internal_transactions(
+    chain: Chain,
+    account: &web3::types::H160,
+    internal: &[Tx],
+    nft_trades: &HashMap<&str, NftTrade>,
+) -> Result<Vec<Transaction>, Box<dyn Error>> {
+    let native_token = format!("{}@{}", chain.native_token(), chain);
+    let mut transactions = Vec::new();
+    for tx in internal.iter() {
+        if paid_in(nft_trades, &tx.hash, chain.native_token()) {
+            continue;
+        }
+        let id = id(account, tx, &format!("internal-{}", tx.trace_id));
+        let transaction = transaction(chain, id, account, tx, &native_token, NATIVE_DECIMALS)?;
+        if !transaction.amount.is_zero() {
+            transactions.push(transaction);
+        }
+    }
+    Ok(transactions)
+}
+
+// nft_trade is the trade NFTs were bought or sold in for a payment, in the wrapped native token if
+// any was paid and otherwise in the native token. The gas paid is accounted for in their cost or
+// proceeds when it's in the same token, otherwise it's spent from the native token like any other.
+fn nft_trade(chain: Chain, payment: Payment, gas: BigDecimal, received: bool) -> Option<NftTrade> {
+    match chain.wrapped_native_token() {
+        Some(token) if !payment.wrapped.is_zero() => Some(NftTrade {
+            token,
+            payment: payment.wrapped,
+            gas: BigDecimal::zero(),
+        }),
+        _ if !payment.native.is_zero() || (received && !gas.is_zero()) => Some(NftTrade {
+            token: chain.native_token(),
+            payment: payment.native,
+            gas,
+        }),
+        _ => None,
+    }
+}
+
+// wrapped_native is the network's wrapped native token, if that's what a token transfer moved
+fn wrapped_native(chain: Chain, tx: &Tx) -> Option<Crypto> {
+    chain
+        .wrapped_native_token()
+        .filter(|token| tx.token_symbol == token.to_string())
+}
+
+// Payment is the native and wrapped native token the account received in a transaction, less what
+// it sent, not counting gas
+#[derive(Debug, Default, PartialEq)]
+struct Payment {
+    native: BigDecimal,
+    wrapped: BigDecimal,
+}
+
+fn payment(
+    chain: Chain,
+    account: &web3::types::H160,
+    hash: &str,
+    normal: &[Tx],
+    internal: &[Tx],
+    tokens: &[Tx],
+) -> Result<Payment, Box<dyn Error>> {
+    let address = format!("{:#x}", account);
+    let net = |tx: &Tx, decimals: u32| -> Result<BigDecimal, Box<dyn Error>> {
+        let amount = units(value(tx), decimals)?;
+        let mut received = BigDecimal::zero();
+        if tx.to == address {
+            received += &amount;
+        }
+        if tx.from == address {
+            received -= &amount;
+        }
+        Ok(received)
+    };
+
+    let mut payment = Payment::default();
+    for tx in normal.iter().chain(internal.iter()) {
+        if tx.hash == hash {
+            payment.native += net(tx, NATIVE_DECIMALS)?;
+        }
+    }
+    for tx in tokens.iter() {
+        if tx.hash == hash && wrapped_native(chain, tx).is_some() {
+            payment.wrapped += net(tx, tx.token_decimal.parse()?)?;
+        }
+    }
+
+    Ok(payment)
+}
+
+// gas_paid totals the gas the account paid for a transaction, which it only pays if it sent it
+fn gas_paid(
+    account: &web3::types::H160,
+    hash: &str,
+    normal: &[Tx],
+) -> Result<BigDecimal, Box<dyn Error>> {
+    let address = format!("{:#x}", account);
+    let mut paid = BigDecimal::zero();
+    for tx in normal
+        .iter()
+        .filter(|tx| tx.hash == hash && tx.from == address)
+    {
+        paid += gas(tx)?;
+    }
+    Ok(paid)
+}
+
+// value is what a transaction transferred, nothing if it was reverted although its gas was still
+// paid
+fn value(tx: &Tx) -> &str {
+    if tx.is_error == "1" {
+        "0"
+    } else {
+        &tx.value
+    }
+}
+
+fn gas(tx: &Tx) -> Result<BigDecimal, Box<dyn Error>> {
+    let gas = tx.gas_used.parse::<BigInt>()? * tx.gas_price.parse::<BigInt>()?;
+    Ok(BigDecimal::new(gas, NATIVE_DECIMALS.into()))
+}
+
+// units reads an amount given in a token's smallest unit, which can have more decimals than fit in
+// a u64
+pub(crate) fn units(value: &str, decimals: u32) -> Result<BigDecimal, Box<dyn Error>> {
+    Ok(BigDecimal::new(value.parse()?, decimals.into()))
+}
+
+// nft_units is the number of tokens transferred, which is always one for an ERC-721 token
+fn nft_units(tx: &Tx) -> Result<BigDecimal, Box<dyn Error>> {
+    if tx.token_value.is_empty() {
+        Ok(BigDecimal::from(1))
+    } else {
+        Ok(BigDecimal::from_str(&tx.token_value)?)
+    }
+}
+
+// nft_transaction is a trade of an NFT bought or sold at a rate in the token paid, or else a
+// transfer of one given, received or moved between wallets
+async fn nft_transaction(
+    chain: Chain,
+    account: &web3::types::H160,
+    tx: &Tx,
+    rate: Option<(Crypto, BigDecimal)>,
+    prices: &mut Prices,
+) -> Result<Transaction, Box<dyn Error>> {
+    let timestamp = NaiveDateTime::parse_from_str(&tx.time_stamp, "%s")?;
+    let created_at = DateTime::from_utc(timestamp, chrono::Utc);
+
+    let nft = Nft {
+        chain,
+        contract: tx.contract_address.parse()?,
+        token_id: U256::from_dec_str(&tx.token_id)?,
+    };
+    let mut amount = nft_units(tx)?;
+    let mut category = Category::TransferIn;
+    if tx.from == format!("{:#x}", account) {
+        amount = -amount;
+        category = Category::TransferOut;
+    }
+    if rate.is_some() {
+        category = Category::Trade;
+    }
+    let (paid_in, rate) = rate.unwrap_or((chain.native_token(), BigDecimal::zero()));
+
+    let mut fiat_rate = BigDecimal::zero();
+    if !rate.is_zero() {
+        match prices.price_at(paid_in, created_at).await {
+            Ok(price) => fiat_rate = &rate * price,
+            Err(e) => eprintln!("Unable to value {} #{}: {}", tx.token_name, tx.token_id, e),
+        }
+    }
+    let fiat_amount = &fiat_rate * amount.abs();
+
+    Ok(Transaction {
+        id: id(account, tx, &tx.log_index),
+        hash: Some(tx.hash.clone()),
+        market: format!("{}-{}@{}", Symbol::Nft(nft), paid_in, chain),
+        token: format!("{} #{}", tx.token_name, tx.token_id),
+        amount,
+        rate,
+        fiat_rate,
+        fiat_amount,
+        fiat: prices.denomination().symbol(),
+        fee: BigDecimal::zero(),
+        category,
+        created_at: Some(created_at),
+        provider: chain.explorer_name(),
+    })
+}
+
+// txlist lists every transaction of the account from any of the listing actions, in the order
+// they were made
+async fn txlist(
+    api_key: &str,
+    url: &str,
+    action: &str,
+    account: &web3::types::H160,
+) -> Result<Vec<Tx>, Box<dyn Error>> {
+    let mut txs = Vec::new();
+    let mut start_block = "0".to_string();
+    loop {
+        let query = vec![
+            "module=account",
+            &format!("action={}", action),
+            &format!("address={:#x}", account),
+            &format!("startblock={}", start_block),
+            "endblock=999999999",
+            "sort=asc",
+            &format!("apiKey={}", api_key),
+        ]
+        .join("&");
+        let mut page: Vec<Tx> = serde_json::from_value(get(&format!("{}?{}", url, query)).await?)?;
+        let next = resume_from(&mut page)?;
+        txs.extend(page);
+        match next {
+            Some(block) => start_block = block,
+            None => return Ok(txs),
+        }
+    }
+}
 
-    let response = reqwest::get(&url).await?.json::<Response>().await?;
-    Ok(response.result)
+// resume_from trims a listing cut off at the most results there can be back to the last block
+// listed in full, and returns the block to list the rest from, or none if nothing was cut off
+fn resume_from(page: &mut Vec<Tx>) -> Result<Option<String>, Box<dyn Error>> {
+    if page.len() < MAX_RESULTS {
+        return Ok(None);
+    }
+    let last_block = page
+        .last()
+        .map(|tx| tx.block_number.clone())
+        .unwrap_or_default();
+    page.retain(|tx| tx.block_number != last_block);
+    if page.is_empty() {
+        return Err(format!(
+            "More than {} transactions in block {}",
+            MAX_RESULTS, last_block
+        )
+        .into());
+    }
+    Ok(Some(last_block))
+}
+
+// balance fetches a balance from either the balance or tokenbalance actions, which give it in the
+// token's smallest unit
+async fn balance(
+    api_key: &str,
+    url: &str,
+    query: &str,
+    decimals: u32,
+) -> Result<BigDecimal, Box<dyn Error>> {
+    let url = format!(
+        "{}?module=account&{}&tag=latest&apiKey={}",
+        url, query, api_key
+    );
+
+    let result = get(&url).await?;
+    units(result.as_str().ok_or("Invalid balance")?, decimals)
+}
+
+// get makes a request of the API, waiting between requests to stay within the rate limit, and
+// returns its result
+async fn get(url: &str) -> Result<Value, Box<dyn Error>> {
+    let mut retries = 0;
+    loop {
+        tokio::time::sleep(REQUEST_INTERVAL * (retries + 1)).await;
+
+        let response = reqwest::get(url).await?.json::<Response>().await?;
+        match result(response) {
+            Err(ApiError::RateLimited(_)) if retries < RETRIES => retries += 1,
+            result => return Ok(result?),
+        }
+    }
 }
 
+// result is what a request returned, an empty list where there was nothing to list, or the
+// reason it failed
+fn result(response: Response) -> Result<Value, ApiError> {
+    if response.status == "1" {
+        return Ok(response.result);
+    }
+    if response.message.starts_with("No transactions found") {
+        return Ok(Value::Array(Vec::new()));
+    }
+
+    let reason = match response.result {
+        Value::String(reason) => format!("{}: {}", response.message, reason),
+        _ => response.message,
+    };
+    if reason.to_lowercase().contains("rate limit") {
+        Err(ApiError::RateLimited(reason))
+    } else {
+        Err(ApiError::Failed(reason))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ApiError {
+    RateLimited(String),
+    Failed(String),
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiError::RateLimited(reason) | ApiError::Failed(reason) => {
+                write!(f, "Block explorer request failed: {}", reason)
+            }
+        }
+    }
+}
+
+impl Error for ApiError {}
+
+// Response is the envelope of every API response, the result is a list or balance when the status
+// is 1, and otherwise usually the reason the request failed
 #[derive(Deserialize, Debug)]
 struct Response {
     status: String,
     message: String,
-    result: Vec<Tx>,
+    result: Value,
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
+// Tx is a transaction as listed by any of the txlist, txlistinternal, tokentx, tokennfttx or
+// token1155tx actions, the token fields are only present for token transfers and the trace id
+// for internal ones
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
 struct Tx {
     block_number: String,
     time_stamp: String,
@@ -76,9 +611,14 @@ struct Tx {
     contract_address: String,
     to: String,
     value: String,
+    log_index: String,
+    trace_id: String,
     token_name: String,
     token_symbol: String,
     token_decimal: String,
+    #[serde(rename = "tokenID")]
+    token_id: String,
+    token_value: String,
     transaction_index: String,
     gas: String,
     gas_price: String,
@@ -86,4 +626,314 @@ struct Tx {
     cumulative_gas_used: String,
     input: String,
     confirmations: String,
+    is_error: String,
+}
+
+#[cfg(test)]
+mod test {
+    use futures::executor::block_on;
+
+    use crate::error::Warnings;
+    use crate::export::{write, Format};
+    use crate::fx::FxRates;
+    use crate::jurisdiction::UnitedStates;
+    use crate::portfolio::Matching;
+    use crate::report::{read_portfolio, read_records};
+    use crate::symbol::Crypto;
+    use crate::wallet::Wallet;
+
+    use super::*;
+
+    const ACCOUNT: &str = "0x4c0457c5fb35183cb25db52c14fea30e737fcf5e";
+    const OTHER: &str = "0x9d1a4c3e0b6f3c0c2b8e8a7f5e1d2c3b4a596877";
+    const MARKET: &str = "0x7be8076f4ea4a4ad08075c2508e481d6c946d12b";
+
+    fn tx(hash: &str, from: &str, to: &str, value: &str) -> Tx {
+        Tx {
+            hash: hash.to_string(),
+            from: from.to_string(),
+            to: to.to_string(),
+            value: value.to_string(),
+            gas_used: "100000".to_string(),
+            gas_price: "100000000000".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_payment_purchase_with_eth() {
+        let account = ACCOUNT.parse().unwrap();
+        let normal = vec![
+            tx("0x1", ACCOUNT, MARKET, "1500000000000000000"),
+            tx("0x2", ACCOUNT, MARKET, "9000000000000000000"),
+        ];
+
+        let payment = payment(Chain::Ethereum, &account, "0x1", &normal, &[], &[]).unwrap();
+        assert_eq!(payment.native, "-1.5".parse().unwrap());
+        assert!(payment.wrapped.is_zero());
+        let gas = gas_paid(&account, "0x1", &normal).unwrap();
+        assert_eq!(gas, "0.01".parse().unwrap());
+    }
+
+    #[test]
+    fn test_payment_sale_for_weth() {
+        let account = ACCOUNT.parse().unwrap();
+        let weth = Tx {
+            token_symbol: "WETH".to_string(),
+            token_decimal: "18".to_string(),
+            ..tx("0x1", MARKET, ACCOUNT, "2000000000000000000")
+        };
+        let internal = vec![tx("0x1", MARKET, ACCOUNT, "500000000000000000")];
+
+        let payment = payment(Chain::Ethereum, &account, "0x1", &[], &internal, &[weth]).unwrap();
+        assert_eq!(
+            payment,
+            Payment {
+                native: "0.5".parse().unwrap(),
+                wrapped: "2".parse().unwrap(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_nft_trade_paid_in_weth() {
+        let account = ACCOUNT.parse().unwrap();
+        let normal = vec![tx("0x1", ACCOUNT, MARKET, "0")];
+        let weth = Tx {
+            token_symbol: "WETH".to_string(),
+            token_decimal: "18".to_string(),
+            ..tx("0x1", ACCOUNT, MARKET, "2000000000000000000")
+        };
+
+        // The NFT is bought for WETH, leaving the gas to be spent from ETH
+        let payment = payment(Chain::Ethereum, &account, "0x1", &normal, &[], &[weth]).unwrap();
+        let gas = gas_paid(&account, "0x1", &normal).unwrap();
+        let trade = nft_trade(Chain::Ethereum, payment, gas, true).unwrap();
+        assert_eq!(trade.token, Crypto::WETH);
+        assert_eq!(trade.payment, "-2".parse().unwrap());
+        assert!(trade.gas.is_zero());
+
+        // but gas paid for an NFT bought with ETH is part of its cost
+        let payment = Payment {
+            native: "-1.5".parse().unwrap(),
+            ..Default::default()
+        };
+        let trade = nft_trade(Chain::Ethereum, payment, "0.01".parse().unwrap(), true).unwrap();
+        assert_eq!(trade.token, Crypto::ETH);
+        assert_eq!(trade.gas, "0.01".parse().unwrap());
+    }
+
+    #[test]
+    fn test_nft_trade_paid_in_wmatic() {
+        let account = ACCOUNT.parse().unwrap();
+        let normal = vec![tx("0x1", ACCOUNT, MARKET, "0")];
+        let wrapped = |symbol: &str| Tx {
+            token_symbol: symbol.to_string(),
+            token_decimal: "18".to_string(),
+            ..tx("0x1", ACCOUNT, MARKET, "30000000000000000000")
+        };
+
+        // An NFT on Polygon is bought for WMATIC, and WETH there is just another token
+        let paid = |symbol| {
+            payment(
+                Chain::Polygon,
+                &account,
+                "0x1",
+                &normal,
+                &[],
+                &[wrapped(symbol)],
+            )
+            .unwrap()
+        };
+        let wmatic = paid("WMATIC");
+        assert_eq!(wmatic.wrapped, "-30".parse().unwrap());
+        let trade = nft_trade(Chain::Polygon, wmatic, BigDecimal::zero(), true).unwrap();
+        assert_eq!(trade.token, Crypto::WMATIC);
+
+        assert!(paid("WETH").wrapped.is_zero());
+    }
+
+    #[test]
+    fn test_internal_transactions() {
+        let account = ACCOUNT.parse().unwrap();
+        let swap = Tx {
+            time_stamp: "1609459200".to_string(),
+            trace_id: "0_1".to_string(),
+            ..tx("0x1", MARKET, ACCOUNT, "1500000000000000000")
+        };
+        let nft_sale = Tx {
+            time_stamp: "1609459200".to_string(),
+            trace_id: "0".to_string(),
+            ..tx("0x2", MARKET, ACCOUNT, "2000000000000000000")
+        };
+        let nft_trades = HashMap::from([(
+            "0x2",
+            NftTrade {
+                token: Crypto::ETH,
+                payment: "2".parse().unwrap(),
+                gas: BigDecimal::zero(),
+            },
+        )]);
+
+        // ETH received from a swap is exported, and that paid for an NFT is left to its trade
+        let transactions =
+            internal_transactions(Chain::Ethereum, &account, &[swap, nft_sale], &nft_trades)
+                .unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].id, format!("0x1-internal-0_1-{}", ACCOUNT));
+        assert_eq!(transactions[0].token, "ETH@ethereum");
+        assert_eq!(transactions[0].amount, "1.5".parse().unwrap());
+        assert_eq!(transactions[0].category, Category::TransferIn);
+    }
+
+    #[test]
+    fn test_payment_reverted() {
+        let account = ACCOUNT.parse().unwrap();
+        let normal = vec![Tx {
+            is_error: "1".to_string(),
+            ..tx("0x1", ACCOUNT, MARKET, "1500000000000000000")
+        }];
+
+        let payment = payment(Chain::Ethereum, &account, "0x1", &normal, &[], &[]).unwrap();
+        assert_eq!(payment, Payment::default());
+        let gas = gas_paid(&account, "0x1", &normal).unwrap();
+        assert_eq!(gas, "0.01".parse().unwrap());
+    }
+
+    #[test]
+    fn test_units() {
+        assert_eq!(units("1500000", 6).unwrap(), "1.5".parse().unwrap());
+        assert_eq!(
+            units("123000000000000000000000000", 24).unwrap(),
+            "123".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_nft_units() {
+        assert_eq!(nft_units(&Tx::default()).unwrap(), BigDecimal::from(1));
+        let erc1155 = Tx {
+            token_value: "3".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(nft_units(&erc1155).unwrap(), BigDecimal::from(3));
+    }
+
+    #[test]
+    fn test_result() {
+        let response = |body: &str| serde_json::from_str::<Response>(body).unwrap();
+
+        assert_eq!(
+            result(response(r#"{"status":"1","message":"OK","result":"42"}"#)),
+            Ok(Value::String("42".into()))
+        );
+        assert_eq!(
+            result(response(
+                r#"{"status":"0","message":"No transactions found","result":[]}"#
+            )),
+            Ok(Value::Array(Vec::new()))
+        );
+        assert_eq!(
+            result(response(
+                r#"{"status":"0","message":"NOTOK","result":"Invalid API Key"}"#
+            )),
+            Err(ApiError::Failed("NOTOK: Invalid API Key".into()))
+        );
+        assert_eq!(
+            result(response(
+                r#"{"status":"0","message":"NOTOK","result":"Max rate limit reached"}"#
+            )),
+            Err(ApiError::RateLimited(
+                "NOTOK: Max rate limit reached".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_resume_from() {
+        let block = |number: usize| Tx {
+            block_number: number.to_string(),
+            ..Default::default()
+        };
+
+        let mut page = (0..10).map(block).collect::<Vec<_>>();
+        assert_eq!(resume_from(&mut page).unwrap(), None);
+        assert_eq!(page.len(), 10);
+
+        // A full page is listed again from its last block, which may not have been listed in full
+        let mut page = (0..MAX_RESULTS).map(|i| block(i / 2)).collect::<Vec<_>>();
+        let last = (MAX_RESULTS - 1) / 2;
+        assert_eq!(resume_from(&mut page).unwrap(), Some(last.to_string()));
+        assert_eq!(page.len(), MAX_RESULTS - 2);
+        assert!(page.iter().all(|tx| tx.block_number != last.to_string()));
+
+        let mut page = (0..MAX_RESULTS).map(|_| block(1)).collect::<Vec<_>>();
+        assert!(resume_from(&mut page).is_err());
+    }
+
+    #[test]
+    fn test_export_transfers_between_accounts() {
+        let account = ACCOUNT.parse().unwrap();
+        let other = OTHER.parse().unwrap();
+        let token = |symbol: &str, (hash, log_index): (&str, &str), from, to, value, day: i64| Tx {
+            time_stamp: (1609459200 + day * 86400).to_string(),
+            log_index: log_index.to_string(),
+            token_symbol: symbol.to_string(),
+            token_decimal: "6".to_string(),
+            ..tx(hash, from, to, value)
+        };
+        let usdc = |id, from, to, value, day| token("USDC", id, from, to, value, day);
+
+        // USDC received, then sent to another account, then swapped for DAI in one transaction
+        let received = usdc(("0x1", "0"), MARKET, ACCOUNT, "100000000", 0);
+        let sent = usdc(("0x2", "0"), ACCOUNT, OTHER, "10000000", 1);
+        let swapped = usdc(("0x3", "1"), ACCOUNT, MARKET, "50000000", 2);
+        let dai = token("DAI", ("0x3", "2"), MARKET, ACCOUNT, "7000000", 2);
+
+        let mut exported = Vec::new();
+        for (account, tx) in [
+            (&account, &received),
+            (&account, &sent),
+            (&account, &swapped),
+            (&account, &dai),
+            (&other, &sent),
+        ] {
+            let token = format!("{}@{}", tx.token_symbol, Chain::Ethereum);
+            let id = id(account, tx, &tx.log_index);
+            exported.push(vec![transaction(
+                Chain::Ethereum,
+                id,
+                account,
+                tx,
+                &token,
+                6,
+            )
+            .unwrap()]);
+        }
+        let mut output = Vec::new();
+        write(&mut output, exported, Format::Csv).unwrap();
+
+        // Both sides of the transfer, and every transfer of the swap, are exported
+        let records = read_records(output.as_slice()).unwrap();
+        assert_eq!(records.len(), 5);
+
+        // and the transfer is paired by its hash, so the USDC sent isn't received a second time
+        let us = UnitedStates {
+            method: Matching::Fifo,
+        };
+        let portfolio = block_on(read_portfolio(
+            output.as_slice(),
+            &USD,
+            &[],
+            &[],
+            FxRates::new(),
+            &us,
+            &mut Warnings::new(true),
+        ))
+        .unwrap();
+        let wallets = portfolio.wallets(&USD, &Wallet::new, Utc.ymd(2022, 1, 1).and_hms(0, 0, 0));
+        let wallet = |crypto| &wallets[&Symbol::OnChain(crypto, Chain::Ethereum)];
+        assert_eq!(wallet(Crypto::USDC).count(), BigDecimal::from(100));
+        assert_eq!(wallet(Crypto::DAI).count(), BigDecimal::from(7));
+    }
 }
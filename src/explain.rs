@@ -0,0 +1,168 @@
+use std::io::{self, Write};
+
+use anyhow::{anyhow, Result};
+
+use crate::amount::Amount;
+use crate::error::Warnings;
+use crate::fx::FxRates;
+use crate::jurisdiction::Jurisdiction;
+use crate::portfolio::{Migration, Source, Step, Trade};
+use crate::report::read_portfolio;
+use crate::symbol::Symbol;
+use crate::types::{format_amount, format_fiat_amount};
+
+// explain traces how the disposals made by a transaction were matched against earlier
+// acquisitions, through any tokens they were exchanged for, to what was realized
+pub async fn explain(
+    id: &str,
+    denomination: &Symbol,
+    migrations: &[Migration],
+    events: &[Trade],
+    fx_rates: FxRates,
+    jurisdiction: &dyn Jurisdiction,
+) -> Result<()> {
+    let mut warnings = Warnings::new(false);
+    let portfolio = read_portfolio(
+        io::stdin(),
+        denomination,
+        migrations,
+        events,
+        fx_rates,
+        jurisdiction,
+        &mut warnings,
+    )
+    .await?;
+    warnings.summarize();
+
+    let steps = jurisdiction.explain(&portfolio, denomination, id)?;
+    if steps.is_empty() {
+        return Err(anyhow!("No disposal was made by transaction {}", id));
+    }
+    write_steps(&mut io::stdout(), &steps, denomination)
+}
+
+fn format(amount: &Amount, denomination: &Symbol) -> String {
+    if amount.symbol == *denomination {
+        format_fiat_amount(&amount.amount, denomination)
+    } else {
+        format!(
+            "{} {}",
+            format_amount(&amount.amount),
+            amount.symbol.symbol()
+        )
+    }
+}
+
+fn source(source: &Option<Source>) -> String {
+    source.as_ref().map_or("no transaction".to_string(), |s| {
+        format!("{} {}", s.provider, s.id)
+    })
+}
+
+pub fn write_steps(output: &mut dyn Write, steps: &[Step], denomination: &Symbol) -> Result<()> {
+    for step in steps {
+        match step {
+            Step::Disposed {
+                when,
+                offered,
+                gained,
+            } => writeln!(
+                output,
+                "Disposed of {} for {} on {}",
+                format(offered, denomination),
+                format(gained, denomination),
+                when.format("%Y-%m-%d")
+            )?,
+            Step::Matched(acquisition) => {
+                writeln!(
+                    output,
+                    "  Matched {} acquired on {} by {} ({} of it) at a cost of {}",
+                    format(&acquisition.amount, denomination),
+                    acquisition.when.format("%Y-%m-%d"),
+                    source(&acquisition.source),
+                    format_amount(&acquisition.fraction),
+                    format(&acquisition.cost, denomination)
+                )?;
+                if acquisition.cost.symbol != *denomination {
+                    writeln!(
+                        output,
+                        "  Matching the {} it was acquired with",
+                        acquisition.cost.symbol.symbol()
+                    )?;
+                }
+            }
+            Step::Unmatched { amount } => writeln!(
+                output,
+                "  Nothing was held to match {} against, it has no cost basis",
+                format(amount, denomination)
+            )?,
+            Step::Realized(realization) => writeln!(
+                output,
+                "  Realized {} {} acquired on {}: proceeds {}, cost basis {}, gain {}",
+                format_amount(&realization.amount),
+                realization.symbol.symbol(),
+                realization
+                    .acquired_when
+                    .map_or("an unknown date".to_string(), |d| {
+                        d.format("%Y-%m-%d").to_string()
+                    }),
+                format_fiat_amount(&realization.proceeds, denomination),
+                format_fiat_amount(&realization.cost_basis, denomination),
+                format_fiat_amount(&realization.gain, denomination)
+            )?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    use crate::jurisdiction::UnitedStates;
+    use crate::portfolio::{Kind, Matching, Portfolio};
+    use crate::symbol::{Crypto, Fiat, USD};
+    use crate::{btc, eth, usd};
+
+    use super::*;
+
+    #[test]
+    fn test_explain() {
+        let mut portfolio = Portfolio::new();
+        let trades = [
+            ("a", Utc.ymd(2020, 1, 1), usd!(1000), btc!(2)),
+            ("b", Utc.ymd(2020, 2, 1), btc!(1), eth!(10)),
+            ("c", Utc.ymd(2020, 3, 1), eth!(10), usd!(3000)),
+        ];
+        for (id, date, offered, gained) in trades {
+            portfolio.add_sourced_trade(
+                &Trade {
+                    when: date.and_hms(0, 0, 0),
+                    kind: Kind::Trade { offered, gained },
+                },
+                &Source {
+                    id: id.to_string(),
+                    provider: "coinbase".to_string(),
+                },
+            );
+        }
+        let us = UnitedStates {
+            method: Matching::Fifo,
+        };
+
+        let steps = us.explain(&portfolio, &USD, "c").unwrap();
+        let mut output = Vec::new();
+        write_steps(&mut output, &steps, &USD).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Disposed of 10.0000 ETH for $3000.00 on 2020-03-01\n\
+             \x20 Matched 10.0000 ETH acquired on 2020-02-01 by coinbase b (1.0000 of it) at a cost of 1.0000 BTC\n\
+             \x20 Matching the BTC it was acquired with\n\
+             \x20 Matched 1.0000 BTC acquired on 2020-01-01 by coinbase a (0.5000 of it) at a cost of $500.00\n\
+             \x20 Realized 10.0000 ETH acquired on 2020-01-01: proceeds $3000.00, cost basis $500.00, gain $2500.00\n"
+        );
+
+        assert!(us.explain(&portfolio, &USD, "a").unwrap().is_empty());
+    }
+}
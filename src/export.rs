@@ -1,76 +1,104 @@
 use crate::itertools::Itertools;
 use std::error::Error;
 use std::io;
+use std::str::FromStr;
 
-use crate::config::{Config, Exchange};
-use crate::types::{format_amount, format_usd_amount, Transaction};
-use crate::{coinbase, coinbase_pro, ethereum, etherscan};
+use crate::config::Config;
+use crate::provider::Registry;
+use crate::types::{format_amount, format_exact_amount, write_json, Transaction};
 
 #[derive(Debug, Deserialize)]
 struct Record {
     id: String,
+    hash: String,
     market: String,
     token: String,
     amount: String,
     rate: String,
-    usd_rate: String,
-    usd_amount: String,
+    fiat_rate: String,
+    fiat_amount: String,
+    fiat: String,
+    fee: String,
+    category: String,
     created_at: String,
     provider: String,
 }
 
-pub async fn export(config: &Config) -> Result<(), Box<dyn Error>> {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseFormatError {}
+
+// Format is how transactions are exported, a CSV for spreadsheets and reports, or JSON for other
+// tools, either as an array or JSON Lines
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Format {
+    Csv,
+    Json,
+    JsonLines,
+}
+
+impl FromStr for Format {
+    type Err = ParseFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "jsonl" => Ok(Format::JsonLines),
+            _ => Err(ParseFormatError {}),
+        }
+    }
+}
+
+pub async fn export(
+    config: &Config,
+    registry: &Registry,
+    format: Format,
+) -> Result<(), Box<dyn Error>> {
     let mut exchange_transactions: Vec<Vec<Transaction>> = Vec::new();
 
     // Add the manual transactions
     exchange_transactions.push(config.transactions());
 
     // Add all exchange transactions
-    for exchange in &config.exchanges {
-        exchange_transactions.push(match exchange {
-            Exchange::CoinbasePro {
-                ref key,
-                ref secret,
-                ref passphrase,
-            } => coinbase_pro::transactions(key, secret, passphrase, config.denomination()).await?,
-            Exchange::Coinbase {
-                ref key,
-                ref secret,
-            } => coinbase::transactions(key, secret).await?,
-            Exchange::Ethereum { ref url } => {
-                if let Some(ref a) = config.accounts {
-                    ethereum::transactions(url, a).await?
-                } else {
-                    eprintln!("Specified ethereum configuration with no accounts");
-                    vec![]
-                }
-            }
-            Exchange::Etherscan { ref key } => {
-                if let Some(ref a) = config.accounts {
-                    etherscan::transactions(key, a).await?
-                } else {
-                    eprintln!("Specified etherscan configuration with no accounts");
-                    vec![]
-                }
-            }
-        });
+    for provider in registry.providers() {
+        exchange_transactions.push(provider.transactions(None).await?);
     }
 
+    write(&mut io::stdout(), exchange_transactions, format)
+}
+
+// write merges the transactions of every provider in order of date, dropping any exported twice,
+// and writes them in the format
+pub fn write(
+    output: &mut dyn io::Write,
+    exchange_transactions: Vec<Vec<Transaction>>,
+    format: Format,
+) -> Result<(), Box<dyn Error>> {
     // This will likely need to hold the entire set of transactions in memory, so watch out...
     let transactions = itertools::kmerge(exchange_transactions)
         .unique_by(|t| t.id.clone())
         .sorted();
 
     // Output
-    let mut writer = csv::Writer::from_writer(io::stdout());
+    if format != Format::Csv {
+        let transactions = transactions.collect::<Vec<_>>();
+        write_json(output, &transactions, format == Format::JsonLines)?;
+        return Ok(());
+    }
+
+    let mut writer = csv::Writer::from_writer(output);
     writer.write_record(&[
         "ID",
+        "Hash",
         "Market",
         "Token",
         "Amount",
         "Rate",
-        "USD Rate",
-        "USD Amount",
+        "Fiat Rate",
+        "Fiat Amount",
+        "Fiat",
+        "Fee",
+        "Category",
         "Created At",
         "Provider",
     ])?;
@@ -78,12 +106,16 @@ pub async fn export(config: &Config) -> Result<(), Box<dyn Error>> {
     for transaction in transactions {
         writer.write_record(&[
             &transaction.id,
+            transaction.hash.as_deref().unwrap_or_default(),
             &transaction.market,
             &transaction.token,
-            &format_amount(&transaction.amount),
+            &format_exact_amount(&transaction.amount),
             &format_amount(&transaction.rate),
-            &format_usd_amount(&transaction.usd_rate),
-            &format_usd_amount(&transaction.usd_amount),
+            &format_amount(&transaction.fiat_rate),
+            &format_amount(&transaction.fiat_amount),
+            &transaction.fiat,
+            &format_exact_amount(&transaction.fee),
+            &transaction.category.to_string(),
             &transaction.created_at.map_or("".to_string(), |t| {
                 t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
             }),
@@ -94,3 +126,42 @@ pub async fn export(config: &Config) -> Result<(), Box<dyn Error>> {
     writer.flush()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    use crate::report::read_records;
+    use crate::types::Category;
+
+    use super::*;
+
+    #[test]
+    fn test_write_satoshi_fee() {
+        let transaction = Transaction {
+            id: "f4184fc5".to_string(),
+            hash: Some("f4184fc5".to_string()),
+            market: "BTC@bitcoin-USD".to_string(),
+            token: "BTC@bitcoin".to_string(),
+            amount: "-0.00123456".parse().unwrap(),
+            rate: "0".parse().unwrap(),
+            fiat_rate: "0".parse().unwrap(),
+            fiat_amount: "0".parse().unwrap(),
+            fiat: "USD".to_string(),
+            fee: "0.00001234".parse().unwrap(),
+            category: Category::TransferOut,
+            created_at: Some(Utc.ymd(2021, 3, 1).and_hms(12, 30, 0)),
+            provider: "esplora",
+        };
+
+        let mut output = Vec::new();
+        write(&mut output, vec![vec![transaction]], Format::Csv).unwrap();
+
+        // Amounts and fees of a few satoshis are read back as they were
+        let records = read_records(output.as_slice()).unwrap();
+        assert_eq!(records[0].amount, "-0.00123456".parse().unwrap());
+        assert_eq!(records[0].fee, "0.00001234".parse().unwrap());
+        assert_eq!(records[0].hash, Some("f4184fc5".to_string()));
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Result;
+use bigdecimal::{BigDecimal, One};
+use chrono::{Duration, NaiveDate};
+use serde_json::value::RawValue;
+
+use crate::symbol::Fiat;
+use crate::types::DateTime;
+
+const FRANKFURTER_URL: &str = "https://api.frankfurter.app";
+
+// Rates are only published on business days, so a rate is looked up from up to this many days
+// before the day it's needed
+const STALE_DAYS: i64 = 7;
+
+// FxRates is a table of daily exchange rates between fiat currencies, used to convert amounts
+// recorded in one currency into another
+#[derive(Debug, Default)]
+pub struct FxRates {
+    rates: HashMap<(Fiat, Fiat), BTreeMap<NaiveDate, BigDecimal>>,
+}
+
+impl FxRates {
+    pub fn new() -> FxRates {
+        FxRates::default()
+    }
+
+    // add_rate records the amount of to that one unit of from was worth on day
+    pub fn add_rate(&mut self, from: Fiat, to: Fiat, day: NaiveDate, rate: BigDecimal) {
+        self.rates.entry((from, to)).or_default().insert(day, rate);
+    }
+
+    // has_rate returns true if a rate between the two currencies is known for day, in either
+    // direction
+    pub fn has_rate(&self, from: Fiat, to: Fiat, day: NaiveDate) -> bool {
+        self.rate(from, to, day).is_some()
+    }
+
+    // convert values an amount of from in to, at the most recent rate on or before when, none if
+    // there's no rate from the week before
+    pub fn convert(
+        &self,
+        amount: &BigDecimal,
+        from: Fiat,
+        to: Fiat,
+        when: DateTime,
+    ) -> Option<BigDecimal> {
+        self.rate(from, to, when.naive_utc().date())
+            .map(|rate| amount * rate)
+    }
+
+    fn rate(&self, from: Fiat, to: Fiat, day: NaiveDate) -> Option<BigDecimal> {
+        if from == to {
+            return Some(BigDecimal::one());
+        }
+
+        let recent = |rates: &BTreeMap<NaiveDate, BigDecimal>| {
+            rates
+                .range(day - Duration::days(STALE_DAYS)..=day)
+                .next_back()
+                .map(|(_, rate)| rate.clone())
+        };
+        if let Some(rate) = self.rates.get(&(from, to)).and_then(recent) {
+            return Some(rate);
+        }
+        self.rates
+            .get(&(to, from))
+            .and_then(recent)
+            .map(|rate| BigDecimal::one() / rate)
+    }
+
+    // fetch adds the daily reference rates published by the European Central Bank between two
+    // days, via the Frankfurter API
+    pub async fn fetch(
+        &mut self,
+        from: Fiat,
+        to: Fiat,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<()> {
+        let url = format!(
+            "{}/{}..{}?from={}&to={}",
+            FRANKFURTER_URL,
+            start - Duration::days(STALE_DAYS),
+            end,
+            from,
+            to
+        );
+        let body = reqwest::get(&url).await?.error_for_status()?.text().await?;
+        self.add_time_series(from, to, &body)
+    }
+
+    // add_time_series adds every rate in a Frankfurter time series, read from the JSON as the
+    // decimals they're published as
+    fn add_time_series(&mut self, from: Fiat, to: Fiat, body: &str) -> Result<()> {
+        let series: TimeSeries = serde_json::from_str(body)?;
+        for (day, rates) in series.rates {
+            if let Some(rate) = rates.get(&to.symbol()) {
+                self.add_rate(from, to, day.parse()?, rate.get().parse()?);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct TimeSeries {
+    rates: HashMap<String, HashMap<String, Box<RawValue>>>,
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::offset::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_convert() {
+        let mut rates = FxRates::new();
+        rates.add_rate(
+            Fiat::EUR,
+            Fiat::USD,
+            NaiveDate::from_ymd(2021, 1, 8),
+            "1.25".parse().unwrap(),
+        );
+
+        // Saturday uses Friday's rate
+        let saturday = chrono::Utc.ymd(2021, 1, 9).and_hms(12, 0, 0);
+        assert_eq!(
+            rates
+                .convert(&BigDecimal::from(100), Fiat::EUR, Fiat::USD, saturday)
+                .unwrap(),
+            BigDecimal::from(125)
+        );
+        assert_eq!(
+            rates
+                .convert(&BigDecimal::from(125), Fiat::USD, Fiat::EUR, saturday)
+                .unwrap(),
+            BigDecimal::from(100)
+        );
+        assert_eq!(
+            rates
+                .convert(&BigDecimal::from(7), Fiat::GBP, Fiat::GBP, saturday)
+                .unwrap(),
+            BigDecimal::from(7)
+        );
+
+        let before = chrono::Utc.ymd(2021, 1, 7).and_hms(12, 0, 0);
+        assert!(rates
+            .convert(&BigDecimal::from(100), Fiat::EUR, Fiat::USD, before)
+            .is_none());
+        let long_after = chrono::Utc.ymd(2021, 2, 1).and_hms(12, 0, 0);
+        assert!(rates
+            .convert(&BigDecimal::from(100), Fiat::EUR, Fiat::USD, long_after)
+            .is_none());
+    }
+
+    #[test]
+    fn test_add_time_series() {
+        let mut rates = FxRates::new();
+        rates
+            .add_time_series(
+                Fiat::EUR,
+                Fiat::USD,
+                r#"{"amount":1.0,"base":"EUR","start_date":"2021-01-04","end_date":"2021-01-05",
+                    "rates":{"2021-01-04":{"USD":1.2296},"2021-01-05":{"USD":1.2271}}}"#,
+            )
+            .unwrap();
+
+        // Rates are exactly as published, rather than the nearest float
+        let when = chrono::Utc.ymd(2021, 1, 5).and_hms(0, 0, 0);
+        assert_eq!(
+            rates
+                .convert(&BigDecimal::from(10000), Fiat::EUR, Fiat::USD, when)
+                .unwrap(),
+            BigDecimal::from(12271)
+        );
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use anyhow::Result;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::Duration;
+
+use crate::error::Warnings;
+use crate::fx::FxRates;
+use crate::jurisdiction::Jurisdiction;
+use crate::portfolio::{Migration, Portfolio, Trade};
+use crate::prices::Prices;
+use crate::report::read_portfolio;
+use crate::symbol::Symbol;
+use crate::types::{format_fiat_amount, DateTime};
+
+// Holding is a lot still held, with its share of the cost of whatever was given up for it
+#[derive(Debug, Eq, PartialEq)]
+pub struct Holding {
+    pub symbol: Symbol,
+    pub description: String,
+    pub amount: BigDecimal,
+    pub acquired_when: DateTime,
+    pub unit_cost: BigDecimal,
+    pub cost_basis: BigDecimal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseFormatError {}
+
+// Format is how holdings are written, a CSV for spreadsheets or a table to read
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Format {
+    CSV,
+    Table,
+}
+
+impl FromStr for Format {
+    type Err = ParseFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "csv" => Ok(Format::CSV),
+            "table" => Ok(Format::Table),
+            _ => Err(ParseFormatError {}),
+        }
+    }
+}
+
+// holdings lists every lot held up until a given time, valued at the market price of the day
+// before
+pub async fn holdings(
+    until: DateTime,
+    denomination: &Symbol,
+    migrations: &[Migration],
+    events: &[Trade],
+    fx_rates: FxRates,
+    jurisdiction: &dyn Jurisdiction,
+    format: Format,
+) -> Result<()> {
+    let mut warnings = Warnings::new(false);
+    let portfolio = read_portfolio(
+        io::stdin(),
+        denomination,
+        migrations,
+        events,
+        fx_rates,
+        jurisdiction,
+        &mut warnings,
+    )
+    .await?;
+    warnings.summarize();
+    let holdings = open_lots(&portfolio, jurisdiction, denomination, until);
+
+    let mut prices = Prices::new(*denomination);
+    let mut market_prices = HashMap::new();
+    for holding in holdings.iter() {
+        if market_prices.contains_key(&holding.symbol) {
+            continue;
+        }
+        let price = match holding.symbol {
+            Symbol::Crypto(token) | Symbol::OnChain(token, _) => {
+                match prices.price_at(token, until - Duration::seconds(1)).await {
+                    Ok(price) => Some(price),
+                    Err(err) => {
+                        eprintln!("Unable to value {}: {}", holding.description, err);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        market_prices.insert(holding.symbol, price);
+    }
+
+    write_holdings(
+        &mut io::stdout(),
+        &holdings,
+        &market_prices,
+        denomination,
+        format,
+    )
+}
+
+// open_lots replays every trade made before until, selling lots in the order the jurisdiction
+// disposes of them, and returns the lots left over. A token exchanged for another carries the
+// cost and date of each lot over to what it was exchanged for.
+pub fn open_lots(
+    portfolio: &Portfolio,
+    jurisdiction: &dyn Jurisdiction,
+    denomination: &Symbol,
+    until: DateTime,
+) -> Vec<Holding> {
+    let wallets = portfolio.wallets(denomination, &|token| jurisdiction.wallet(token), until);
+
+    let mut holdings = wallets
+        .iter()
+        .flat_map(|(symbol, wallet)| {
+            wallet
+                .lots()
+                .iter()
+                .filter(|lot| lot.amount > BigDecimal::zero())
+                .map(|lot| Holding {
+                    symbol: *symbol,
+                    description: portfolio.name(symbol),
+                    amount: lot.amount.clone(),
+                    acquired_when: lot.date_of_purchase,
+                    unit_cost: lot.unit_cost.clone(),
+                    cost_basis: lot.cost_basis(),
+                })
+        })
+        .collect::<Vec<_>>();
+    holdings.sort_by(|a, b| {
+        a.description
+            .cmp(&b.description)
+            .then(a.acquired_when.cmp(&b.acquired_when))
+    });
+    holdings
+}
+
+// write_holdings writes a row for each holding, and their totals, valuing those with a market
+// price
+pub fn write_holdings(
+    output: &mut dyn Write,
+    holdings: &[Holding],
+    market_prices: &HashMap<Symbol, Option<BigDecimal>>,
+    denomination: &Symbol,
+    format: Format,
+) -> Result<()> {
+    let mut rows = vec![[
+        "Asset",
+        "Amount",
+        "Date acquired",
+        "Unit cost",
+        "Cost basis",
+        "Market value",
+        "Unrealized gain",
+    ]
+    .map(String::from)];
+
+    let (mut total_cost, mut total_value) = (BigDecimal::zero(), Some(BigDecimal::zero()));
+    for holding in holdings {
+        let value = market_prices
+            .get(&holding.symbol)
+            .cloned()
+            .flatten()
+            .map(|price| &holding.amount * price);
+
+        total_cost += &holding.cost_basis;
+        total_value = total_value.zip(value.clone()).map(|(t, v)| t + v);
+
+        rows.push([
+            holding.description.clone(),
+            holding.amount.to_string(),
+            holding.acquired_when.format("%Y-%m-%d").to_string(),
+            format_fiat_amount(&holding.unit_cost, denomination),
+            format_fiat_amount(&holding.cost_basis, denomination),
+            value
+                .as_ref()
+                .map_or("".to_string(), |v| format_fiat_amount(v, denomination)),
+            value.as_ref().map_or("".to_string(), |v| {
+                format_fiat_amount(&(v - &holding.cost_basis), denomination)
+            }),
+        ]);
+    }
+
+    // Totals are only valued when every holding could be
+    rows.push([
+        "Total".to_string(),
+        "".to_string(),
+        "".to_string(),
+        "".to_string(),
+        format_fiat_amount(&total_cost, denomination),
+        total_value
+            .as_ref()
+            .map_or("".to_string(), |v| format_fiat_amount(v, denomination)),
+        total_value.as_ref().map_or("".to_string(), |v| {
+            format_fiat_amount(&(v - &total_cost), denomination)
+        }),
+    ]);
+
+    match format {
+        Format::CSV => {
+            let mut writer = csv::Writer::from_writer(output);
+            for row in rows {
+                writer.write_record(&row)?;
+            }
+            writer.flush()?;
+        }
+        Format::Table => {
+            let widths = (0..rows[0].len())
+                .map(|i| rows.iter().map(|row| row[i].chars().count()).max().unwrap())
+                .collect::<Vec<_>>();
+            for (n, row) in rows.iter().enumerate() {
+                // The asset and date are aligned left, amounts right
+                let line = row
+                    .iter()
+                    .zip(widths.iter())
+                    .enumerate()
+                    .map(|(i, (cell, width))| match i {
+                        0 | 2 => format!("{:<width$}", cell, width = width),
+                        _ => format!("{:>width$}", cell, width = width),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("  ");
+                writeln!(output, "{}", line.trim_end())?;
+                if n == 0 || n == rows.len() - 2 {
+                    let rule = widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>();
+                    writeln!(output, "{}", rule.join("  "))?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    use crate::amount::Amount;
+    use crate::jurisdiction::UnitedStates;
+    use crate::portfolio::{Kind, Matching, Trade};
+    use crate::symbol::{Crypto, Fiat, BTC, USD};
+    use crate::{btc, eth, usd};
+
+    use super::*;
+
+    const ETH: Symbol = Symbol::Crypto(Crypto::ETH);
+
+    fn trade(portfolio: &mut Portfolio, when: DateTime, offered: Amount, gained: Amount) {
+        portfolio.add_trade(&Trade {
+            when,
+            kind: Kind::Trade { offered, gained },
+        });
+    }
+
+    #[test]
+    fn test_open_lots() {
+        let mut portfolio = Portfolio::new();
+        let day = |m, d| Utc.ymd(2021, m, d).and_hms(0, 0, 0);
+
+        trade(&mut portfolio, day(1, 1), usd!(1000), btc!(10));
+        trade(&mut portfolio, day(2, 1), usd!(3000), btc!(10));
+        trade(&mut portfolio, day(3, 1), btc!(5), usd!(1500));
+        trade(&mut portfolio, day(4, 1), btc!(5), eth!(100));
+        trade(&mut portfolio, day(6, 1), btc!(10), usd!(5000));
+
+        let us = UnitedStates {
+            method: Matching::Fifo,
+        };
+        let holding = |symbol, amount, acquired_when, unit_cost: &str, cost_basis| Holding {
+            symbol,
+            description: portfolio.name(&symbol),
+            amount: BigDecimal::from(amount),
+            acquired_when,
+            unit_cost: unit_cost.parse().unwrap(),
+            cost_basis: BigDecimal::from(cost_basis),
+        };
+
+        // The first lot is sold, then exchanged, carrying its cost and date over to the ETH
+        assert_eq!(
+            open_lots(&portfolio, &us, &USD, day(5, 1)),
+            vec![
+                holding(BTC, 10, day(2, 1), "300", 3000),
+                holding(ETH, 100, day(1, 1), "5", 500),
+            ]
+        );
+
+        let us = UnitedStates {
+            method: Matching::Lifo,
+        };
+        assert_eq!(
+            open_lots(&portfolio, &us, &USD, day(5, 1)),
+            vec![
+                holding(BTC, 10, day(1, 1), "100", 1000),
+                holding(ETH, 100, day(2, 1), "15", 1500),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_holdings() {
+        let holdings = vec![Holding {
+            symbol: BTC,
+            description: "BTC".into(),
+            amount: BigDecimal::from(2),
+            acquired_when: Utc.ymd(2021, 1, 1).and_hms(0, 0, 0),
+            unit_cost: BigDecimal::from(100),
+            cost_basis: BigDecimal::from(200),
+        }];
+        let market_prices = HashMap::from([(BTC, Some(BigDecimal::from(150)))]);
+
+        let mut output = Vec::new();
+        write_holdings(
+            &mut output,
+            &holdings,
+            &market_prices,
+            &Symbol::Fiat(Fiat::USD),
+            Format::CSV,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Asset,Amount,Date acquired,Unit cost,Cost basis,Market value,Unrealized gain\n\
+             BTC,2,2021-01-01,$100.00,$200.00,$300.00,$100.00\n\
+             Total,,,,$200.00,$300.00,$100.00\n"
+        );
+
+        let mut output = Vec::new();
+        write_holdings(
+            &mut output,
+            &holdings,
+            &HashMap::new(),
+            &Symbol::Fiat(Fiat::USD),
+            Format::Table,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Asset  Amount  Date acquired  Unit cost  Cost basis  Market value  Unrealized gain\n\
+             -----  ------  -------------  ---------  ----------  ------------  ---------------\n\
+             BTC         2  2021-01-01       $100.00     $200.00\n\
+             -----  ------  -------------  ---------  ----------  ------------  ---------------\n\
+             Total                                       $200.00\n"
+        );
+    }
+}
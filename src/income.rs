@@ -0,0 +1,267 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+use anyhow::Result;
+use bigdecimal::{BigDecimal, Zero};
+
+use crate::amount::Amount;
+use crate::error::Warnings;
+use crate::fx::FxRates;
+use crate::jurisdiction::{Jurisdiction, YearStart};
+use crate::portfolio::{Kind, Source, Trade};
+use crate::report::{fetch_fx_rates, in_denomination, read_records, symbol};
+use crate::symbol::Symbol;
+use crate::types::{format_fiat_amount, Category, DateTime};
+
+// The categories of income, in the order they're reported
+const CATEGORIES: [Category; 5] = [
+    Category::Staking,
+    Category::Mining,
+    Category::Interest,
+    Category::Reward,
+    Category::Fork,
+];
+
+// Income is a receipt of tokens as income, valued in the denomination when it was received
+#[derive(Debug, Eq, PartialEq)]
+pub struct Income {
+    pub when: DateTime,
+    pub category: Category,
+    pub token: String,
+    pub amount: BigDecimal,
+    pub value: BigDecimal,
+}
+
+// income reports the ordinary income received in the tax year, totalled by category for each
+// month, or each receipt when detailed
+pub async fn income(
+    year: u16,
+    denomination: &Symbol,
+    events: &[Trade],
+    mut fx_rates: FxRates,
+    jurisdiction: &dyn Jurisdiction,
+    year_start: Option<YearStart>,
+    detail: bool,
+) -> Result<()> {
+    let records = read_records(io::stdin())?;
+    if let Symbol::Fiat(to) = denomination {
+        fetch_fx_rates(&mut fx_rates, &records, *to).await?;
+    }
+
+    let tax_year = year_start
+        .unwrap_or_else(|| jurisdiction.year_start())
+        .tax_year(year);
+    let mut warnings = Warnings::new(false);
+    let mut receipts = Vec::new();
+    for record in records
+        .iter()
+        .filter(|r| r.category.is_income() && tax_year.contains(&r.created_at))
+    {
+        let source = Source {
+            id: record.id.clone(),
+            provider: record.provider.clone(),
+        };
+        let Some(fiat) = symbol(&mut warnings, &record.fiat, &source)? else {
+            continue;
+        };
+        if record.fiat_amount.is_zero() {
+            eprintln!(
+                "No market value for {} {} received on {}, it's reported as nothing",
+                record.amount, record.token, record.created_at
+            );
+        }
+        let value = Amount {
+            amount: record.fiat_amount.abs(),
+            symbol: fiat,
+        };
+        let when = record.created_at;
+        let Some(value) =
+            in_denomination(&fx_rates, value, denomination, when, &mut warnings, &source)?
+        else {
+            continue;
+        };
+        receipts.push(Income {
+            when: record.created_at,
+            category: record.category,
+            token: record.token.clone(),
+            amount: record.amount.abs(),
+            value: value.amount,
+        });
+    }
+
+    // Forks taxed as income are income at their cost basis, which is already in the denomination
+    for trade in events.iter().filter(|t| tax_year.contains(&t.when)) {
+        if let Kind::Fork {
+            ref gained,
+            ref cost_basis,
+            income: true,
+        } = trade.kind
+        {
+            receipts.push(Income {
+                when: trade.when,
+                category: Category::Fork,
+                token: gained.symbol.symbol(),
+                amount: gained.amount.clone(),
+                value: cost_basis.clone(),
+            });
+        }
+    }
+    receipts.sort_by_key(|r| r.when);
+    warnings.summarize();
+
+    write_income(
+        &mut io::stdout(),
+        &receipts,
+        jurisdiction,
+        denomination,
+        detail,
+    )
+}
+
+fn title(category: Category) -> &'static str {
+    match category {
+        Category::Trade => "Trade",
+        Category::Staking => "Staking",
+        Category::Mining => "Mining",
+        Category::Interest => "Interest",
+        Category::Reward => "Reward",
+        Category::Fork => "Fork",
+        Category::Conversion => "Conversion",
+        Category::TransferIn => "Transfer in",
+        Category::TransferOut => "Transfer out",
+        Category::Deposit => "Deposit",
+        Category::Withdrawal => "Withdrawal",
+    }
+}
+
+fn write_income(
+    output: &mut dyn Write,
+    receipts: &[Income],
+    jurisdiction: &dyn Jurisdiction,
+    denomination: &Symbol,
+    detail: bool,
+) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(output);
+
+    if detail {
+        writer.write_record(&["Date", "Category", "Token", "Amount", "Value", "Form"])?;
+        for receipt in receipts {
+            writer.write_record(&[
+                receipt.when.format("%Y-%m-%d").to_string(),
+                title(receipt.category).to_string(),
+                receipt.token.clone(),
+                receipt.amount.to_string(),
+                format_fiat_amount(&receipt.value, denomination),
+                jurisdiction
+                    .income_form(receipt.category)
+                    .unwrap_or_default()
+                    .to_string(),
+            ])?;
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+
+    let mut months: BTreeMap<String, HashMap<Category, BigDecimal>> = BTreeMap::new();
+    let mut totals: HashMap<Category, BigDecimal> = HashMap::new();
+    for receipt in receipts {
+        *months
+            .entry(receipt.when.format("%Y-%m").to_string())
+            .or_default()
+            .entry(receipt.category)
+            .or_insert_with(BigDecimal::zero) += &receipt.value;
+        *totals
+            .entry(receipt.category)
+            .or_insert_with(BigDecimal::zero) += &receipt.value;
+    }
+
+    let mut header = vec!["Month"];
+    header.extend(CATEGORIES.iter().map(|c| title(*c)));
+    header.push("Total");
+    writer.write_record(&header)?;
+
+    let row = |label: &str, values: &HashMap<Category, BigDecimal>| {
+        let mut row = vec![label.to_string()];
+        row.extend(CATEGORIES.iter().map(|c| {
+            format_fiat_amount(values.get(c).unwrap_or(&BigDecimal::zero()), denomination)
+        }));
+        let total: BigDecimal = values.values().sum();
+        row.push(format_fiat_amount(&total, denomination));
+        row
+    };
+    for (month, values) in months.iter() {
+        writer.write_record(&row(month, values))?;
+    }
+    writer.write_record(&row("Total", &totals))?;
+
+    if CATEGORIES
+        .iter()
+        .any(|c| jurisdiction.income_form(*c).is_some())
+    {
+        let mut forms = vec!["Form"];
+        forms.extend(
+            CATEGORIES
+                .iter()
+                .map(|c| jurisdiction.income_form(*c).unwrap_or_default()),
+        );
+        forms.push("");
+        writer.write_record(&forms)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    use crate::jurisdiction::{Germany, UnitedStates};
+    use crate::portfolio::Matching;
+    use crate::symbol::USD;
+
+    use super::*;
+
+    fn receipts() -> Vec<Income> {
+        let receipt = |m, d, category, token: &str, amount, value| Income {
+            when: Utc.ymd(2021, m, d).and_hms(0, 0, 0),
+            category,
+            token: token.to_string(),
+            amount: BigDecimal::from(amount),
+            value: BigDecimal::from(value),
+        };
+        vec![
+            receipt(1, 5, Category::Staking, "ETH", 1, 1000),
+            receipt(1, 20, Category::Interest, "USDC", 5, 5),
+            receipt(1, 31, Category::Staking, "ETH", 1, 1200),
+            receipt(3, 1, Category::Reward, "XLM", 100, 30),
+        ]
+    }
+
+    #[test]
+    fn test_write_income() {
+        let us = UnitedStates {
+            method: Matching::Fifo,
+        };
+        let mut output = Vec::new();
+        write_income(&mut output, &receipts(), &us, &USD, false).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Month,Staking,Mining,Interest,Reward,Fork,Total\n\
+             2021-01,$2200.00,$0.00,$5.00,$0.00,$0.00,$2205.00\n\
+             2021-03,$0.00,$0.00,$0.00,$30.00,$0.00,$30.00\n\
+             Total,$2200.00,$0.00,$5.00,$30.00,$0.00,$2235.00\n\
+             Form,Schedule 1,Schedule C,Schedule 1,Schedule 1,Schedule 1,\n"
+        );
+
+        // Jurisdictions without forms for income leave them out
+        let mut output = Vec::new();
+        write_income(&mut output, &receipts()[3..], &Germany {}, &USD, true).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Date,Category,Token,Amount,Value,Form\n\
+             2021-03-01,Reward,XLM,100,$30.00,\n"
+        );
+    }
+}
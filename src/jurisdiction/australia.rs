@@ -0,0 +1,222 @@
+use std::io::Write;
+
+use anyhow::Result;
+use bigdecimal::{BigDecimal, Zero};
+
+use crate::jurisdiction::{held_over_a_year, Jurisdiction, YearStart};
+use crate::portfolio::{Disposal, Matching, Portfolio, Provenance, Step};
+use crate::report::{Format, Realization};
+use crate::symbol::Symbol;
+use crate::types::format_fiat_amount;
+use crate::wallet::Wallet;
+
+// Australia discounts capital gains on assets held for over 12 months by half, after losses
+// have been applied, the tax year runs from July 1st to June 30th
+pub struct Australia {
+    pub method: Matching,
+}
+
+// NetCapitalGain follows the method statement of the CGT schedule: losses are applied to gains
+// that can't be discounted first, then the discount is applied to what remains
+struct NetCapitalGain {
+    gains: BigDecimal,
+    losses: BigDecimal,
+    discount: BigDecimal,
+    net: BigDecimal,
+}
+
+impl Australia {
+    fn net_capital_gain(&self, realizations: &[Realization]) -> NetCapitalGain {
+        let (mut undiscounted, mut discountable, mut losses) =
+            (BigDecimal::zero(), BigDecimal::zero(), BigDecimal::zero());
+        for realization in realizations {
+            if realization.gain < BigDecimal::zero() {
+                losses -= &realization.gain;
+            } else if self.long_term(realization) {
+                discountable += &realization.gain;
+            } else {
+                undiscounted += &realization.gain;
+            }
+        }
+        let gains = &undiscounted + &discountable;
+
+        let applied = losses.clone().min(undiscounted.clone());
+        undiscounted -= &applied;
+        let applied = (&losses - &applied).min(discountable.clone());
+        discountable -= &applied;
+
+        let discount = &discountable / BigDecimal::from(2);
+        let net = undiscounted + discountable - &discount;
+        NetCapitalGain {
+            gains,
+            losses,
+            discount,
+            net,
+        }
+    }
+}
+
+impl Jurisdiction for Australia {
+    fn name(&self) -> &'static str {
+        "Australia"
+    }
+
+    fn realizations(&self, portfolio: &Portfolio, denomination: &Symbol) -> Vec<Realization> {
+        portfolio.realizations(denomination, self.method)
+    }
+
+    fn dispositions(
+        &self,
+        portfolio: &Portfolio,
+        denomination: &Symbol,
+    ) -> Vec<(Disposal, Realization)> {
+        portfolio.dispositions(denomination, self.method)
+    }
+
+    fn audit(
+        &self,
+        portfolio: &Portfolio,
+        denomination: &Symbol,
+    ) -> Vec<(Realization, Provenance)> {
+        portfolio.audit(denomination, self.method)
+    }
+
+    fn explain(&self, portfolio: &Portfolio, denomination: &Symbol, id: &str) -> Result<Vec<Step>> {
+        Ok(portfolio.explain(denomination, self.method, id))
+    }
+
+    fn wallet(&self, token: &Symbol) -> Wallet {
+        self.method.wallet(token)
+    }
+
+    fn year_start(&self) -> YearStart {
+        YearStart::new(7, 1)
+    }
+
+    // The discount applies to assets held for at least 12 months, not counting the days they
+    // were bought and sold
+    fn long_term(&self, realization: &Realization) -> bool {
+        held_over_a_year(realization)
+    }
+
+    fn values_exchanges(&self) -> bool {
+        true
+    }
+
+    fn taxable_gain(&self, _year: u16, realizations: &[Realization]) -> BigDecimal {
+        self.net_capital_gain(realizations).net
+    }
+
+    fn formats(&self) -> &'static [Format] {
+        &[Format::ATO]
+    }
+
+    fn write_report(
+        &self,
+        output: &mut dyn Write,
+        _year: u16,
+        realizations: &[Realization],
+        denomination: &Symbol,
+        _format: &Format,
+    ) -> Result<()> {
+        let mut writer = csv::Writer::from_writer(output);
+
+        writer.write_record(&[
+            "Asset",
+            "Date acquired",
+            "Date disposed",
+            "Capital proceeds",
+            "Cost base",
+            "Capital gain or (loss)",
+            "Discount method",
+        ])?;
+
+        for realization in realizations {
+            let discount = if self.long_term(realization) {
+                "Yes"
+            } else {
+                "No"
+            };
+            writer.write_record(&[
+                realization.description.clone(),
+                realization
+                    .acquired_when
+                    .map_or("".to_string(), |d| d.format("%d/%m/%Y").to_string()),
+                realization.disposed_when.format("%d/%m/%Y").to_string(),
+                format_fiat_amount(&realization.proceeds, denomination),
+                format_fiat_amount(&realization.cost_basis, denomination),
+                format_fiat_amount(&realization.gain, denomination),
+                discount.to_string(),
+            ])?;
+        }
+
+        let summary = self.net_capital_gain(realizations);
+        for (label, amount) in [
+            ("Total current year capital gains", summary.gains),
+            ("Capital losses", summary.losses),
+            ("CGT discount", summary.discount),
+            ("Net capital gain", summary.net),
+        ] {
+            writer.write_record(&[
+                label,
+                "",
+                "",
+                "",
+                "",
+                &format_fiat_amount(&amount, denomination),
+                "",
+            ])?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use crate::symbol::BTC;
+    use crate::types::DateTime;
+
+    use super::*;
+
+    fn realization(acquired_when: DateTime, gain: i32) -> Realization {
+        Realization {
+            amount: BigDecimal::from(1),
+            description: "BTC sold via BTC-AUD pair".into(),
+            symbol: BTC,
+            acquired_when: Some(acquired_when),
+            disposed_when: Utc.ymd(2022, 3, 1).and_hms(0, 0, 0),
+            proceeds: BigDecimal::from(gain + 1000),
+            cost_basis: BigDecimal::from(1000),
+            gain: BigDecimal::from(gain),
+        }
+    }
+
+    #[test]
+    fn test_net_capital_gain() {
+        let australia = Australia {
+            method: Matching::Fifo,
+        };
+        let short = Utc.ymd(2021, 12, 1).and_hms(0, 0, 0);
+        let long = Utc.ymd(2020, 1, 1).and_hms(0, 0, 0);
+
+        // The loss is applied to the undiscounted gain first, then what's left of it to the
+        // discountable gain before it's halved
+        let realizations = vec![
+            realization(short, 300),
+            realization(long, 1000),
+            realization(short, -500),
+        ];
+        let summary = australia.net_capital_gain(&realizations);
+        assert_eq!(summary.gains, BigDecimal::from(1300));
+        assert_eq!(summary.losses, BigDecimal::from(500));
+        assert_eq!(summary.discount, BigDecimal::from(400));
+        assert_eq!(
+            australia.taxable_gain(2022, &realizations),
+            BigDecimal::from(400)
+        );
+    }
+}
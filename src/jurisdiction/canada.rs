@@ -0,0 +1,595 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use anyhow::{anyhow, Result};
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{Datelike, Duration};
+
+use crate::jurisdiction::Jurisdiction;
+use crate::portfolio::{Acquisition, Disposal, Kind, Portfolio, Provenance, Source, Step};
+use crate::report::{Format, Realization};
+use crate::symbol::Symbol;
+use crate::types::{format_fiat_amount, DateTime};
+use crate::wallet::{Lot, Wallet};
+
+// A loss is superficial if the property is bought within this many days either side of the sale
+const SUPERFICIAL_LOSS_DAYS: i64 = 30;
+
+// Canada costs every disposal at the adjusted cost base, the average cost of all identical
+// property held, and denies superficial losses, adding them to the cost of the property bought
+// back instead
+pub struct Canada {}
+
+impl Jurisdiction for Canada {
+    fn name(&self) -> &'static str {
+        "Canada"
+    }
+
+    fn realizations(&self, portfolio: &Portfolio, denomination: &Symbol) -> Vec<Realization> {
+        adjusted_cost_base(portfolio, denomination)
+            .0
+            .into_iter()
+            .map(|(realization, _)| realization)
+            .collect()
+    }
+
+    // Gifts, donations and losses are taken out of the pool at the adjusted cost base
+    fn dispositions(
+        &self,
+        portfolio: &Portfolio,
+        denomination: &Symbol,
+    ) -> Vec<(Disposal, Realization)> {
+        adjusted_cost_base(portfolio, denomination).1
+    }
+
+    // Disposals are matched against a pool of every acquisition, rather than any one of them
+    fn audit(
+        &self,
+        portfolio: &Portfolio,
+        denomination: &Symbol,
+    ) -> Vec<(Realization, Provenance)> {
+        adjusted_cost_base(portfolio, denomination).0
+    }
+
+    fn explain(
+        &self,
+        _portfolio: &Portfolio,
+        _denomination: &Symbol,
+        _id: &str,
+    ) -> Result<Vec<Step>> {
+        Err(anyhow!(
+            "Disposals are matched against a pool under Canadian rules, not any one acquisition"
+        ))
+    }
+
+    fn wallet(&self, token: &Symbol) -> Wallet {
+        Wallet::average(token)
+    }
+
+    // Capital gains are included at the same rate no matter how long an asset was held
+    fn long_term(&self, _realization: &Realization) -> bool {
+        false
+    }
+
+    fn values_exchanges(&self) -> bool {
+        true
+    }
+
+    // Half of a net capital gain is taxable
+    fn taxable_gain(&self, _year: u16, realizations: &[Realization]) -> BigDecimal {
+        let gain: BigDecimal = realizations.iter().map(|r| &r.gain).sum();
+        if gain > BigDecimal::zero() {
+            gain / BigDecimal::from(2)
+        } else {
+            BigDecimal::zero()
+        }
+    }
+
+    fn formats(&self) -> &'static [Format] {
+        &[Format::Schedule3]
+    }
+
+    fn write_report(
+        &self,
+        output: &mut dyn Write,
+        year: u16,
+        realizations: &[Realization],
+        denomination: &Symbol,
+        _format: &Format,
+    ) -> Result<()> {
+        let mut writer = csv::Writer::from_writer(output);
+
+        writer.write_record(&[
+            "Number of units",
+            "Description",
+            "Year of acquisition",
+            "Proceeds of disposition",
+            "Adjusted cost base",
+            "Outlays and expenses",
+            "Gain (or loss)",
+        ])?;
+
+        let (mut total_proceeds, mut total_cost, mut total_gain) =
+            (BigDecimal::zero(), BigDecimal::zero(), BigDecimal::zero());
+        for realization in realizations {
+            total_proceeds += &realization.proceeds;
+            total_cost += &realization.cost_basis;
+            total_gain += &realization.gain;
+
+            writer.write_record(&[
+                realization.amount.to_string(),
+                realization.description.clone(),
+                realization
+                    .acquired_when
+                    .map_or("".to_string(), |d| d.year().to_string()),
+                format_fiat_amount(&realization.proceeds, denomination),
+                format_fiat_amount(&realization.cost_basis, denomination),
+                format_fiat_amount(&BigDecimal::zero(), denomination),
+                format_fiat_amount(&realization.gain, denomination),
+            ])?;
+        }
+
+        writer.write_record(&[
+            "",
+            "Total",
+            "",
+            &format_fiat_amount(&total_proceeds, denomination),
+            &format_fiat_amount(&total_cost, denomination),
+            &format_fiat_amount(&BigDecimal::zero(), denomination),
+            &format_fiat_amount(&total_gain, denomination),
+        ])?;
+
+        let taxable = self.taxable_gain(year, realizations);
+        writer.write_record(&[
+            "",
+            "Taxable capital gains",
+            "",
+            "",
+            "",
+            "",
+            &format_fiat_amount(&taxable, denomination),
+        ])?;
+
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+// Pooled is every disposal realized at the adjusted cost base with the transaction behind each, and
+// every gift, donation and loss
+type Pooled = (Vec<(Realization, Provenance)>, Vec<(Disposal, Realization)>);
+
+// adjusted_cost_base realizes every disposal in the portfolio at the average cost of the property
+// held at the time. Gifts and donations are deemed to be disposed of at their fair market value,
+// and realized like a sale, losses realize nothing.
+fn adjusted_cost_base(portfolio: &Portfolio, denomination: &Symbol) -> Pooled {
+    let mut trades = portfolio.migrated_trades();
+    trades.sort_by_key(|(t, _)| t.when);
+
+    // Every change in holdings of each asset, to find whether it was bought back around a loss
+    let mut changes: HashMap<Symbol, Vec<(DateTime, BigDecimal)>> = HashMap::new();
+    for (trade, _) in trades.iter() {
+        if let Some((_, given, _)) = trade.kind.disposal(denomination) {
+            changes
+                .entry(given.symbol.asset())
+                .or_default()
+                .push((trade.when, -&given.amount));
+            continue;
+        }
+        let Some((offered, gained)) = trade.kind.exchange(denomination) else {
+            continue;
+        };
+        if offered.symbol != *denomination {
+            changes
+                .entry(offered.symbol.asset())
+                .or_default()
+                .push((trade.when, -&offered.amount));
+        }
+        if gained.symbol != *denomination {
+            changes
+                .entry(gained.symbol.asset())
+                .or_default()
+                .push((trade.when, gained.amount.clone()));
+        }
+    }
+
+    let mut wallets: HashMap<Symbol, Wallet> = HashMap::new();
+    // Denied losses waiting for the property to be bought back before they can be added to its cost
+    let mut denied_losses: HashMap<Symbol, BigDecimal> = HashMap::new();
+    let mut realizations = Vec::new();
+    let mut dispositions = Vec::new();
+
+    for (trade, source) in trades.iter() {
+        // Identical property is held whichever network it's on, so moving it only pays fees, which
+        // leave the cost of what's paid with what's left, or are realized for nothing if there's
+        // none left
+        if let Kind::Transfer { sent, received } = &trade.kind {
+            let fees = &sent.amount - &received.amount;
+            if fees > BigDecimal::zero() {
+                let asset = sent.symbol.asset();
+                let wallet = wallets
+                    .entry(asset)
+                    .or_insert_with(|| Wallet::average(&asset));
+                let sale = wallet.sell(&fees);
+                if !wallet.add_cost(&sale.cost_basis) {
+                    let realization = Realization {
+                        amount: fees,
+                        description: format!("{} paid in fees", portfolio.name(&asset)),
+                        symbol: asset,
+                        acquired_when: sale.date_of_purchase,
+                        disposed_when: trade.when,
+                        proceeds: BigDecimal::zero(),
+                        gain: -&sale.cost_basis,
+                        cost_basis: sale.cost_basis,
+                    };
+                    let provenance = Provenance {
+                        disposal: source.clone(),
+                        acquisitions: provenance(&sale.acquisitions),
+                    };
+                    realizations.push((realization, provenance));
+                }
+            }
+            continue;
+        }
+        if let Some((disposal, given, value)) = trade.kind.disposal(denomination) {
+            let asset = given.symbol.asset();
+            let wallet = wallets
+                .entry(asset)
+                .or_insert_with(|| Wallet::average(&asset));
+            let sale = wallet.sell(&given.amount);
+            let realization = Realization {
+                amount: given.amount.clone(),
+                description: portfolio.name(&asset),
+                symbol: asset,
+                acquired_when: sale.date_of_purchase,
+                disposed_when: trade.when,
+                gain: &value.amount - &sale.cost_basis,
+                proceeds: value.amount,
+                cost_basis: sale.cost_basis,
+            };
+            let deemed = match disposal {
+                Disposal::Gift => Some("gift"),
+                Disposal::Donation => Some("donation"),
+                Disposal::Loss => None,
+            };
+            if let Some(deemed) = deemed {
+                let realized = Realization {
+                    description: format!("{} ({})", realization.description, deemed),
+                    ..realization.clone()
+                };
+                let provenance = Provenance {
+                    disposal: source.clone(),
+                    acquisitions: provenance(&sale.acquisitions),
+                };
+                realizations.push((realized, provenance));
+            }
+            dispositions.push((disposal, realization));
+            continue;
+        }
+        let Some((offered, gained)) = trade.kind.exchange(denomination) else {
+            continue;
+        };
+
+        let (offered_asset, gained_asset) = (offered.symbol.asset(), gained.symbol.asset());
+        if offered.symbol == *denomination {
+            let wallet = wallets
+                .entry(gained_asset)
+                .or_insert_with(|| Wallet::average(&gained_asset));
+            wallet.add(Lot {
+                amount: gained.amount.clone(),
+                unit_cost: &offered.amount / &gained.amount,
+                date_of_purchase: trade.when,
+                acquisitions: vec![Acquisition {
+                    source: source.clone(),
+                    when: trade.when,
+                    amount: gained.clone(),
+                    fraction: BigDecimal::from(1),
+                    cost: offered.clone(),
+                }],
+            });
+            if let Some(loss) = denied_losses.remove(&gained_asset) {
+                wallet.add_cost(&loss);
+            }
+        } else if gained.symbol == *denomination {
+            let wallet = wallets
+                .entry(offered_asset)
+                .or_insert_with(|| Wallet::average(&offered_asset));
+            let sale = wallet.sell(&offered.amount);
+            let proceeds = gained.amount.clone();
+            let mut gain = &proceeds - &sale.cost_basis;
+            let mut description = portfolio.name(&offered_asset);
+
+            if gain < BigDecimal::zero() {
+                let denied =
+                    superficial_loss(&changes[&offered_asset], trade.when, &offered.amount, &gain);
+                if !denied.is_zero() {
+                    gain += &denied;
+                    description = format!(
+                        "{} (superficial loss of {} denied)",
+                        description,
+                        format_fiat_amount(&denied, denomination)
+                    );
+                    if !wallet.add_cost(&denied) {
+                        *denied_losses
+                            .entry(offered_asset)
+                            .or_insert_with(BigDecimal::zero) += denied;
+                    }
+                }
+            }
+
+            let realization = Realization {
+                amount: offered.amount.clone(),
+                description,
+                symbol: offered_asset,
+                acquired_when: sale.date_of_purchase,
+                disposed_when: trade.when,
+                proceeds,
+                cost_basis: sale.cost_basis,
+                gain,
+            };
+            let provenance = Provenance {
+                disposal: source.clone(),
+                acquisitions: provenance(&sale.acquisitions),
+            };
+            realizations.push((realization, provenance));
+        }
+        // Exchanges that couldn't be valued were warned about as they were read, and are left out
+    }
+
+    (realizations, dispositions)
+}
+
+// provenance lists the transactions what was sold was acquired through, with the share of each sold
+fn provenance(acquisitions: &[Acquisition]) -> Vec<(Option<Source>, BigDecimal)> {
+    acquisitions
+        .iter()
+        .map(|a| (a.source.clone(), a.fraction.clone()))
+        .collect()
+}
+
+// superficial_loss is the part of a loss denied because the property was bought in the 30 days
+// either side of the sale and was still held 30 days after it
+fn superficial_loss(
+    changes: &[(DateTime, BigDecimal)],
+    when: DateTime,
+    sold: &BigDecimal,
+    loss: &BigDecimal,
+) -> BigDecimal {
+    let start = when - Duration::days(SUPERFICIAL_LOSS_DAYS);
+    let end = when + Duration::days(SUPERFICIAL_LOSS_DAYS);
+
+    let bought: BigDecimal = changes
+        .iter()
+        .filter(|(t, change)| *t >= start && *t <= end && change > &BigDecimal::zero())
+        .map(|(_, change)| change)
+        .sum();
+    let held: BigDecimal = changes
+        .iter()
+        .filter(|(t, _)| *t <= end)
+        .map(|(_, change)| change)
+        .sum();
+
+    let units = sold.clone().min(bought).min(held);
+    if units <= BigDecimal::zero() {
+        return BigDecimal::zero();
+    }
+    -loss * units / sold
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    use crate::amount::Amount;
+    use crate::portfolio::{Kind, Trade};
+    use crate::symbol::{Crypto, Fiat, BTC};
+    use crate::{btc, cad};
+
+    use super::*;
+
+    const CAD: Symbol = Symbol::Fiat(Fiat::CAD);
+
+    fn trade(portfolio: &mut Portfolio, when: DateTime, offered: Amount, gained: Amount) {
+        portfolio.add_trade(&Trade {
+            when,
+            kind: Kind::Trade { offered, gained },
+        });
+    }
+
+    #[test]
+    fn test_adjusted_cost_base() {
+        let mut portfolio = Portfolio::new();
+        let day = |m, d| Utc.ymd(2021, m, d).and_hms(0, 0, 0);
+
+        trade(&mut portfolio, day(1, 1), cad!(1000), btc!(10));
+        trade(&mut portfolio, day(2, 1), cad!(2000), btc!(10));
+        trade(&mut portfolio, day(3, 1), btc!(5), cad!(1000));
+
+        assert_eq!(
+            Canada {}.realizations(&portfolio, &CAD),
+            vec![Realization {
+                amount: BigDecimal::from(5),
+                description: "BTC".into(),
+                symbol: BTC,
+                acquired_when: Some(day(1, 1)),
+                disposed_when: day(3, 1),
+                proceeds: BigDecimal::from(1000),
+                cost_basis: BigDecimal::from(750),
+                gain: BigDecimal::from(250),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_audit() {
+        let mut portfolio = Portfolio::new();
+        let day = |m, d| Utc.ymd(2021, m, d).and_hms(0, 0, 0);
+        let source = |id: &str| Source {
+            id: id.into(),
+            provider: "coinbase".into(),
+        };
+        let trades = [
+            ("1", day(1, 1), cad!(1000), btc!(10)),
+            ("2", day(2, 1), cad!(2000), btc!(10)),
+            ("3", day(3, 1), btc!(5), cad!(1000)),
+        ];
+        for (id, when, offered, gained) in trades {
+            portfolio.add_sourced_trade(
+                &Trade {
+                    when,
+                    kind: Kind::Trade { offered, gained },
+                },
+                &source(id),
+            );
+        }
+
+        // The sale is matched against a quarter of everything held at its adjusted cost base
+        let audited = Canada {}.audit(&portfolio, &CAD);
+        assert_eq!(audited.len(), 1);
+        assert_eq!(
+            audited[0].1,
+            Provenance {
+                disposal: Some(source("3")),
+                acquisitions: vec![
+                    (Some(source("1")), "0.25".parse().unwrap()),
+                    (Some(source("2")), "0.25".parse().unwrap()),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_transfer_whole_balance_in_fees() {
+        let mut portfolio = Portfolio::new();
+        let day = |m, d| Utc.ymd(2021, m, d).and_hms(0, 0, 0);
+
+        trade(&mut portfolio, day(1, 1), cad!(1000), btc!(10));
+        portfolio.add_trade(&Trade {
+            when: day(2, 1),
+            kind: Kind::Transfer {
+                sent: btc!(10),
+                received: btc!(0),
+            },
+        });
+
+        // Nothing is left to add the cost of the fees to, so it's realized for nothing
+        assert_eq!(
+            Canada {}.realizations(&portfolio, &CAD),
+            vec![Realization {
+                amount: BigDecimal::from(10),
+                description: "BTC paid in fees".into(),
+                symbol: BTC,
+                acquired_when: Some(day(1, 1)),
+                disposed_when: day(2, 1),
+                proceeds: BigDecimal::zero(),
+                cost_basis: BigDecimal::from(1000),
+                gain: BigDecimal::from(-1000),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_gifts_donations_and_losses() {
+        let mut portfolio = Portfolio::new();
+        let day = |m, d| Utc.ymd(2021, m, d).and_hms(0, 0, 0);
+        let event =
+            |portfolio: &mut Portfolio, when, kind| portfolio.add_trade(&Trade { when, kind });
+
+        trade(&mut portfolio, day(1, 1), cad!(1000), btc!(10));
+        event(
+            &mut portfolio,
+            day(2, 1),
+            Kind::GiftReceived {
+                gained: btc!(10),
+                cost_basis: BigDecimal::from(2000),
+                acquired: day(1, 15),
+            },
+        );
+        event(
+            &mut portfolio,
+            day(3, 1),
+            Kind::Gift {
+                given: btc!(2),
+                value: BigDecimal::from(600),
+            },
+        );
+        event(
+            &mut portfolio,
+            day(4, 1),
+            Kind::Donation {
+                given: btc!(2),
+                value: BigDecimal::from(700),
+            },
+        );
+        event(&mut portfolio, day(5, 1), Kind::Loss { lost: btc!(1) });
+        trade(&mut portfolio, day(6, 1), btc!(5), cad!(1000));
+
+        // Gifts and donations are deemed to be sold at their value, each at the average cost of
+        // 150 a token, and what's left after the loss is still pooled at that cost
+        let summary = |r: &Realization| {
+            (
+                r.description.clone(),
+                r.proceeds.clone(),
+                r.cost_basis.clone(),
+                r.gain.clone(),
+            )
+        };
+        let realized = |description: &str, proceeds, cost_basis, gain| {
+            (
+                description.to_string(),
+                BigDecimal::from(proceeds),
+                BigDecimal::from(cost_basis),
+                BigDecimal::from(gain),
+            )
+        };
+        let realizations = Canada {}.realizations(&portfolio, &CAD);
+        assert_eq!(
+            realizations.iter().map(summary).collect::<Vec<_>>(),
+            vec![
+                realized("BTC (gift)", 600, 300, 300),
+                realized("BTC (donation)", 700, 300, 400),
+                realized("BTC", 1000, 750, 250),
+            ]
+        );
+
+        let dispositions = Canada {}.dispositions(&portfolio, &CAD);
+        assert_eq!(
+            dispositions
+                .iter()
+                .map(|(d, r)| (*d, summary(r)))
+                .collect::<Vec<_>>(),
+            vec![
+                (Disposal::Gift, realized("BTC", 600, 300, 300)),
+                (Disposal::Donation, realized("BTC", 700, 300, 400)),
+                (Disposal::Loss, realized("BTC", 0, 150, -150)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_superficial_loss() {
+        let mut portfolio = Portfolio::new();
+        let day = |m, d| Utc.ymd(2021, m, d).and_hms(0, 0, 0);
+
+        trade(&mut portfolio, day(1, 1), cad!(1000), btc!(10));
+        trade(&mut portfolio, day(3, 1), btc!(10), cad!(600));
+        trade(&mut portfolio, day(3, 15), cad!(300), btc!(5));
+        trade(&mut portfolio, day(6, 1), btc!(5), cad!(600));
+
+        let realizations = Canada {}.realizations(&portfolio, &CAD);
+        assert_eq!(realizations.len(), 2);
+
+        // Half the loss is denied, as half of what was sold was bought back within 30 days
+        assert_eq!(
+            realizations[0].description,
+            "BTC (superficial loss of CA$200.00 denied)"
+        );
+        assert_eq!(realizations[0].cost_basis, BigDecimal::from(1000));
+        assert_eq!(realizations[0].gain, BigDecimal::from(-200));
+
+        // and is added to the cost of what was bought back
+        assert_eq!(realizations[1].cost_basis, BigDecimal::from(500));
+        assert_eq!(realizations[1].gain, BigDecimal::from(100));
+    }
+}
@@ -0,0 +1,201 @@
+use std::io::Write;
+
+use anyhow::Result;
+use bigdecimal::{BigDecimal, Zero};
+
+use crate::jurisdiction::{held_over_a_year, Jurisdiction};
+use crate::portfolio::{Matching, Portfolio};
+use crate::report::{Format, Realization};
+use crate::symbol::Symbol;
+use crate::types::format_fiat_amount;
+
+// Germany taxes crypto as a private sale (§ 23 EStG), reported on Anlage SO, lots are matched
+// first in, first out
+pub struct Germany {}
+
+// exemption_limit is the Freigrenze for private sales, if the year's net gain is below it none of
+// it is taxed, otherwise all of it is
+fn exemption_limit(year: u16) -> BigDecimal {
+    if year >= 2024 {
+        BigDecimal::from(1000)
+    } else {
+        BigDecimal::from(600)
+    }
+}
+
+impl Jurisdiction for Germany {
+    fn name(&self) -> &'static str {
+        "Germany"
+    }
+
+    fn realizations(&self, portfolio: &Portfolio, denomination: &Symbol) -> Vec<Realization> {
+        portfolio.realizations(denomination, Matching::Fifo)
+    }
+
+    // Sales are only taxable within the speculation period of one year
+    fn long_term(&self, realization: &Realization) -> bool {
+        held_over_a_year(realization)
+    }
+
+    fn values_exchanges(&self) -> bool {
+        true
+    }
+
+    fn taxable_gain(&self, year: u16, realizations: &[Realization]) -> BigDecimal {
+        let gain: BigDecimal = realizations
+            .iter()
+            .filter(|r| !self.long_term(r))
+            .map(|r| &r.gain)
+            .sum();
+        if gain < exemption_limit(year) {
+            BigDecimal::zero()
+        } else {
+            gain
+        }
+    }
+
+    fn formats(&self) -> &'static [Format] {
+        &[Format::AnlageSO]
+    }
+
+    fn write_report(
+        &self,
+        output: &mut dyn Write,
+        year: u16,
+        realizations: &[Realization],
+        denomination: &Symbol,
+        _format: &Format,
+    ) -> Result<()> {
+        let mut writer = csv::Writer::from_writer(output);
+
+        writer.write_record(&[
+            "Wirtschaftsgut",
+            "Anschaffung",
+            "Veräußerung",
+            "Veräußerungspreis",
+            "Anschaffungskosten",
+            "Gewinn/Verlust",
+            "Haltedauer über ein Jahr",
+            "Steuerpflichtig",
+        ])?;
+
+        let (mut total_proceeds, mut total_cost, mut total_gain, mut total_taxable) = (
+            BigDecimal::zero(),
+            BigDecimal::zero(),
+            BigDecimal::zero(),
+            BigDecimal::zero(),
+        );
+        for realization in realizations {
+            let (held, taxable) = if self.long_term(realization) {
+                ("Ja", BigDecimal::zero())
+            } else {
+                ("Nein", realization.gain.clone())
+            };
+
+            total_proceeds += &realization.proceeds;
+            total_cost += &realization.cost_basis;
+            total_gain += &realization.gain;
+            total_taxable += &taxable;
+
+            writer.write_record(&[
+                realization.description.clone(),
+                realization
+                    .acquired_when
+                    .map_or("".to_string(), |d| d.format("%d.%m.%Y").to_string()),
+                realization.disposed_when.format("%d.%m.%Y").to_string(),
+                format_fiat_amount(&realization.proceeds, denomination),
+                format_fiat_amount(&realization.cost_basis, denomination),
+                format_fiat_amount(&realization.gain, denomination),
+                held.to_string(),
+                format_fiat_amount(&taxable, denomination),
+            ])?;
+        }
+
+        writer.write_record(&[
+            "Summe",
+            "",
+            "",
+            &format_fiat_amount(&total_proceeds, denomination),
+            &format_fiat_amount(&total_cost, denomination),
+            &format_fiat_amount(&total_gain, denomination),
+            "",
+            &format_fiat_amount(&total_taxable, denomination),
+        ])?;
+        writer.write_record(&[
+            "Freigrenze",
+            "",
+            "",
+            "",
+            "",
+            "",
+            "",
+            &format_fiat_amount(&exemption_limit(year), denomination),
+        ])?;
+        writer.write_record(&[
+            "Zu versteuernder Gewinn",
+            "",
+            "",
+            "",
+            "",
+            "",
+            "",
+            &format_fiat_amount(&self.taxable_gain(year, realizations), denomination),
+        ])?;
+
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use crate::symbol::BTC;
+    use crate::types::DateTime;
+
+    use super::*;
+
+    fn realization(acquired_when: DateTime, gain: i32) -> Realization {
+        Realization {
+            amount: BigDecimal::from(1),
+            description: "BTC sold via BTC-EUR pair".into(),
+            symbol: BTC,
+            acquired_when: Some(acquired_when),
+            disposed_when: Utc.ymd(2023, 6, 1).and_hms(0, 0, 0),
+            proceeds: BigDecimal::from(gain + 1000),
+            cost_basis: BigDecimal::from(1000),
+            gain: BigDecimal::from(gain),
+        }
+    }
+
+    #[test]
+    fn test_taxable_gain() {
+        let within_a_year = Utc.ymd(2023, 1, 1).and_hms(0, 0, 0);
+        let over_a_year = Utc.ymd(2022, 1, 1).and_hms(0, 0, 0);
+
+        // Gains held over a year are exempt
+        let realizations = vec![
+            realization(within_a_year, 700),
+            realization(over_a_year, 5000),
+        ];
+        assert_eq!(
+            Germany {}.taxable_gain(2023, &realizations),
+            BigDecimal::from(700)
+        );
+
+        // and the rest are exempt entirely when under the Freigrenze
+        let realizations = vec![
+            realization(within_a_year, 700),
+            realization(within_a_year, -200),
+        ];
+        assert_eq!(
+            Germany {}.taxable_gain(2023, &realizations),
+            BigDecimal::zero()
+        );
+        assert_eq!(
+            Germany {}.taxable_gain(2024, &[realization(within_a_year, 700)]),
+            BigDecimal::zero()
+        );
+    }
+}
@@ -0,0 +1,274 @@
+use std::io::Write;
+use std::ops::Range;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{Datelike, NaiveDate, TimeZone, Utc};
+
+use crate::error::ReportError;
+use crate::portfolio::{Disposal, Matching, Portfolio, Provenance, Step};
+use crate::report::{Format, Realization};
+use crate::symbol::Symbol;
+use crate::types::{Category, DateTime};
+use crate::wallet::Wallet;
+
+mod australia;
+mod canada;
+mod germany;
+mod uk;
+mod us;
+
+pub use australia::Australia;
+pub use canada::Canada;
+pub use germany::Germany;
+pub use uk::UnitedKingdom;
+pub use us::UnitedStates;
+
+// Jurisdiction is the set of tax rules that realizations are reported under
+pub trait Jurisdiction {
+    fn name(&self) -> &'static str;
+
+    // realizations matches every disposal in the portfolio against the acquisitions it disposed of
+    fn realizations(&self, portfolio: &Portfolio, denomination: &Symbol) -> Vec<Realization>;
+
+    // dispositions matches every gift, donation and loss in the portfolio against the
+    // acquisitions it disposed of
+    fn dispositions(
+        &self,
+        portfolio: &Portfolio,
+        denomination: &Symbol,
+    ) -> Vec<(Disposal, Realization)> {
+        portfolio.dispositions(denomination, Matching::Fifo)
+    }
+
+    // audit matches every disposal like realizations, along with the transactions behind each
+    fn audit(
+        &self,
+        portfolio: &Portfolio,
+        denomination: &Symbol,
+    ) -> Vec<(Realization, Provenance)> {
+        portfolio.audit(denomination, Matching::Fifo)
+    }
+
+    // shortfalls finds every disposal of more than was held at the time, the shortfall of which
+    // has no cost basis
+    fn shortfalls(&self, portfolio: &Portfolio, denomination: &Symbol) -> Vec<ReportError> {
+        portfolio.shortfalls(denomination)
+    }
+
+    // explain returns each step taken in matching the disposals made by a transaction
+    fn explain(&self, portfolio: &Portfolio, denomination: &Symbol, id: &str) -> Result<Vec<Step>> {
+        Ok(portfolio.explain(denomination, Matching::Fifo, id))
+    }
+
+    // wallet holds the lots of a token in the order they'd be disposed of, to list what's held
+    fn wallet(&self, token: &Symbol) -> Wallet {
+        Wallet::new(token)
+    }
+
+    // year_start is the day tax years begin on, unless configured otherwise
+    fn year_start(&self) -> YearStart {
+        YearStart::JANUARY_1ST
+    }
+
+    // long_term returns true if the asset was held long enough to be taxed differently
+    fn long_term(&self, realization: &Realization) -> bool;
+
+    // values_exchanges returns true if exchanging one token for another is a disposal at the
+    // value of the tokens, rather than carrying the cost of one over to the other
+    fn values_exchanges(&self) -> bool {
+        false
+    }
+
+    // taxable_gain is the part of the gains realized in the tax year that's taxed, after any
+    // exemptions, discounts or allowances, a net loss is carried to other years instead
+    fn taxable_gain(&self, _year: u16, realizations: &[Realization]) -> BigDecimal {
+        let gain: BigDecimal = realizations.iter().map(|r| &r.gain).sum();
+        gain.max(BigDecimal::zero())
+    }
+
+    // income_form names the form income of a category is reported on, if the jurisdiction has one
+    fn income_form(&self, _category: Category) -> Option<&'static str> {
+        None
+    }
+
+    // formats lists the report formats supported, the first is used unless another is requested
+    fn formats(&self) -> &'static [Format];
+
+    fn write_report(
+        &self,
+        output: &mut dyn Write,
+        year: u16,
+        realizations: &[Realization],
+        denomination: &Symbol,
+        format: &Format,
+    ) -> Result<()>;
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+pub enum Country {
+    #[serde(alias = "us", alias = "US")]
+    UnitedStates,
+    #[serde(alias = "au", alias = "AU")]
+    Australia,
+    #[serde(alias = "ca", alias = "CA")]
+    Canada,
+    #[serde(alias = "de", alias = "DE")]
+    Germany,
+    #[serde(alias = "uk", alias = "UK", alias = "gb", alias = "GB")]
+    UnitedKingdom,
+}
+
+// jurisdiction returns the tax rules of a country, matching lots by method where the country
+// allows a choice, and an error where it requires another method
+pub fn jurisdiction(country: Country, method: Matching) -> Result<Box<dyn Jurisdiction>> {
+    match country {
+        Country::UnitedStates => Ok(Box::new(UnitedStates { method })),
+        Country::Australia => Ok(Box::new(Australia { method })),
+        Country::Canada if method != Matching::Fifo => Err(anyhow!(
+            "Canada requires the average cost of identical property, lot_matching can't be {:?}",
+            method
+        )),
+        Country::Canada => Ok(Box::new(Canada {})),
+        Country::Germany if method != Matching::Fifo => Err(anyhow!(
+            "Germany requires lots to be matched first in, first out, lot_matching can't be {:?}",
+            method
+        )),
+        Country::Germany => Ok(Box::new(Germany {})),
+        Country::UnitedKingdom if method != Matching::Fifo => Err(anyhow!(
+            "The United Kingdom requires shares to be pooled, lot_matching can't be {:?}",
+            method
+        )),
+        Country::UnitedKingdom => Ok(Box::new(UnitedKingdom {})),
+    }
+}
+
+// YearStart is the month and day a tax year begins on, tax years are named by the calendar year
+// they end in, e.g. the Australian tax year 2022 runs from July 1st 2021 to June 30th 2022
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct YearStart {
+    month: u32,
+    day: u32,
+}
+
+impl YearStart {
+    pub const JANUARY_1ST: YearStart = YearStart { month: 1, day: 1 };
+
+    pub const fn new(month: u32, day: u32) -> YearStart {
+        YearStart { month, day }
+    }
+
+    // tax_year is the span of time covered by the tax year named year
+    pub fn tax_year(&self, year: u16) -> Range<DateTime> {
+        let mut end = year as i32;
+        if *self == YearStart::JANUARY_1ST {
+            end += 1;
+        }
+        Utc.ymd(end - 1, self.month, self.day).and_hms(0, 0, 0)
+            ..Utc.ymd(end, self.month, self.day).and_hms(0, 0, 0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseYearStartError {}
+
+// A YearStart is written as MM-DD, February 29th isn't allowed as it doesn't begin every year
+impl FromStr for YearStart {
+    type Err = ParseYearStartError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let date = NaiveDate::parse_from_str(&format!("2001-{}", s), "%Y-%m-%d")
+            .map_err(|_| ParseYearStartError {})?;
+        Ok(YearStart::new(date.month(), date.day()))
+    }
+}
+
+// held_over_a_year returns true if an asset was disposed of after the anniversary of its
+// acquisition, assets acquired on February 29th reach their anniversary on March 1st
+pub fn held_over_a_year(realization: &Realization) -> bool {
+    realization.acquired_when.map_or(false, |acquired| {
+        let acquired = acquired.naive_utc().date();
+        let next_year = acquired.year() + 1;
+        let anniversary = acquired
+            .with_year(next_year)
+            .unwrap_or_else(|| NaiveDate::from_ymd(next_year, 3, 1));
+        realization.disposed_when.naive_utc().date() > anniversary
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use bigdecimal::BigDecimal;
+
+    use crate::symbol::BTC;
+
+    use super::*;
+
+    fn held(acquired: DateTime, disposed: DateTime) -> Realization {
+        Realization {
+            amount: BigDecimal::from(1),
+            description: "BTC sold via BTC-USD pair".into(),
+            symbol: BTC,
+            acquired_when: Some(acquired),
+            disposed_when: disposed,
+            proceeds: BigDecimal::from(2),
+            cost_basis: BigDecimal::from(1),
+            gain: BigDecimal::from(1),
+        }
+    }
+
+    #[test]
+    fn test_jurisdiction_lot_matching() {
+        assert!(jurisdiction(Country::UnitedStates, Matching::Lifo).is_ok());
+        assert!(jurisdiction(Country::UnitedKingdom, Matching::Fifo).is_ok());
+        assert!(jurisdiction(Country::UnitedKingdom, Matching::Lifo).is_err());
+        assert!(jurisdiction(Country::Canada, Matching::Lifo).is_err());
+        assert!(jurisdiction(Country::Germany, Matching::Lifo).is_err());
+    }
+
+    #[test]
+    fn test_held_over_a_year() {
+        let acquired = Utc.ymd(2019, 3, 15).and_hms(12, 0, 0);
+        assert!(!held_over_a_year(&held(
+            acquired,
+            Utc.ymd(2020, 3, 15).and_hms(23, 0, 0)
+        )));
+        assert!(held_over_a_year(&held(
+            acquired,
+            Utc.ymd(2020, 3, 16).and_hms(0, 0, 0)
+        )));
+
+        let leap_day = Utc.ymd(2020, 2, 29).and_hms(0, 0, 0);
+        assert!(!held_over_a_year(&held(
+            leap_day,
+            Utc.ymd(2021, 3, 1).and_hms(0, 0, 0)
+        )));
+        assert!(held_over_a_year(&held(
+            leap_day,
+            Utc.ymd(2021, 3, 2).and_hms(0, 0, 0)
+        )));
+    }
+
+    #[test]
+    fn test_tax_year() {
+        let year = YearStart::JANUARY_1ST.tax_year(2020);
+        assert!(year.contains(&Utc.ymd(2020, 1, 1).and_hms(0, 0, 0)));
+        assert!(year.contains(&Utc.ymd(2020, 12, 31).and_hms(23, 59, 59)));
+        assert!(!year.contains(&Utc.ymd(2021, 1, 1).and_hms(0, 0, 0)));
+
+        let year = YearStart::new(7, 1).tax_year(2022);
+        assert!(!year.contains(&Utc.ymd(2021, 6, 30).and_hms(23, 59, 59)));
+        assert!(year.contains(&Utc.ymd(2021, 7, 1).and_hms(0, 0, 0)));
+        assert!(year.contains(&Utc.ymd(2022, 6, 30).and_hms(23, 59, 59)));
+        assert!(!year.contains(&Utc.ymd(2022, 7, 1).and_hms(0, 0, 0)));
+    }
+
+    #[test]
+    fn test_parse_year_start() {
+        assert_eq!("07-01".parse(), Ok(YearStart::new(7, 1)));
+        assert_eq!("04-06".parse(), Ok(YearStart::new(4, 6)));
+        assert_eq!("02-29".parse::<YearStart>(), Err(ParseYearStartError {}));
+        assert_eq!("13-01".parse::<YearStart>(), Err(ParseYearStartError {}));
+    }
+}
@@ -0,0 +1,699 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+use anyhow::{anyhow, Result};
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{Duration, NaiveDate, Utc};
+
+use crate::error::ReportError;
+use crate::jurisdiction::{Jurisdiction, YearStart};
+use crate::portfolio::{Disposal, Kind, Portfolio, Provenance, Source, Step};
+use crate::report::{Format, Realization};
+use crate::symbol::Symbol;
+use crate::types::{format_fiat_amount, DateTime};
+use crate::wallet::Wallet;
+
+// Acquisitions made up to this many days after a disposal are matched with it ahead of the pool
+const BED_AND_BREAKFAST_DAYS: i64 = 30;
+
+// UnitedKingdom matches disposals with acquisitions of the same asset made on the same day, then
+// with acquisitions in the following 30 days, then with the average cost of everything else held
+// (the Section 104 pool), as set out by HMRC in CRYPTO22200
+pub struct UnitedKingdom {}
+
+impl Jurisdiction for UnitedKingdom {
+    fn name(&self) -> &'static str {
+        "United Kingdom"
+    }
+
+    fn realizations(&self, portfolio: &Portfolio, denomination: &Symbol) -> Vec<Realization> {
+        share_pooling(portfolio, denomination)
+            .0
+            .into_iter()
+            .map(|(realization, _)| realization)
+            .collect()
+    }
+
+    // Gifts, donations and losses are taken out of the pool at its average cost
+    fn dispositions(
+        &self,
+        portfolio: &Portfolio,
+        denomination: &Symbol,
+    ) -> Vec<(Disposal, Realization)> {
+        share_pooling(portfolio, denomination).1
+    }
+
+    // Disposals are matched against a pool of every acquisition, rather than any one of them
+    fn audit(
+        &self,
+        portfolio: &Portfolio,
+        denomination: &Symbol,
+    ) -> Vec<(Realization, Provenance)> {
+        share_pooling(portfolio, denomination).0
+    }
+
+    // Only disposals of more than is left in the pool have no cost basis, as those made before
+    // the asset was acquired are matched with its acquisition by the bed and breakfast rule
+    fn shortfalls(&self, portfolio: &Portfolio, denomination: &Symbol) -> Vec<ReportError> {
+        share_pooling(portfolio, denomination).2
+    }
+
+    fn explain(
+        &self,
+        _portfolio: &Portfolio,
+        _denomination: &Symbol,
+        _id: &str,
+    ) -> Result<Vec<Step>> {
+        Err(anyhow!(
+            "Disposals are matched against a pool under UK rules, not any one acquisition"
+        ))
+    }
+
+    fn wallet(&self, token: &Symbol) -> Wallet {
+        Wallet::average(token)
+    }
+
+    fn year_start(&self) -> YearStart {
+        YearStart::new(4, 6)
+    }
+
+    // Gains are taxed the same no matter how long an asset was held
+    fn long_term(&self, _realization: &Realization) -> bool {
+        false
+    }
+
+    fn values_exchanges(&self) -> bool {
+        true
+    }
+
+    fn formats(&self) -> &'static [Format] {
+        &[Format::HMRC, Format::SA108]
+    }
+
+    fn write_report(
+        &self,
+        output: &mut dyn Write,
+        _year: u16,
+        realizations: &[Realization],
+        denomination: &Symbol,
+        format: &Format,
+    ) -> Result<()> {
+        let mut writer = csv::Writer::from_writer(output);
+
+        match format {
+            Format::SA108 => {
+                // Each asset disposed of on a day is a single disposal, no matter how many
+                // acquisitions it was matched with
+                let mut disposals: BTreeMap<(DateTime, String), BigDecimal> = BTreeMap::new();
+                let (mut proceeds, mut costs) = (BigDecimal::zero(), BigDecimal::zero());
+                for realization in realizations {
+                    proceeds += &realization.proceeds;
+                    costs += &realization.cost_basis;
+                    *disposals
+                        .entry((realization.disposed_when, realization.symbol.symbol()))
+                        .or_insert_with(BigDecimal::zero) += &realization.gain;
+                }
+
+                let (mut gains, mut losses) = (BigDecimal::zero(), BigDecimal::zero());
+                for gain in disposals.values() {
+                    if gain < &BigDecimal::zero() {
+                        losses -= gain;
+                    } else {
+                        gains += gain;
+                    }
+                }
+
+                writer.write_record(&["Summary", "Amount"])?;
+                writer.write_record(&["Number of disposals", &disposals.len().to_string()])?;
+                writer.write_record(&[
+                    "Disposal proceeds",
+                    &format_fiat_amount(&proceeds, denomination),
+                ])?;
+                writer.write_record(&[
+                    "Allowable costs",
+                    &format_fiat_amount(&costs, denomination),
+                ])?;
+                writer.write_record(&[
+                    "Gains in the year, before losses",
+                    &format_fiat_amount(&gains, denomination),
+                ])?;
+                writer.write_record(&[
+                    "Losses in the year",
+                    &format_fiat_amount(&losses, denomination),
+                ])?;
+            }
+            _ => {
+                writer.write_record(&[
+                    "Asset",
+                    "Date of disposal",
+                    "Date of acquisition",
+                    "Amount",
+                    "Disposal proceeds",
+                    "Allowable cost",
+                    "Gain or (loss)",
+                ])?;
+
+                for realization in realizations {
+                    writer.write_record(&[
+                        realization.description.clone(),
+                        realization.disposed_when.format("%d/%m/%Y").to_string(),
+                        realization
+                            .acquired_when
+                            .map_or("Section 104 pool".to_string(), |d| {
+                                d.format("%d/%m/%Y").to_string()
+                            }),
+                        realization.amount.to_string(),
+                        format_fiat_amount(&realization.proceeds, denomination),
+                        format_fiat_amount(&realization.cost_basis, denomination),
+                        format_fiat_amount(&realization.gain, denomination),
+                    ])?;
+                }
+            }
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+// Day totals everything acquired and disposed of for an asset on one day, reduced as each is
+// matched, and whatever was paid in fees to move it, which leaves the pool without taking any of
+// its cost. The day's disposals are a single disposal, audited by the first transaction of them,
+// and its acquisitions are audited by what's left of each. What was given away or lost that day is
+// taken out of the pool separately.
+#[derive(Debug, Default)]
+struct Day {
+    acquired: BigDecimal,
+    cost: BigDecimal,
+    acquisitions: Acquisitions,
+    disposed: BigDecimal,
+    proceeds: BigDecimal,
+    fees: BigDecimal,
+    source: Option<Source>,
+    given: Vec<(Disposal, BigDecimal, BigDecimal, Option<Source>)>,
+}
+
+// Acquisitions are the transactions something was acquired through, with the fraction of each
+// that's left to match, or that was matched
+type Acquisitions = Vec<(Option<Source>, BigDecimal)>;
+
+// Pooled is every disposal of an asset matched under the UK rules with the transactions behind
+// each, every gift, donation and loss of it, and every disposal of more than was left in the pool
+type Pooled = (
+    Vec<(Realization, Provenance)>,
+    Vec<(Disposal, Realization)>,
+    Vec<ReportError>,
+);
+
+// share_pooling matches the disposals of every asset in the portfolio under the UK rules
+fn share_pooling(portfolio: &Portfolio, denomination: &Symbol) -> Pooled {
+    let mut assets: HashMap<Symbol, BTreeMap<NaiveDate, Day>> = HashMap::new();
+
+    for (trade, source) in portfolio.migrated_trades() {
+        let date = trade.when.naive_utc().date();
+        // Tokens are pooled whichever network they're held on, so moving them only pays fees
+        if let Kind::Transfer { sent, received } = &trade.kind {
+            let day = assets
+                .entry(sent.symbol.asset())
+                .or_default()
+                .entry(date)
+                .or_default();
+            day.fees += &sent.amount - &received.amount;
+            continue;
+        }
+        if let Some((disposal, given, value)) = trade.kind.disposal(denomination) {
+            let day = assets
+                .entry(given.symbol.asset())
+                .or_default()
+                .entry(date)
+                .or_default();
+            day.given
+                .push((disposal, given.amount, value.amount, source));
+            continue;
+        }
+        let Some((offered, gained)) = trade.kind.exchange(denomination) else {
+            continue;
+        };
+        if offered.symbol == *denomination {
+            let day = assets
+                .entry(gained.symbol.asset())
+                .or_default()
+                .entry(date)
+                .or_default();
+            day.acquired += &gained.amount;
+            day.cost += &offered.amount;
+            day.acquisitions.push((source, BigDecimal::from(1)));
+        } else if gained.symbol == *denomination {
+            let day = assets
+                .entry(offered.symbol.asset())
+                .or_default()
+                .entry(date)
+                .or_default();
+            day.disposed += &offered.amount;
+            day.proceeds += &gained.amount;
+            if day.source.is_none() {
+                day.source = source;
+            }
+        }
+        // Exchanges that couldn't be valued were warned about as they were read, and are left out
+    }
+
+    let (mut realizations, mut dispositions, mut shortfalls) = (Vec::new(), Vec::new(), Vec::new());
+    for (symbol, days) in assets {
+        let description = portfolio.name(&symbol);
+        let (realized, disposed, oversold) = match_disposals(&description, symbol, days);
+        realizations.extend(realized);
+        dispositions.extend(disposed);
+        shortfalls.extend(oversold);
+    }
+    // Matches are made by rule, so a stable sort keeps the rules in order for each disposal
+    realizations.sort_by_key(|(r, _)| (r.disposed_when, r.symbol.symbol()));
+    dispositions.sort_by_key(|(_, r)| (r.disposed_when, r.symbol.symbol()));
+    shortfalls.sort_by_key(|e| match e {
+        ReportError::InsufficientLots { when, symbol, .. } => Some((*when, symbol.symbol())),
+        _ => None,
+    });
+    (realizations, dispositions, shortfalls)
+}
+
+fn match_disposals(description: &str, symbol: Symbol, days: BTreeMap<NaiveDate, Day>) -> Pooled {
+    let mut days = days.into_iter().collect::<Vec<_>>();
+    let mut realizations = Vec::new();
+    let mut dispositions = Vec::new();
+    let mut shortfalls = Vec::new();
+    let realization = |disposed: &NaiveDate,
+                       acquired: Option<&NaiveDate>,
+                       rule: &str,
+                       amount: BigDecimal,
+                       proceeds: BigDecimal,
+                       cost_basis: BigDecimal,
+                       source: &Option<Source>,
+                       acquisitions: Acquisitions| {
+        let realization = Realization {
+            amount,
+            description: format!("{} ({})", description, rule),
+            symbol,
+            acquired_when: acquired.map(start_of),
+            disposed_when: start_of(disposed),
+            gain: &proceeds - &cost_basis,
+            proceeds,
+            cost_basis,
+        };
+        let provenance = Provenance {
+            disposal: source.clone(),
+            acquisitions,
+        };
+        (realization, provenance)
+    };
+
+    // Same day rule
+    for (date, day) in days.iter_mut() {
+        let units = day.disposed.clone().min(day.acquired.clone());
+        if units.is_zero() {
+            continue;
+        }
+        let acquisitions = take_acquisitions(&mut day.acquisitions, &units, &day.acquired);
+        let cost = take(&mut day.acquired, &mut day.cost, &units);
+        let proceeds = take(&mut day.disposed, &mut day.proceeds, &units);
+        realizations.push(realization(
+            date,
+            Some(date),
+            "same day",
+            units,
+            proceeds,
+            cost,
+            &day.source,
+            acquisitions,
+        ));
+    }
+
+    // Bed and breakfast rule, earliest acquisitions first
+    for i in 0..days.len() {
+        let (disposals, acquisitions) = days.split_at_mut(i + 1);
+        let (date, disposal) = &mut disposals[i];
+        let last = *date + Duration::days(BED_AND_BREAKFAST_DAYS);
+        for (acquired, acquisition) in acquisitions.iter_mut().take_while(|(d, _)| *d <= last) {
+            let units = disposal.disposed.clone().min(acquisition.acquired.clone());
+            if units.is_zero() {
+                continue;
+            }
+            let acquisitions =
+                take_acquisitions(&mut acquisition.acquisitions, &units, &acquisition.acquired);
+            let cost = take(&mut acquisition.acquired, &mut acquisition.cost, &units);
+            let proceeds = take(&mut disposal.disposed, &mut disposal.proceeds, &units);
+            realizations.push(realization(
+                date,
+                Some(acquired),
+                "bed and breakfast",
+                units,
+                proceeds,
+                cost,
+                &disposal.source,
+                acquisitions,
+            ));
+        }
+    }
+
+    // Section 104 pool. Gifts, donations and losses leave it at its average cost, and a gift is a
+    // disposal at its market value, where donations to charity and losses realize nothing.
+    let mut pool = Pool::default();
+    for (date, day) in days.iter_mut() {
+        pool.amount += &day.acquired;
+        pool.cost += &day.cost;
+        pool.acquisitions.append(&mut day.acquisitions);
+        let fees = day.fees.clone().min(pool.amount.clone());
+        take_acquisitions(&mut pool.acquisitions, &fees, &pool.amount);
+        pool.amount -= fees;
+
+        if !day.disposed.is_zero() {
+            let units = day.disposed.clone();
+            let (cost, acquisitions, shortfall) = pool.take(&units);
+            if let Some(shortfall) = shortfall {
+                shortfalls.push(ReportError::InsufficientLots {
+                    symbol,
+                    when: start_of(date),
+                    shortfall,
+                    source: day.source.clone(),
+                });
+            }
+            realizations.push(realization(
+                date,
+                None,
+                "section 104",
+                units,
+                day.proceeds.clone(),
+                cost,
+                &day.source,
+                acquisitions,
+            ));
+        }
+
+        for (disposal, units, value, source) in day.given.drain(..) {
+            let (cost, acquisitions, shortfall) = pool.take(&units);
+            if let Some(shortfall) = shortfall {
+                shortfalls.push(ReportError::InsufficientLots {
+                    symbol,
+                    when: start_of(date),
+                    shortfall,
+                    source: source.clone(),
+                });
+            }
+            if disposal == Disposal::Gift {
+                realizations.push(realization(
+                    date,
+                    None,
+                    "gift",
+                    units.clone(),
+                    value.clone(),
+                    cost.clone(),
+                    &source,
+                    acquisitions,
+                ));
+            }
+            let given = Realization {
+                amount: units,
+                description: description.to_string(),
+                symbol,
+                acquired_when: None,
+                disposed_when: start_of(date),
+                gain: &value - &cost,
+                proceeds: value,
+                cost_basis: cost,
+            };
+            dispositions.push((disposal, given));
+        }
+    }
+
+    (realizations, dispositions, shortfalls)
+}
+
+// Pool is the Section 104 pool of an asset, the amount held at its total cost, and what's left of
+// each acquisition in it
+#[derive(Debug, Default)]
+struct Pool {
+    amount: BigDecimal,
+    cost: BigDecimal,
+    acquisitions: Acquisitions,
+}
+
+impl Pool {
+    // take removes units from the pool at its average cost, returning their cost, the share of
+    // each acquisition they were matched with, and however many more than was held were taken,
+    // which have no cost
+    fn take(&mut self, units: &BigDecimal) -> (BigDecimal, Acquisitions, Option<BigDecimal>) {
+        let pooled = units.clone().min(self.amount.clone());
+        let acquisitions = take_acquisitions(&mut self.acquisitions, &pooled, &self.amount);
+        let cost = take(&mut self.amount, &mut self.cost, &pooled);
+        let shortfall = Some(units - &pooled).filter(|shortfall| !shortfall.is_zero());
+        (cost, acquisitions, shortfall)
+    }
+}
+
+// take_acquisitions takes the same share of each acquisition as units are of the amount they add
+// up to, returning the fraction of each acquisition's transaction taken
+fn take_acquisitions(
+    acquisitions: &mut Acquisitions,
+    units: &BigDecimal,
+    amount: &BigDecimal,
+) -> Acquisitions {
+    if units.is_zero() {
+        return Vec::new();
+    }
+    let taken = if units == amount {
+        std::mem::take(acquisitions)
+    } else {
+        acquisitions
+            .iter_mut()
+            .map(|(source, left)| {
+                let fraction = &*left * units / amount;
+                *left -= &fraction;
+                (source.clone(), fraction)
+            })
+            .collect()
+    };
+    acquisitions.retain(|(_, left)| !left.is_zero());
+    taken
+}
+
+// take removes units from a total amount, returning their share of the total value
+fn take(amount: &mut BigDecimal, value: &mut BigDecimal, units: &BigDecimal) -> BigDecimal {
+    let share = if units == amount {
+        value.clone()
+    } else {
+        &*value * units / &*amount
+    };
+    *amount -= units;
+    *value -= &share;
+    share
+}
+
+fn start_of(date: &NaiveDate) -> DateTime {
+    DateTime::from_utc(date.and_hms(0, 0, 0), Utc)
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+    use pretty_assertions::assert_eq;
+
+    use crate::amount::Amount;
+    use crate::portfolio::{Kind, Trade};
+    use crate::symbol::{Crypto, Fiat, BTC};
+    use crate::{btc, gbp};
+
+    use super::*;
+
+    const GBP: Symbol = Symbol::Fiat(Fiat::GBP);
+
+    fn trade(portfolio: &mut Portfolio, when: DateTime, offered: Amount, gained: Amount) {
+        portfolio.add_trade(&Trade {
+            when,
+            kind: Kind::Trade { offered, gained },
+        });
+    }
+
+    #[test]
+    fn test_share_pooling() {
+        let mut portfolio = Portfolio::new();
+        let day = |m, d| Utc.ymd(2021, m, d).and_hms(12, 0, 0);
+
+        trade(&mut portfolio, day(1, 1), gbp!(1000), btc!(100));
+        trade(&mut portfolio, day(3, 1), gbp!(600), btc!(50));
+        trade(&mut portfolio, day(6, 10), gbp!(150), btc!(10));
+        trade(&mut portfolio, day(6, 10), btc!(60), gbp!(1200));
+        trade(&mut portfolio, day(6, 20), gbp!(250), btc!(20));
+
+        let realization =
+            |acquired_when, rule: &str, amount, proceeds, cost_basis, gain| Realization {
+                amount: BigDecimal::from(amount),
+                description: format!("BTC ({})", rule),
+                symbol: BTC,
+                acquired_when,
+                disposed_when: Utc.ymd(2021, 6, 10).and_hms(0, 0, 0),
+                proceeds: BigDecimal::from(proceeds),
+                cost_basis: BigDecimal::from(cost_basis),
+                gain: BigDecimal::from(gain),
+            };
+        assert_eq!(
+            UnitedKingdom {}.realizations(&portfolio, &GBP),
+            vec![
+                realization(
+                    Some(Utc.ymd(2021, 6, 10).and_hms(0, 0, 0)),
+                    "same day",
+                    10,
+                    200,
+                    150,
+                    50
+                ),
+                realization(
+                    Some(Utc.ymd(2021, 6, 20).and_hms(0, 0, 0)),
+                    "bed and breakfast",
+                    20,
+                    400,
+                    250,
+                    150
+                ),
+                realization(None, "section 104", 30, 600, 320, 280),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gifts_donations_and_losses() {
+        let mut portfolio = Portfolio::new();
+        let day = |m, d| Utc.ymd(2021, m, d).and_hms(12, 0, 0);
+        let event =
+            |portfolio: &mut Portfolio, when, kind| portfolio.add_trade(&Trade { when, kind });
+
+        trade(&mut portfolio, day(1, 1), gbp!(1000), btc!(10));
+        event(
+            &mut portfolio,
+            day(2, 1),
+            Kind::GiftReceived {
+                gained: btc!(10),
+                cost_basis: BigDecimal::from(2000),
+                acquired: day(1, 15),
+            },
+        );
+        event(
+            &mut portfolio,
+            day(3, 1),
+            Kind::Gift {
+                given: btc!(2),
+                value: BigDecimal::from(600),
+            },
+        );
+        event(
+            &mut portfolio,
+            day(4, 1),
+            Kind::Donation {
+                given: btc!(2),
+                value: BigDecimal::from(700),
+            },
+        );
+        event(&mut portfolio, day(5, 1), Kind::Loss { lost: btc!(1) });
+        trade(&mut portfolio, day(6, 1), btc!(5), gbp!(1000));
+
+        let realization = |m, description: &str, amount, proceeds, cost_basis| Realization {
+            amount: BigDecimal::from(amount),
+            description: description.into(),
+            symbol: BTC,
+            acquired_when: None,
+            disposed_when: Utc.ymd(2021, m, 1).and_hms(0, 0, 0),
+            gain: BigDecimal::from(proceeds - cost_basis),
+            proceeds: BigDecimal::from(proceeds),
+            cost_basis: BigDecimal::from(cost_basis),
+        };
+
+        // Only the gift is a disposal, at its market value, but all of them leave the pool at its
+        // average cost of 150 a token
+        assert_eq!(
+            UnitedKingdom {}.realizations(&portfolio, &GBP),
+            vec![
+                realization(3, "BTC (gift)", 2, 600, 300),
+                realization(6, "BTC (section 104)", 5, 1000, 750),
+            ]
+        );
+        assert_eq!(
+            UnitedKingdom {}.dispositions(&portfolio, &GBP),
+            vec![
+                (Disposal::Gift, realization(3, "BTC", 2, 600, 300)),
+                (Disposal::Donation, realization(4, "BTC", 2, 700, 300)),
+                (Disposal::Loss, realization(5, "BTC", 1, 0, 150)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_audit() {
+        let mut portfolio = Portfolio::new();
+        let day = |m, d| Utc.ymd(2021, m, d).and_hms(12, 0, 0);
+        let source = |id: &str| Source {
+            id: id.into(),
+            provider: "coinbase".into(),
+        };
+        let sourced = |portfolio: &mut Portfolio, when, offered, gained, id| {
+            portfolio.add_sourced_trade(
+                &Trade {
+                    when,
+                    kind: Kind::Trade { offered, gained },
+                },
+                &source(id),
+            )
+        };
+
+        sourced(&mut portfolio, day(1, 1), gbp!(1000), btc!(10), "1");
+        sourced(&mut portfolio, day(3, 1), btc!(2), gbp!(400), "2");
+        sourced(&mut portfolio, day(3, 1), btc!(3), gbp!(600), "3");
+
+        sourced(&mut portfolio, day(3, 2), gbp!(100), btc!(1), "4");
+        sourced(&mut portfolio, day(3, 2), btc!(2), gbp!(400), "5");
+        sourced(&mut portfolio, day(4, 1), btc!(6), gbp!(1200), "6");
+
+        // Both sales on the day are one disposal, matched against half of what's in the pool
+        let audited = UnitedKingdom {}.audit(&portfolio, &GBP);
+        assert_eq!(audited.len(), 4);
+        assert_eq!(audited[0].0.amount, BigDecimal::from(5));
+        assert_eq!(
+            audited[0].1,
+            Provenance {
+                disposal: Some(source("2")),
+                acquisitions: vec![(Some(source("1")), "0.5".parse().unwrap())],
+            }
+        );
+
+        // A sale is matched with what was bought the same day, then the rest of it with the pool
+        assert_eq!(
+            audited[1].1.acquisitions,
+            vec![(Some(source("4")), BigDecimal::from(1))]
+        );
+        assert_eq!(
+            audited[2].1.acquisitions,
+            vec![(Some(source("1")), "0.1".parse().unwrap())]
+        );
+
+        // and selling more than is left in the pool matches all of it, leaving a shortfall
+        assert_eq!(
+            audited[3].1.acquisitions,
+            vec![(Some(source("1")), "0.4".parse().unwrap())]
+        );
+        assert_eq!(
+            UnitedKingdom {}.shortfalls(&portfolio, &GBP),
+            vec![ReportError::InsufficientLots {
+                symbol: BTC,
+                when: Utc.ymd(2021, 4, 1).and_hms(0, 0, 0),
+                shortfall: BigDecimal::from(2),
+                source: Some(source("6")),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_tax_year() {
+        let year = UnitedKingdom {}.year_start().tax_year(2022);
+        assert!(!year.contains(&Utc.ymd(2021, 4, 5).and_hms(23, 59, 59)));
+        assert!(year.contains(&Utc.ymd(2021, 4, 6).and_hms(0, 0, 0)));
+        assert!(year.contains(&Utc.ymd(2022, 4, 5).and_hms(23, 59, 59)));
+        assert!(!year.contains(&Utc.ymd(2022, 4, 6).and_hms(0, 0, 0)));
+    }
+}
@@ -0,0 +1,154 @@
+use std::io::Write;
+
+use anyhow::Result;
+use bigdecimal::{BigDecimal, Zero};
+
+use crate::jurisdiction::{held_over_a_year, Jurisdiction};
+use crate::portfolio::{Disposal, Matching, Portfolio, Provenance, Step};
+use crate::report::{Format, Realization};
+use crate::symbol::Symbol;
+use crate::types::{format_amount, format_amount_for_turbotax, format_fiat_amount, Category};
+use crate::wallet::Wallet;
+
+// UnitedStates reports realizations on Form 8949, lots may be matched by any method as long as
+// it's applied consistently
+pub struct UnitedStates {
+    pub method: Matching,
+}
+
+impl Jurisdiction for UnitedStates {
+    fn name(&self) -> &'static str {
+        "United States"
+    }
+
+    fn realizations(&self, portfolio: &Portfolio, denomination: &Symbol) -> Vec<Realization> {
+        portfolio.realizations(denomination, self.method)
+    }
+
+    fn dispositions(
+        &self,
+        portfolio: &Portfolio,
+        denomination: &Symbol,
+    ) -> Vec<(Disposal, Realization)> {
+        portfolio.dispositions(denomination, self.method)
+    }
+
+    fn audit(
+        &self,
+        portfolio: &Portfolio,
+        denomination: &Symbol,
+    ) -> Vec<(Realization, Provenance)> {
+        portfolio.audit(denomination, self.method)
+    }
+
+    fn explain(&self, portfolio: &Portfolio, denomination: &Symbol, id: &str) -> Result<Vec<Step>> {
+        Ok(portfolio.explain(denomination, self.method, id))
+    }
+
+    fn wallet(&self, token: &Symbol) -> Wallet {
+        self.method.wallet(token)
+    }
+
+    fn long_term(&self, realization: &Realization) -> bool {
+        held_over_a_year(realization)
+    }
+
+    // Mining is reported as self-employment, other income as additional income
+    fn income_form(&self, category: Category) -> Option<&'static str> {
+        match category {
+            Category::Mining => Some("Schedule C"),
+            _ if category.is_income() => Some("Schedule 1"),
+            _ => None,
+        }
+    }
+
+    fn formats(&self) -> &'static [Format] {
+        &[Format::IRS1099B, Format::TurboTax]
+    }
+
+    fn write_report(
+        &self,
+        output: &mut dyn Write,
+        _year: u16,
+        realizations: &[Realization],
+        denomination: &Symbol,
+        format: &Format,
+    ) -> Result<()> {
+        let mut writer = csv::Writer::from_writer(output);
+
+        match format {
+            Format::TurboTax => {
+                writer.write_record(&[
+                    "Amount",
+                    "Currency Name",
+                    "Purchase Date",
+                    "Date Sold",
+                    "Cost Basis",
+                    "Proceeds",
+                ])?;
+
+                for realization in realizations {
+                    writer.write_record(&[
+                        format_amount_for_turbotax(&realization.amount),
+                        realization.symbol.symbol(),
+                        realization
+                            .acquired_when
+                            .map_or("".to_string(), |d| d.format("%D %R").to_string()),
+                        realization.disposed_when.format("%D %R").to_string(),
+                        format_amount(&realization.cost_basis),
+                        format_amount(&realization.proceeds),
+                    ])?;
+                }
+            }
+            _ => {
+                writer.write_record(&[
+                    "Description of property",
+                    "Date acquired",
+                    "Date sold or disposed of",
+                    "Proceeds",
+                    "Cost basis",
+                    "Gain or (loss)",
+                    "Short-term or long-term",
+                ])?;
+
+                let (mut total_proceeds, mut total_cost, mut total_gain) =
+                    (BigDecimal::zero(), BigDecimal::zero(), BigDecimal::zero());
+                for realization in realizations {
+                    total_proceeds += &realization.proceeds;
+                    total_cost += &realization.cost_basis;
+                    total_gain += &realization.gain;
+
+                    let term = if self.long_term(realization) {
+                        "Long-term"
+                    } else {
+                        "Short-term"
+                    };
+                    writer.write_record(&[
+                        realization.description.clone(),
+                        realization
+                            .acquired_when
+                            .map_or("".to_string(), |d| d.format("%D").to_string()),
+                        realization.disposed_when.format("%D").to_string(),
+                        format_fiat_amount(&realization.proceeds, denomination),
+                        format_fiat_amount(&realization.cost_basis, denomination),
+                        format_fiat_amount(&realization.gain, denomination),
+                        term.to_string(),
+                    ])?;
+                }
+
+                writer.write_record(&[
+                    "Total",
+                    "",
+                    "",
+                    &format_fiat_amount(&total_proceeds, denomination),
+                    &format_fiat_amount(&total_cost, denomination),
+                    &format_fiat_amount(&total_gain, denomination),
+                    "",
+                ])?;
+            }
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+}
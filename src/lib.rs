@@ -0,0 +1,101 @@
+//! Tribute exports transactions from crypto exchanges and blockchains, and replays them to
+//! realize the gains and losses reported for taxes.
+//!
+//! Transactions are fetched by each [`Provider`] in a [`Registry`], such as those of the
+//! [`coinbase`], [`coinbase_pro`], [`esplora`], [`ethereum`] and [`etherscan`] modules, or any
+//! implemented by another crate, each as a list of [`Transaction`]s. Exported transactions are
+//! read into a [`Portfolio`] of [`Trade`]s with [`report::read_portfolio`], and a [`Jurisdiction`]
+//! matches its disposals against acquisitions to give each [`Realization`], which it writes as a
+//! report with [`Jurisdiction::write_report`].
+//!
+//! ```no_run
+//! use std::io;
+//!
+//! use tribute::error::Warnings;
+//! use tribute::fx::FxRates;
+//! use tribute::jurisdiction::UnitedStates;
+//! use tribute::portfolio::Matching;
+//! use tribute::report::read_portfolio;
+//! use tribute::symbol::USD;
+//! use tribute::Jurisdiction;
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let us = UnitedStates {
+//!     method: Matching::Fifo,
+//! };
+//! let mut warnings = Warnings::new(false);
+//! let portfolio = read_portfolio(io::stdin(), &USD, &[], &[], FxRates::new(), &us, &mut warnings)
+//!     .await?;
+//! for realization in us.realizations(&portfolio, &USD) {
+//!     println!("{}: {}", realization.description, realization.gain);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+extern crate bitcoin;
+extern crate coinbase_pro_rs;
+extern crate coinbase_rs;
+extern crate csv;
+extern crate futures;
+extern crate itertools;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate tempfile;
+extern crate toml;
+extern crate uuid;
+extern crate web3;
+
+/// Amounts of an asset, and macros to write them in tests
+pub mod amount;
+/// Lists disposals of more than was held
+pub mod basis_gaps;
+/// Transactions and balances of Coinbase accounts
+pub mod coinbase;
+/// Transactions and balances of Coinbase Pro accounts
+pub mod coinbase_pro;
+/// The configuration read from config.toml
+pub mod config;
+/// Errors found in the transactions a report is made from
+pub mod error;
+/// Transactions and balances of Bitcoin addresses from an Esplora API
+pub mod esplora;
+/// Transactions and balances of Ethereum accounts from a node
+pub mod ethereum;
+/// Transactions and balances of accounts from an Etherscan compatible block explorer
+pub mod etherscan;
+/// Traces how a disposal was matched against acquisitions
+pub mod explain;
+/// Exports the transactions of every provider as CSV or JSON
+pub mod export;
+/// Exchange rates between fiat currencies
+pub mod fx;
+/// Lists the lots held at a time
+pub mod holdings;
+/// Reports income received, such as staking rewards
+pub mod income;
+/// The tax rules of each country, and the reports they're written in
+pub mod jurisdiction;
+/// Trades, and the replay of them that matches disposals against acquisitions
+pub mod portfolio;
+/// Daily market prices from CoinGecko
+pub mod prices;
+/// The exchanges and blockchains transactions are exported from
+pub mod provider;
+/// Compares the balances held with those computed from transactions
+pub mod reconcile;
+/// Reads exported transactions into a portfolio, and writes reports of what was realized
+pub mod report;
+/// Fiat, crypto, on-chain and NFT assets
+pub mod symbol;
+/// Transactions as exported by providers
+pub mod types;
+/// Lots held of a single asset
+pub mod wallet;
+
+pub use crate::jurisdiction::Jurisdiction;
+pub use crate::portfolio::{Kind, Portfolio, Trade};
+pub use crate::provider::{Provider, Registry};
+pub use crate::report::Realization;
+pub use crate::types::{Balance, Category, Transaction};
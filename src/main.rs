@@ -1,39 +1,25 @@
-#![feature(is_some_with)]
-#![feature(drain_filter)]
-#![feature(async_closure)]
-
 extern crate clap;
-extern crate coinbase_pro_rs;
-extern crate coinbase_rs;
-extern crate csv;
-extern crate futures;
-extern crate itertools;
-#[macro_use]
-extern crate serde_derive;
-extern crate tempfile;
-extern crate toml;
-extern crate uuid;
-extern crate web3;
-
-mod amount;
-mod coinbase;
-mod coinbase_pro;
-mod config;
-mod ethereum;
-mod etherscan;
-mod export;
-mod portfolio;
-mod report;
-mod symbol;
-mod types;
-mod wallet;
+extern crate tribute;
 
 use std::process;
 
+use chrono::{DateTime, Duration, NaiveDate, Utc};
 use clap::{arg, Command};
 
-use crate::config::{load_config, ConfigError};
-use crate::report::Format;
+use tribute::config::{load_config, Config, ConfigError};
+use tribute::jurisdiction::Jurisdiction;
+use tribute::provider::Registry;
+use tribute::report::Format;
+use tribute::{basis_gaps, explain, export, holdings, income, reconcile, report};
+
+// jurisdiction is the configured tax rules, resolved only by the commands that apply them so that a
+// mistake in them doesn't stop transactions being exported
+fn jurisdiction(config: &Config) -> Box<dyn Jurisdiction> {
+    config.jurisdiction().unwrap_or_else(|e| {
+        eprintln!("Error in config.toml: {}", e);
+        process::exit(1);
+    })
+}
 
 #[tokio::main]
 async fn main() {
@@ -52,27 +38,228 @@ async fn main() {
         .version("1.0")
         .author("John Barker <me@j16r.net>")
         .about("Generate tax records from various crypto exchanges")
-        .subcommand(Command::new("export").about("Exports your exchange order history"))
+        .subcommand(
+            Command::new("export")
+                .arg(arg!(--format <FORMAT>).help("Sets the output format, one of: CSV, JSON, JSONL"))
+                .about("Exports your exchange order history"),
+        )
         .subcommand(
             Command::new("report")
-                .arg(arg!(--format <FORMAT>).help("Sets the output report format, one of: IRS1099B, TurboTax"))
+                .arg(arg!(--format <FORMAT>).help(
+                    "Sets the output report format, one of: IRS1099B, TurboTax, AnlageSO, HMRC, SA108, Schedule3, ATO, JSON, JSONL",
+                ))
+                .arg(arg!(--audit).help(
+                    "Lists each realization with the transactions it was matched from, rather than the report",
+                ))
+                .arg(arg!(--strict).help(
+                    "Fails on any sale of more than was held, unknown symbol, missing price or bad market",
+                ))
+                .arg(arg!(--warnings <FILE>).help(
+                    "Where to list every warning when not strict, by default warnings.csv",
+                ))
+                .arg(arg!(--input <FILE>).help("Reads the exported transactions from a file rather than stdin"))
+                .arg(arg!(--output <FILE>).help(
+                    "Writes the report to a file rather than stdout, with any {year} replaced by the tax year",
+                ))
+                .arg(
+                    arg!(--year <YEAR>)
+                        .value_parser(clap::value_parser!(u16))
+                        .conflicts_with_all(["from", "to"])
+                        .help("Reports on this tax year rather than the configured tax_year"),
+                )
+                .arg(
+                    arg!(--from <YEAR>)
+                        .value_parser(clap::value_parser!(u16))
+                        .help("Reports on each tax year from this one, writing one report per year"),
+                )
+                .arg(
+                    arg!(--to <YEAR>)
+                        .value_parser(clap::value_parser!(u16))
+                        .help("Reports on each tax year up to and including this one"),
+                )
                 .about("Create a report from your order history"),
         )
+        .subcommand(
+            Command::new("explain")
+                .arg(arg!(<ID>).help("The ID of the transaction that disposed of tokens"))
+                .about("Explain how a disposal was matched against acquisitions, step by step"),
+        )
+        .subcommand(
+            Command::new("basis-gaps")
+                .arg(arg!(--stubs).help(
+                    "Writes a manual transaction for each, to fill in with the missing acquisition",
+                ))
+                .about("List every disposal of more than was held, which has no cost basis"),
+        )
+        .subcommand(
+            Command::new("holdings")
+                .arg(
+                    arg!(--date <DATE>)
+                        .help("Lists holdings at the end of this day, by default the end of the tax year"),
+                )
+                .arg(arg!(--format <FORMAT>).help("Sets the output format, one of: table, CSV"))
+                .about("List every lot held, with its cost basis and unrealized gain"),
+        )
+        .subcommand(
+            Command::new("reconcile")
+                .about("Compare balances held with those computed from your order history"),
+        )
+        .subcommand(
+            Command::new("income")
+                .arg(
+                    arg!(--detail)
+                        .help("Lists each receipt of income, rather than totals for each month"),
+                )
+                .about("Report staking, mining, interest and rewards received as income"),
+        )
         .get_matches();
 
-    if matches.subcommand_matches("export").is_some() {
-        if let Err(err) = export::export(&config).await {
+    if let Some(subcommand) = matches.subcommand_matches("export") {
+        let format = match subcommand.get_one::<String>("format") {
+            Some(format) => format.parse().unwrap_or_else(|_| {
+                eprintln!("Invalid format {}, expected CSV, JSON or JSONL", format);
+                process::exit(1);
+            }),
+            None => export::Format::Csv,
+        };
+        if let Err(err) = export::export(&config, &Registry::from_config(&config), format).await {
             eprintln!("Error while exporting: {}", err);
             process::exit(1);
         }
     } else if let Some(subcommand) = matches.subcommand_matches("report") {
-        let format: Option<Format> = subcommand
-            .get_one::<String>("format")
-            .map(|v| v.parse().unwrap())
-            .or(config.report_format.clone());
-        if let Err(err) = report::report(config.tax_year, &config.denomination(), &format) {
+        let jurisdiction = jurisdiction(&config);
+        let format: Option<Format> = match subcommand.get_one::<String>("format") {
+            Some(format) => Some(format.parse().unwrap_or_else(|_| {
+                eprintln!(
+                    "Invalid format {}, expected IRS1099B, TurboTax, AnlageSO, HMRC, SA108, \
+                     Schedule3, ATO, JSON or JSONL",
+                    format
+                );
+                process::exit(1);
+            })),
+            None => config.report_format.clone(),
+        };
+        let year = subcommand
+            .get_one::<u16>("year")
+            .copied()
+            .unwrap_or(config.tax_year);
+        let from = subcommand.get_one::<u16>("from").copied().unwrap_or(year);
+        let to = subcommand.get_one::<u16>("to").copied().unwrap_or(year);
+        if let Err(err) = report::report(
+            from..=to,
+            subcommand
+                .get_one::<String>("input")
+                .map(|path| path.as_str()),
+            subcommand
+                .get_one::<String>("output")
+                .map(|path| path.as_str()),
+            &config.denomination(),
+            &config.migrations(),
+            &config.events(),
+            config.fx_rates(),
+            jurisdiction.as_ref(),
+            config.tax_year_start(),
+            &format,
+            subcommand.get_flag("audit"),
+            subcommand.get_flag("strict"),
+            subcommand
+                .get_one::<String>("warnings")
+                .map_or("warnings.csv", |path| path.as_str()),
+        )
+        .await
+        {
             eprintln!("Error while generating report: {}", err);
             process::exit(1);
         }
+    } else if let Some(subcommand) = matches.subcommand_matches("explain") {
+        let jurisdiction = jurisdiction(&config);
+        if let Err(err) = explain::explain(
+            subcommand.get_one::<String>("ID").unwrap(),
+            &config.denomination(),
+            &config.migrations(),
+            &config.events(),
+            config.fx_rates(),
+            jurisdiction.as_ref(),
+        )
+        .await
+        {
+            eprintln!("Error while explaining disposal: {}", err);
+            process::exit(1);
+        }
+    } else if let Some(subcommand) = matches.subcommand_matches("basis-gaps") {
+        let jurisdiction = jurisdiction(&config);
+        if let Err(err) = basis_gaps::basis_gaps(
+            &config.denomination(),
+            &config.migrations(),
+            &config.events(),
+            config.fx_rates(),
+            jurisdiction.as_ref(),
+            subcommand.get_flag("stubs"),
+        )
+        .await
+        {
+            eprintln!("Error while listing basis gaps: {}", err);
+            process::exit(1);
+        }
+    } else if let Some(subcommand) = matches.subcommand_matches("holdings") {
+        let jurisdiction = jurisdiction(&config);
+        let until = match subcommand.get_one::<String>("date") {
+            Some(date) => match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+                Ok(date) => DateTime::from_utc((date + Duration::days(1)).and_hms(0, 0, 0), Utc),
+                Err(_) => {
+                    eprintln!("Invalid date {}, expected YYYY-MM-DD", date);
+                    process::exit(1);
+                }
+            },
+            None => {
+                config
+                    .tax_year_start()
+                    .unwrap_or_else(|| jurisdiction.year_start())
+                    .tax_year(config.tax_year)
+                    .end
+            }
+        };
+        let format = match subcommand.get_one::<String>("format") {
+            Some(format) => format.parse().unwrap_or_else(|_| {
+                eprintln!("Invalid format {}, expected table or CSV", format);
+                process::exit(1);
+            }),
+            None => holdings::Format::Table,
+        };
+        if let Err(err) = holdings::holdings(
+            until,
+            &config.denomination(),
+            &config.migrations(),
+            &config.events(),
+            config.fx_rates(),
+            jurisdiction.as_ref(),
+            format,
+        )
+        .await
+        {
+            eprintln!("Error while listing holdings: {}", err);
+            process::exit(1);
+        }
+    } else if matches.subcommand_matches("reconcile").is_some() {
+        if let Err(err) = reconcile::reconcile(&config, &Registry::from_config(&config)).await {
+            eprintln!("Error while reconciling: {}", err);
+            process::exit(1);
+        }
+    } else if let Some(subcommand) = matches.subcommand_matches("income") {
+        let jurisdiction = jurisdiction(&config);
+        if let Err(err) = income::income(
+            config.tax_year,
+            &config.denomination(),
+            &config.events(),
+            config.fx_rates(),
+            jurisdiction.as_ref(),
+            config.tax_year_start(),
+            subcommand.get_flag("detail"),
+        )
+        .await
+        {
+            eprintln!("Error while generating income report: {}", err);
+            process::exit(1);
+        }
     }
 }
@@ -1,22 +1,65 @@
-use std::collections::{HashMap, VecDeque};
-use std::fmt;
+use std::collections::HashMap;
 
 use bigdecimal::{BigDecimal, Zero};
 
 use crate::amount::Amount;
+use crate::error::ReportError;
 use crate::report::Realization;
 use crate::symbol::Symbol;
 use crate::types::DateTime;
-use crate::wallet::Wallet;
+use crate::wallet::{Lot, Wallet};
 
+#[derive(Debug, Default)]
 pub struct Portfolio {
-    wallets: HashMap<Symbol, Wallet>,
     trades: Vec<Trade>,
+    migrations: Vec<Migration>,
+    names: HashMap<Symbol, String>,
+    // sources is where each trade was read from, if it was
+    sources: Vec<Option<Source>>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Kind {
-    Trade { offered: Amount, gained: Amount },
+    Trade {
+        offered: Amount,
+        gained: Amount,
+    },
+    // Gift is tokens given away, the recipient carries over their cost basis, value is what they
+    // were worth in the denomination when given
+    Gift {
+        given: Amount,
+        value: BigDecimal,
+    },
+    // GiftReceived is tokens received as a gift, which keep the cost basis, in the denomination,
+    // and the date the donor acquired them
+    GiftReceived {
+        gained: Amount,
+        cost_basis: BigDecimal,
+        acquired: DateTime,
+    },
+    // Donation is tokens given to charity, deductible at their value without realizing a gain
+    Donation {
+        given: Amount,
+        value: BigDecimal,
+    },
+    // Loss is tokens lost to theft, a hack or lost keys
+    Loss {
+        lost: Amount,
+    },
+    // Fork is tokens credited when a chain split, acquired for cost_basis in the denomination,
+    // which is their value when the fork is taxed as income and otherwise usually nothing
+    Fork {
+        gained: Amount,
+        cost_basis: BigDecimal,
+        income: bool,
+    },
+    // Transfer is tokens moved between accounts, e.g. withdrawn from an exchange to a wallet or
+    // bridged to another network, which keep their cost and date of purchase. Anything sent but
+    // not received was paid in fees, and its cost is carried over to what was received.
+    Transfer {
+        sent: Amount,
+        received: Amount,
+    },
     // StakingReward{
     //     symbol: Symbol,
     //     amount: BigDecimal,
@@ -33,293 +76,889 @@ pub struct Trade {
     pub kind: Kind,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Sale {
-    when: DateTime,
-    original_offered: Amount,
-    offered: Amount,
-    gained: Amount,
-}
+impl Kind {
+    // migrate converts every amount in the trade into whatever it was migrated to by when
+    fn migrate(&self, when: DateTime, migrations: &[Migration]) -> Kind {
+        match self {
+            Kind::Trade { offered, gained } => Kind::Trade {
+                offered: migrate_amount(offered, when, migrations),
+                gained: migrate_amount(gained, when, migrations),
+            },
+            Kind::Gift { given, value } => Kind::Gift {
+                given: migrate_amount(given, when, migrations),
+                value: value.clone(),
+            },
+            Kind::GiftReceived {
+                gained,
+                cost_basis,
+                acquired,
+            } => Kind::GiftReceived {
+                gained: migrate_amount(gained, when, migrations),
+                cost_basis: cost_basis.clone(),
+                acquired: *acquired,
+            },
+            Kind::Donation { given, value } => Kind::Donation {
+                given: migrate_amount(given, when, migrations),
+                value: value.clone(),
+            },
+            Kind::Loss { lost } => Kind::Loss {
+                lost: migrate_amount(lost, when, migrations),
+            },
+            Kind::Fork {
+                gained,
+                cost_basis,
+                income,
+            } => Kind::Fork {
+                gained: migrate_amount(gained, when, migrations),
+                cost_basis: cost_basis.clone(),
+                income: *income,
+            },
+            Kind::Transfer { sent, received } => Kind::Transfer {
+                sent: migrate_amount(sent, when, migrations),
+                received: migrate_amount(received, when, migrations),
+            },
+        }
+    }
 
-impl Portfolio {
-    pub fn new() -> Self {
-        Portfolio {
-            wallets: HashMap::new(),
-            trades: Vec::new(),
+    // exchange returns what was offered for what was gained, for trades, and for forks and gifts
+    // received, which are acquired for their cost basis
+    pub fn exchange(&self, denomination: &Symbol) -> Option<(Amount, Amount)> {
+        match self {
+            Kind::Trade { offered, gained } => Some((offered.clone(), gained.clone())),
+            Kind::Fork {
+                gained, cost_basis, ..
+            }
+            | Kind::GiftReceived {
+                gained, cost_basis, ..
+            } => Some((
+                Amount {
+                    amount: cost_basis.clone(),
+                    symbol: *denomination,
+                },
+                gained.clone(),
+            )),
+            _ => None,
         }
     }
 
-    pub fn add_trade(&mut self, trade: &Trade) {
-        match trade.kind {
+    // disposal returns why tokens were parted with other than by a trade, what was parted with
+    // and its value in the denomination, which is nothing for a loss
+    pub fn disposal(&self, denomination: &Symbol) -> Option<(Disposal, Amount, Amount)> {
+        let value = |amount: &BigDecimal| Amount {
+            amount: amount.clone(),
+            symbol: *denomination,
+        };
+        match self {
+            Kind::Gift { given, value: v } => Some((Disposal::Gift, given.clone(), value(v))),
+            Kind::Donation { given, value: v } => {
+                Some((Disposal::Donation, given.clone(), value(v)))
+            }
+            Kind::Loss { lost } => Some((Disposal::Loss, lost.clone(), value(&BigDecimal::zero()))),
+            _ => None,
+        }
+    }
+}
+
+impl Trade {
+    // via splits a trade in two at the same time, exchanging what was offered for value, then
+    // value for what was gained. Either half that exchanges one negative amount for another, as
+    // when selling for less than the gas paid, is the other way round.
+    pub fn via(&self, value: &Amount) -> (Trade, Trade) {
+        let (disposal, acquisition) = self.split(value);
+        (disposal.reversed(), acquisition.reversed())
+    }
+
+    fn split(&self, value: &Amount) -> (Trade, Trade) {
+        match self.kind {
+            Kind::Gift { .. }
+            | Kind::GiftReceived { .. }
+            | Kind::Donation { .. }
+            | Kind::Loss { .. }
+            | Kind::Fork { .. }
+            | Kind::Transfer { .. } => panic!("Only trades can be made via a value"),
             Kind::Trade {
                 ref offered,
                 ref gained,
-            } => {
-                self.buy(trade.when, offered, gained);
-                self.sell(trade.when, gained, offered);
+            } => (
+                Trade {
+                    when: self.when,
+                    kind: Kind::Trade {
+                        offered: offered.clone(),
+                        gained: value.clone(),
+                    },
+                },
+                Trade {
+                    when: self.when,
+                    kind: Kind::Trade {
+                        offered: value.clone(),
+                        gained: gained.clone(),
+                    },
+                },
+            ),
+        }
+    }
+
+    fn reversed(self) -> Trade {
+        match self.kind {
+            Kind::Trade { offered, gained }
+                if offered.amount < BigDecimal::zero() && gained.amount < BigDecimal::zero() =>
+            {
+                Trade {
+                    when: self.when,
+                    kind: Kind::Trade {
+                        offered: Amount {
+                            amount: -gained.amount,
+                            symbol: gained.symbol,
+                        },
+                        gained: Amount {
+                            amount: -offered.amount,
+                            symbol: offered.symbol,
+                        },
+                    },
+                }
             }
-        };
+            kind => Trade {
+                when: self.when,
+                kind,
+            },
+        }
+    }
+}
+
+// Migration is a token swap made by the issuer, e.g. LEND to AAVE, every token held before the
+// migration becomes ratio tokens of the new symbol
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Migration {
+    pub when: DateTime,
+    pub from: Symbol,
+    pub to: Symbol,
+    pub ratio: BigDecimal,
+}
+
+// Source is the transaction a trade was read from
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Source {
+    pub id: String,
+    pub provider: String,
+}
+
+// Provenance is where a realization came from, the disposal and every acquisition matched against
+// it, including those the tokens were exchanged through, with the fraction of each that was used
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Provenance {
+    pub disposal: Option<Source>,
+    pub acquisitions: Vec<(Option<Source>, BigDecimal)>,
+}
+
+// MatchedDisposal is a realization, with the reason for any disposal other than a sale and
+// where it came from
+type MatchedDisposal = (Option<Disposal>, Realization, Provenance);
+
+// Acquisition is a transaction that tokens were acquired through, with the amount acquired, the
+// fraction of the transaction that is and what it cost, in the denomination or in tokens that
+// were themselves acquired through an earlier transaction
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Acquisition {
+    pub source: Option<Source>,
+    pub when: DateTime,
+    pub amount: Amount,
+    pub fraction: BigDecimal,
+    pub cost: Amount,
+}
+
+impl Acquisition {
+    // scale is a share of the acquisition, what was acquired by part of it
+    pub fn scale(&self, fraction: &BigDecimal) -> Acquisition {
+        Acquisition {
+            source: self.source.clone(),
+            when: self.when,
+            amount: Amount {
+                amount: &self.amount.amount * fraction,
+                symbol: self.amount.symbol,
+            },
+            fraction: &self.fraction * fraction,
+            cost: Amount {
+                amount: &self.cost.amount * fraction,
+                symbol: self.cost.symbol,
+            },
+        }
+    }
+}
+
+// Step is one step taken in matching a disposal against the acquisitions it disposed of
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Step {
+    // Disposed is what was disposed of, and what was gained for it
+    Disposed {
+        when: DateTime,
+        offered: Amount,
+        gained: Amount,
+    },
+    // Matched is the part of an acquisition matched, with the cost carried over from it, in the
+    // denomination or in tokens that are matched next
+    Matched(Acquisition),
+    // Unmatched is an amount disposed of with no acquisition left to match it against
+    Unmatched {
+        amount: Amount,
+    },
+    Realized(Realization),
+}
+
+// Disposal is a way of parting with tokens other than selling or exchanging them, which isn't
+// realized as a gain or loss
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Disposal {
+    Gift,
+    Donation,
+    Loss,
+}
+
+// Matching is the order in which acquisitions are matched against a disposal
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Matching {
+    // first in, first out
+    #[default]
+    Fifo,
+    // last in, first out, the latest acquisition still held is matched first
+    Lifo,
+}
+
+impl Matching {
+    // wallet creates a wallet that gives up lots in this order
+    pub fn wallet(&self, token: &Symbol) -> Wallet {
+        match self {
+            Matching::Fifo => Wallet::new(token),
+            Matching::Lifo => Wallet::lifo(token),
+        }
+    }
+}
+
+// Ledger is what's left from replaying every trade, the lots still held in each wallet and every
+// disposal realized along the way, along with the steps taken in any that were explained and
+// every disposal of more than was held
+struct Ledger<'a> {
+    new_wallet: &'a dyn Fn(&Symbol) -> Wallet,
+    wallets: HashMap<Symbol, Wallet>,
+    disposals: Vec<MatchedDisposal>,
+    steps: Vec<Step>,
+    shortfalls: Vec<ReportError>,
+}
+
+impl Portfolio {
+    pub fn new() -> Self {
+        Portfolio::default()
+    }
+
+    // name_asset gives an asset a human readable name, e.g. "CryptoPunk #1234" for an NFT
+    pub fn name_asset(&mut self, symbol: Symbol, name: &str) {
+        self.names.insert(symbol, name.to_string());
+    }
+
+    pub fn name(&self, symbol: &Symbol) -> String {
+        self.names
+            .get(symbol)
+            .cloned()
+            .unwrap_or_else(|| symbol.description())
+    }
+
+    // migrated_trades returns every trade and where it was read from, with tokens traded before a
+    // migration converted into what they were migrated to
+    pub fn migrated_trades(&self) -> Vec<(Trade, Option<Source>)> {
+        self.trades
+            .iter()
+            .zip(self.sources.iter())
+            .map(|(trade, source)| {
+                let trade = Trade {
+                    when: trade.when,
+                    kind: trade.kind.migrate(trade.when, &self.migrations),
+                };
+                (trade, source.clone())
+            })
+            .collect()
+    }
+
+    pub fn add_migration(&mut self, migration: &Migration) {
+        self.migrations.push(migration.clone());
+        self.migrations.sort_by_key(|m| m.when);
+    }
+
+    pub fn add_trade(&mut self, trade: &Trade) {
+        self.add(trade, None);
+    }
+
+    // add_sourced_trade adds a trade read from a transaction, so that what's realized from it can
+    // be traced back to the transaction
+    pub fn add_sourced_trade(&mut self, trade: &Trade, source: &Source) {
+        self.add(trade, Some(source.clone()));
+    }
+
+    fn add(&mut self, trade: &Trade, source: Option<Source>) {
         self.trades.push(trade.clone());
+        self.sources.push(source);
     }
 
-    fn buy(&mut self, date: DateTime, _offered: &Amount, gained: &Amount) {
-        self.wallets
-            .entry(gained.symbol)
-            .or_insert_with(|| Wallet::new(&gained.symbol))
-            .add_lot(&gained.amount, &gained.amount, date);
+    pub fn realizations(&self, denomination: &Symbol, method: Matching) -> Vec<Realization> {
+        self.audit(denomination, method)
+            .into_iter()
+            .map(|(realization, _)| realization)
+            .collect()
     }
 
-    fn sell(&mut self, _date: DateTime, _offered: &Amount, gained: &Amount) {
-        self.wallets
-            .entry(gained.symbol)
-            .or_insert_with(|| Wallet::new(&gained.symbol))
-            .sell(&gained.amount);
+    // audit matches every disposal like realizations, along with the transactions behind each
+    pub fn audit(&self, denomination: &Symbol, method: Matching) -> Vec<(Realization, Provenance)> {
+        self.replay(denomination, &|token| method.wallet(token), None, None)
+            .disposals
+            .into_iter()
+            .filter_map(|(reason, realization, provenance)| match reason {
+                None => Some((realization, provenance)),
+                Some(_) => None,
+            })
+            .collect()
     }
 
-    pub fn realizations(&self, denomination: &Symbol) -> Vec<Realization> {
-        let (mut trades_by_gained, mut final_sales) = organize_trades(&self.trades, denomination);
-        let mut realizations: Vec<Realization> = Vec::new();
+    // dispositions matches every gift, donation and loss against the acquisitions it disposed of,
+    // the proceeds of each are the value of what was disposed of
+    pub fn dispositions(
+        &self,
+        denomination: &Symbol,
+        method: Matching,
+    ) -> Vec<(Disposal, Realization)> {
+        self.replay(denomination, &|token| method.wallet(token), None, None)
+            .disposals
+            .into_iter()
+            .filter_map(|(reason, realization, _)| reason.map(|r| (r, realization)))
+            .collect()
+    }
 
-        while let Some(trade) = final_sales.pop_front() {
-            let description = format!(
-                "{original} sold via {original}-{} pair",
-                denomination.symbol(),
-                original = trade.original_offered.symbol.symbol(),
-            );
+    // explain matches every disposal, and returns each step taken in matching those made by the
+    // transaction with the given ID
+    pub fn explain(&self, denomination: &Symbol, method: Matching, id: &str) -> Vec<Step> {
+        self.replay(denomination, &|token| method.wallet(token), None, Some(id))
+            .steps
+    }
 
-            if let Some(matching_sales) = trades_by_gained.get_mut(&trade.offered.symbol) {
-                if matching_sales.is_empty() {
-                    let realization = Realization {
-                        amount: trade.offered.amount.clone(),
-                        description: description.clone(),
-                        symbol: trade.original_offered.symbol,
-                        acquired_when: None,
-                        disposed_when: trade.when,
-                        proceeds: trade.gained.amount.clone(),
-                        cost_basis: BigDecimal::zero(),
-                        gain: trade.gained.amount.clone(),
-                    };
-                    realizations.push(realization);
-                }
+    // shortfalls finds every disposal of more than was held at the time, which doesn't depend on
+    // the order lots are matched in
+    pub fn shortfalls(&self, denomination: &Symbol) -> Vec<ReportError> {
+        self.replay(denomination, &Wallet::new, None, None)
+            .shortfalls
+    }
 
-                if let Some(matching) = matching_sales.pop_front() {
-                    if matching.gained.amount > trade.offered.amount {
-                        let divisor = &trade.offered.amount / &matching.gained.amount;
-                        let proceeds = trade.gained.amount.clone();
-                        let cost_basis = (&matching.offered.amount * &divisor).clone();
-                        let gain = &proceeds - &cost_basis;
-
-                        if &matching.offered.symbol == denomination {
-                            let realization = Realization {
-                                amount: trade.original_offered.amount.clone(),
-                                description: description.clone(),
-                                symbol: trade.original_offered.symbol,
-                                acquired_when: Some(matching.when),
-                                disposed_when: trade.when,
-                                proceeds: proceeds.clone(),
-                                cost_basis: cost_basis.clone(),
-                                gain: gain.clone(),
-                            };
-                            realizations.push(realization);
-                        } else {
-                            let sale = Sale {
-                                when: trade.when,
-                                original_offered: trade.original_offered.clone(),
-                                offered: Amount {
-                                    amount: matching.offered.amount.clone(),
-                                    symbol: matching.offered.symbol,
-                                },
-                                gained: Amount {
-                                    amount: proceeds.clone(),
-                                    symbol: matching.gained.symbol,
-                                },
-                            };
-
-                            final_sales.push_front(sale);
-                        }
+    // wallets replays every trade made before until into wallets created by new_wallet, and
+    // returns them with the lots still held in each
+    pub fn wallets(
+        &self,
+        denomination: &Symbol,
+        new_wallet: &dyn Fn(&Symbol) -> Wallet,
+        until: DateTime,
+    ) -> HashMap<Symbol, Wallet> {
+        self.replay(denomination, new_wallet, Some(until), None)
+            .wallets
+    }
 
-                        // Only part of the matching trade was accounted for, add a new trade in
-                        // with the remainder
-                        let remainder_gained =
-                            (&matching.gained.amount - &trade.offered.amount).clone();
-                        let remainder_offered = (&matching.offered.amount
-                            - &matching.offered.amount * &divisor)
-                            .clone();
-
-                        let sale = Sale {
-                            when: matching.when,
-                            original_offered: Amount {
-                                amount: (&matching.original_offered.amount * &divisor).clone(),
-                                symbol: trade.original_offered.symbol,
-                            },
-                            offered: Amount {
-                                amount: remainder_offered,
-                                symbol: matching.offered.symbol,
-                            },
-                            gained: Amount {
-                                amount: remainder_gained,
-                                symbol: matching.gained.symbol,
-                            },
-                        };
-
-                        matching_sales.push_front(sale);
-                    } else {
-                        let divisor = &matching.gained.amount / &trade.offered.amount;
-                        let proceeds = (&trade.gained.amount * &divisor).clone();
-                        let cost_basis = &matching.offered.amount;
-                        let gain = &proceeds - cost_basis;
-
-                        if &matching.offered.symbol == denomination {
-                            let realization = Realization {
-                                amount: (&trade.original_offered.amount * &divisor).clone(),
-                                description: description.clone(),
-                                symbol: trade.original_offered.symbol,
-                                acquired_when: Some(matching.when),
-                                disposed_when: trade.when,
-                                proceeds: proceeds.clone(),
-                                cost_basis: cost_basis.clone(),
-                                gain: gain.clone(),
-                            };
-                            realizations.push(realization);
-                        } else {
-                            let sale = Sale {
-                                when: trade.when,
-                                original_offered: Amount {
-                                    amount: (&trade.original_offered.amount * &divisor).clone(),
-                                    symbol: trade.original_offered.symbol,
-                                },
-                                offered: Amount {
-                                    amount: matching.offered.amount.clone(),
-                                    symbol: matching.offered.symbol,
-                                },
-                                gained: Amount {
-                                    amount: proceeds.clone(),
-                                    symbol: matching.gained.symbol,
-                                },
-                            };
-
-                            final_sales.push_front(sale);
-                        }
+    // replay runs through every trade in the order they were made, each acquisition adding a lot
+    // at its cost in the denomination, and each disposal taking lots away and realizing them. A
+    // token exchanged for another carries the cost and date of each lot over to what it was
+    // exchanged for.
+    fn replay<'a>(
+        &self,
+        denomination: &Symbol,
+        new_wallet: &'a dyn Fn(&Symbol) -> Wallet,
+        until: Option<DateTime>,
+        explain: Option<&str>,
+    ) -> Ledger<'a> {
+        let mut trades = self
+            .trades
+            .iter()
+            .zip(self.sources.iter())
+            .collect::<Vec<_>>();
+        trades.sort_by_key(|(trade, _)| trade.when);
+
+        let mut ledger = Ledger {
+            new_wallet,
+            wallets: HashMap::new(),
+            disposals: Vec::new(),
+            steps: Vec::new(),
+            shortfalls: Vec::new(),
+        };
+        let mut migrations = self.migrations.iter().peekable();
 
-                        let remainder_gained = (&trade.gained.amount - &proceeds).clone();
-                        let remainder_offered =
-                            (&trade.offered.amount - &trade.offered.amount * &divisor).clone();
-
-                        if !remainder_gained.is_zero() {
-                            let sale = Sale {
-                                when: trade.when,
-                                original_offered: Amount {
-                                    amount: (&trade.original_offered.amount * &divisor).clone(),
-                                    symbol: trade.original_offered.symbol,
-                                },
-                                offered: Amount {
-                                    amount: remainder_offered,
-                                    symbol: trade.offered.symbol,
-                                },
-                                gained: Amount {
-                                    amount: remainder_gained,
-                                    symbol: trade.gained.symbol,
-                                },
-                            };
-
-                            final_sales.push_front(sale);
-                        }
-                    }
+        for (trade, source) in trades {
+            if until.is_some_and(|until| trade.when >= until) {
+                break;
+            }
+            while let Some(migration) = migrations.next_if(|m| m.when <= trade.when) {
+                ledger.migrate(migration);
+            }
+
+            let traced = explain.is_some() && source.as_ref().map(|s| s.id.as_str()) == explain;
+            let in_denomination = |amount: &BigDecimal| Amount {
+                amount: amount.clone(),
+                symbol: *denomination,
+            };
+            // Gifts, donations and losses dispose of tokens for their value, gifts received are
+            // acquired at the donor's cost on the day they acquired them, forks for their basis,
+            // and transfers carry lots over as they are
+            let (offered, gained, reason) = match trade.kind {
+                Kind::Trade {
+                    ref offered,
+                    ref gained,
+                } => (offered.clone(), gained.clone(), None),
+                Kind::Gift {
+                    ref given,
+                    ref value,
+                } => (given.clone(), in_denomination(value), Some(Disposal::Gift)),
+                Kind::Donation {
+                    ref given,
+                    ref value,
+                } => (
+                    given.clone(),
+                    in_denomination(value),
+                    Some(Disposal::Donation),
+                ),
+                Kind::Loss { ref lost } => (
+                    lost.clone(),
+                    in_denomination(&BigDecimal::zero()),
+                    Some(Disposal::Loss),
+                ),
+                Kind::GiftReceived {
+                    ref gained,
+                    ref cost_basis,
+                    acquired,
+                } => {
+                    ledger.acquire(gained, &in_denomination(cost_basis), acquired, source);
+                    continue;
                 }
-            } else {
-                let realization = Realization {
-                    amount: trade.offered.amount,
-                    description: description.clone(),
-                    symbol: trade.original_offered.symbol,
-                    acquired_when: None,
-                    disposed_when: trade.when,
-                    proceeds: trade.gained.amount.clone(),
-                    cost_basis: BigDecimal::zero(),
-                    gain: trade.gained.amount.clone(),
+                Kind::Fork {
+                    ref gained,
+                    ref cost_basis,
+                    ..
+                } => {
+                    ledger.acquire(gained, &in_denomination(cost_basis), trade.when, source);
+                    continue;
+                }
+                Kind::Transfer {
+                    ref sent,
+                    ref received,
+                } => {
+                    let description = format!("{} paid in fees", self.name(&sent.symbol));
+                    ledger.transfer(trade.when, sent, received, source, &description);
+                    continue;
+                }
+            };
+
+            if offered.symbol == *denomination {
+                if gained.symbol != *denomination {
+                    ledger.acquire(&gained, &offered, trade.when, source);
+                }
+            } else if gained.symbol == *denomination {
+                let description = match offered.symbol {
+                    _ if reason.is_some() => self.name(&offered.symbol),
+                    Symbol::Nft(_) => self.name(&offered.symbol),
+                    symbol => format!(
+                        "{} sold via {}-{} pair",
+                        self.name(&symbol),
+                        symbol.symbol(),
+                        denomination.symbol(),
+                    ),
                 };
-                realizations.push(realization);
+                ledger.dispose(
+                    trade.when,
+                    &offered,
+                    &gained,
+                    reason,
+                    source,
+                    &description,
+                    traced,
+                );
+            } else {
+                ledger.exchange(trade.when, &offered, &gained, source, traced);
             }
         }
 
-        realizations
+        for migration in migrations.filter(|m| until.is_none_or(|until| m.when < until)) {
+            ledger.migrate(migration);
+        }
+
+        ledger
     }
 }
 
-fn organize_trades(
-    trades: &Vec<Trade>,
-    denomination: &Symbol,
-) -> (HashMap<Symbol, VecDeque<Sale>>, VecDeque<Sale>) {
-    let mut trades_by_gained: HashMap<Symbol, VecDeque<Sale>> = HashMap::new();
-    let mut final_sales: VecDeque<Sale> = VecDeque::new();
+impl<'a> Ledger<'a> {
+    fn wallet(&mut self, symbol: &Symbol) -> &mut Wallet {
+        self.wallets
+            .entry(*symbol)
+            .or_insert_with(|| (self.new_wallet)(symbol))
+    }
+
+    // migrate moves every lot of the token migrated from into the token it was migrated to
+    fn migrate(&mut self, migration: &Migration) {
+        if let Some(mut from) = self.wallets.remove(&migration.from) {
+            from.migrate(self.wallet(&migration.to), &migration.ratio);
+            self.wallets.insert(migration.from, from);
+        }
+    }
+
+    // acquire adds a lot of what was gained, at the cost of what was offered for it in the
+    // denomination
+    fn acquire(&mut self, gained: &Amount, cost: &Amount, when: DateTime, source: &Option<Source>) {
+        if gained.amount.is_zero() {
+            return;
+        }
+        self.wallet(&gained.symbol).add(Lot {
+            amount: gained.amount.clone(),
+            unit_cost: &cost.amount / &gained.amount,
+            date_of_purchase: when,
+            acquisitions: vec![Acquisition {
+                source: source.clone(),
+                when,
+                amount: gained.clone(),
+                fraction: BigDecimal::from(1),
+                cost: cost.clone(),
+            }],
+        });
+    }
+
+    // dispose takes lots of what was offered away, realizing each for its share of what was
+    // gained in the denomination
+    #[allow(clippy::too_many_arguments)]
+    fn dispose(
+        &mut self,
+        when: DateTime,
+        offered: &Amount,
+        gained: &Amount,
+        reason: Option<Disposal>,
+        source: &Option<Source>,
+        description: &str,
+        traced: bool,
+    ) {
+        if traced {
+            self.steps.push(Step::Disposed {
+                when,
+                offered: offered.clone(),
+                gained: gained.clone(),
+            });
+        }
 
-    // Organize all trades by what was obtained
-    for trade in trades.iter() {
-        let Trade {
+        let (lots, unmatched) = self.wallet(&offered.symbol).take(&offered.amount);
+        self.realize(
             when,
-            kind: Kind::Trade {
-                gained, offered, ..
-            },
-            ..
-        } = trade;
-        let sale = Sale {
-            when: *when,
-            original_offered: offered.clone(),
-            offered: offered.clone(),
-            gained: gained.clone(),
-        };
-        if &gained.symbol == denomination {
-            final_sales.push_back(sale);
-        } else {
-            trades_by_gained
-                .entry(gained.symbol)
-                .or_insert_with(VecDeque::new)
-                .push_back(sale);
+            offered,
+            gained,
+            lots,
+            reason,
+            source,
+            description,
+            traced,
+        );
+
+        // Whatever there were no lots left for has no cost basis
+        if !unmatched.is_zero() {
+            self.shortfall(offered, &unmatched, when, source);
+        }
+        if !unmatched.is_zero() || (offered.amount.is_zero() && !gained.amount.is_zero()) {
+            let proceeds = share(&gained.amount, &unmatched, &offered.amount);
+            let realization = Realization {
+                amount: unmatched.clone(),
+                description: description.to_string(),
+                symbol: offered.symbol,
+                acquired_when: None,
+                disposed_when: when,
+                gain: proceeds.clone(),
+                proceeds,
+                cost_basis: BigDecimal::zero(),
+            };
+            if traced {
+                self.steps.push(Step::Unmatched {
+                    amount: Amount {
+                        amount: unmatched,
+                        symbol: offered.symbol,
+                    },
+                });
+                self.steps.push(Step::Realized(realization.clone()));
+            }
+            let provenance = Provenance {
+                disposal: source.clone(),
+                acquisitions: Vec::new(),
+            };
+            self.disposals.push((reason, realization, provenance));
         }
     }
 
-    (trades_by_gained, final_sales)
-}
+    // realize realizes each lot taken from what was offered for its share of what was gained in
+    // the denomination
+    #[allow(clippy::too_many_arguments)]
+    fn realize(
+        &mut self,
+        when: DateTime,
+        offered: &Amount,
+        gained: &Amount,
+        lots: Vec<Lot>,
+        reason: Option<Disposal>,
+        source: &Option<Source>,
+        description: &str,
+        traced: bool,
+    ) {
+        for lot in lots {
+            let proceeds = share(&gained.amount, &lot.amount, &offered.amount);
+            let cost_basis = lot.cost_basis();
+            let realization = Realization {
+                amount: lot.amount.clone(),
+                description: description.to_string(),
+                symbol: offered.symbol,
+                acquired_when: Some(lot.date_of_purchase),
+                disposed_when: when,
+                gain: &proceeds - &cost_basis,
+                proceeds,
+                cost_basis,
+            };
+            if traced {
+                self.steps
+                    .extend(lot.acquisitions.iter().cloned().map(Step::Matched));
+                self.steps.push(Step::Realized(realization.clone()));
+            }
+            let provenance = Provenance {
+                disposal: source.clone(),
+                acquisitions: lot
+                    .acquisitions
+                    .into_iter()
+                    .map(|a| (a.source, a.fraction))
+                    .collect(),
+            };
+            self.disposals.push((reason, realization, provenance));
+        }
+    }
+
+    fn shortfall(
+        &mut self,
+        offered: &Amount,
+        unmatched: &BigDecimal,
+        when: DateTime,
+        source: &Option<Source>,
+    ) {
+        self.shortfalls.push(ReportError::InsufficientLots {
+            symbol: offered.symbol,
+            when,
+            shortfall: unmatched.clone(),
+            source: source.clone(),
+        });
+    }
+
+    // exchange takes lots of what was offered away, and adds lots of what was gained for each
+    // with its cost basis and date of purchase, so nothing is realized until it's disposed of
+    fn exchange(
+        &mut self,
+        when: DateTime,
+        offered: &Amount,
+        gained: &Amount,
+        source: &Option<Source>,
+        traced: bool,
+    ) {
+        if traced {
+            self.steps.push(Step::Disposed {
+                when,
+                offered: offered.clone(),
+                gained: gained.clone(),
+            });
+        }
+
+        let (lots, unmatched) = self.wallet(&offered.symbol).take(&offered.amount);
+        let mut carried = lots
+            .into_iter()
+            .map(|lot| {
+                if traced {
+                    self.steps
+                        .extend(lot.acquisitions.iter().cloned().map(Step::Matched));
+                }
+                (
+                    Amount {
+                        amount: lot.amount.clone(),
+                        symbol: offered.symbol,
+                    },
+                    lot.cost_basis(),
+                    lot.date_of_purchase,
+                    lot.acquisitions,
+                )
+            })
+            .collect::<Vec<_>>();
+        // Whatever there were no lots left for is acquired with no cost basis at the exchange
+        if !unmatched.is_zero() {
+            self.shortfall(offered, &unmatched, when, source);
+            let amount = Amount {
+                amount: unmatched,
+                symbol: offered.symbol,
+            };
+            if traced {
+                self.steps.push(Step::Unmatched {
+                    amount: amount.clone(),
+                });
+            }
+            carried.push((amount, BigDecimal::zero(), when, Vec::new()));
+        }
+
+        for (exchanged, cost_basis, date_of_purchase, mut acquisitions) in carried {
+            let amount = share(&gained.amount, &exchanged.amount, &offered.amount);
+            if amount.is_zero() {
+                continue;
+            }
+            acquisitions.insert(
+                0,
+                Acquisition {
+                    source: source.clone(),
+                    when,
+                    fraction: share(&BigDecimal::from(1), &amount, &gained.amount),
+                    amount: Amount {
+                        amount: amount.clone(),
+                        symbol: gained.symbol,
+                    },
+                    cost: exchanged,
+                },
+            );
+            self.wallet(&gained.symbol).add(Lot {
+                unit_cost: cost_basis / &amount,
+                amount,
+                date_of_purchase,
+                acquisitions,
+            });
+        }
+    }
+
+    // transfer takes lots of what was sent away, and adds them to what was received with the same
+    // cost basis and date of purchase. When nothing was received it was all paid in fees, and the
+    // cost of the lots is added to what's left of the token, or realized for nothing if there's
+    // none left.
+    fn transfer(
+        &mut self,
+        when: DateTime,
+        sent: &Amount,
+        received: &Amount,
+        source: &Option<Source>,
+        description: &str,
+    ) {
+        let (lots, unmatched) = self.wallet(&sent.symbol).take(&sent.amount);
+        if !unmatched.is_zero() {
+            self.shortfall(sent, &unmatched, when, source);
+        }
+        if received.amount.is_zero() {
+            let cost: BigDecimal = lots.iter().map(|lot| lot.cost_basis()).sum();
+            if !self.wallet(&sent.symbol).add_cost(&cost) {
+                let nothing = Amount {
+                    amount: BigDecimal::zero(),
+                    symbol: sent.symbol,
+                };
+                self.realize(when, sent, &nothing, lots, None, source, description, false);
+            }
+            return;
+        }
 
-impl fmt::Debug for Portfolio {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for (currency, wallet) in self.wallets.iter() {
-            writeln!(
-                f,
-                "Wallet {:} {:} tokens remain worth ${:} ({:.2}/{:.2})",
-                currency,
-                wallet.count(),
-                wallet.cost_basis(),
-                wallet.cumulative_bought,
-                wallet.cumulative_sold
-            )?;
+        for lot in lots {
+            let amount = share(&received.amount, &lot.amount, &sent.amount);
+            self.wallet(&received.symbol).add(Lot {
+                unit_cost: lot.cost_basis() / &amount,
+                amount,
+                date_of_purchase: lot.date_of_purchase,
+                acquisitions: lot.acquisitions,
+            });
         }
-        Ok(())
+        // Whatever there were no lots left for is received with no cost basis
+        if !unmatched.is_zero() {
+            self.wallet(&received.symbol).add(Lot {
+                amount: share(&received.amount, &unmatched, &sent.amount),
+                unit_cost: BigDecimal::zero(),
+                date_of_purchase: when,
+                acquisitions: Vec::new(),
+            });
+        }
+    }
+}
+
+// share is part's share of a total, as a fraction of whole, or all of it if whole is nothing
+fn share(total: &BigDecimal, part: &BigDecimal, whole: &BigDecimal) -> BigDecimal {
+    if whole.is_zero() {
+        total.clone()
+    } else {
+        total * part / whole
     }
 }
 
+// migrate_amount converts an amount held at the given date into whatever it was migrated to
+fn migrate_amount(amount: &Amount, when: DateTime, migrations: &[Migration]) -> Amount {
+    migrations
+        .iter()
+        .filter(|m| when < m.when)
+        .fold(amount.clone(), |amount, m| {
+            if amount.symbol == m.from {
+                Amount {
+                    amount: &amount.amount * &m.ratio,
+                    symbol: m.to,
+                }
+            } else {
+                amount
+            }
+        })
+}
+
 #[cfg(test)]
 mod test {
     use bigdecimal::FromPrimitive;
     use chrono::offset::TimeZone;
     use chrono::Utc;
     use pretty_assertions::assert_eq;
+    use proptest::prelude::*;
 
-    use crate::symbol::{Crypto, Fiat, Symbol, BTC, USD, USDT};
+    use crate::symbol::{Chain, Crypto, Fiat, Symbol, BTC, USD, USDT};
     use crate::{btc, eth, usd, usdt};
 
     use super::*;
 
     #[test]
-    fn test_organize_trades() {
-        let mut trades: Vec<Trade> = Vec::new();
-        trades.push(Trade {
+    fn test_trade_via_negative_value() {
+        let when = Utc.ymd(2022, 1, 1).and_hms(0, 0, 0);
+        let trade = Trade {
+            when,
+            kind: Kind::Trade {
+                offered: btc!(1),
+                gained: Amount {
+                    amount: "-0.01".parse().unwrap(),
+                    symbol: Symbol::Crypto(Crypto::ETH),
+                },
+            },
+        };
+        let value = Amount {
+            amount: "-20".parse().unwrap(),
+            symbol: USD,
+        };
+
+        let (disposal, acquisition) = trade.via(&value);
+        assert_eq!(
+            disposal.kind,
+            Kind::Trade {
+                offered: btc!(1),
+                gained: value.clone(),
+            }
+        );
+        assert_eq!(
+            acquisition.kind,
+            Kind::Trade {
+                offered: eth!(0.01),
+                gained: usd!(20),
+            }
+        );
+    }
+
+    #[test]
+    fn test_replay_organizes_trades() {
+        let mut portfolio = Portfolio::new();
+
+        portfolio.add_trade(&Trade {
             when: Utc.ymd(2020, 1, 3).and_hms(0, 0, 0),
             kind: Kind::Trade {
                 offered: usd!(300),
                 gained: btc!(1),
             },
         });
-        trades.push(Trade {
+        portfolio.add_trade(&Trade {
             when: Utc.ymd(2020, 1, 2).and_hms(0, 0, 0),
             kind: Kind::Trade {
                 offered: btc!(1),
@@ -327,26 +966,40 @@ mod test {
             },
         });
 
-        let (rest, to_usd) = organize_trades(&trades, &USD);
-
-        assert_eq!(rest.len(), 1);
+        // The sale to USD is replayed first, before the BTC bought the day after
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
         assert_eq!(
-            rest.get(&BTC),
-            Some(&VecDeque::from(vec![Sale {
-                when: Utc.ymd(2020, 1, 3).and_hms(0, 0, 0),
-                original_offered: usd!(300),
-                offered: usd!(300),
-                gained: btc!(1),
-            }]))
+            realizations,
+            vec![Realization {
+                amount: BigDecimal::from(1),
+                symbol: BTC,
+                description: "BTC sold via BTC-USD pair".into(),
+                acquired_when: None,
+                disposed_when: Utc.ymd(2020, 1, 2).and_hms(0, 0, 0),
+                proceeds: BigDecimal::from(57000),
+                cost_basis: BigDecimal::zero(),
+                gain: BigDecimal::from(57000),
+            },]
         );
         assert_eq!(
-            to_usd[0],
-            Sale {
+            portfolio.shortfalls(&USD),
+            vec![ReportError::InsufficientLots {
+                symbol: BTC,
                 when: Utc.ymd(2020, 1, 2).and_hms(0, 0, 0),
-                original_offered: btc!(1),
-                offered: btc!(1),
-                gained: usd!(57000),
-            }
+                shortfall: BigDecimal::from(1),
+                source: None,
+            }]
+        );
+
+        // The BTC bought is left over as a lot, at what it was bought for
+        let wallets = portfolio.wallets(&USD, &Wallet::new, Utc.ymd(2021, 1, 1).and_hms(0, 0, 0));
+        let lots = wallets[&BTC].lots();
+        assert_eq!(lots.len(), 1);
+        assert_eq!(lots[0].amount, BigDecimal::from(1));
+        assert_eq!(lots[0].cost_basis(), BigDecimal::from(300));
+        assert_eq!(
+            lots[0].date_of_purchase,
+            Utc.ymd(2020, 1, 3).and_hms(0, 0, 0)
         );
     }
 
@@ -369,7 +1022,7 @@ mod test {
             },
         });
 
-        let realizations = portfolio.realizations(&USD);
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
         assert_eq!(
             realizations,
             vec![Realization {
@@ -404,7 +1057,7 @@ mod test {
             },
         });
 
-        let realizations = portfolio.realizations(&USD);
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
         assert_eq!(
             realizations,
             vec![Realization {
@@ -439,7 +1092,7 @@ mod test {
             },
         });
 
-        let realizations = portfolio.realizations(&USD);
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
         assert_eq!(
             realizations,
             vec![Realization {
@@ -481,7 +1134,7 @@ mod test {
             },
         });
 
-        let realizations = portfolio.realizations(&USD);
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
         assert_eq!(
             realizations,
             vec![
@@ -535,7 +1188,7 @@ mod test {
             },
         });
 
-        let realizations = portfolio.realizations(&USD);
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
         assert_eq!(
             realizations,
             vec![
@@ -582,7 +1235,7 @@ mod test {
             },
         });
 
-        let realizations = portfolio.realizations(&USD);
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
         assert_eq!(
             realizations,
             vec![
@@ -636,7 +1289,7 @@ mod test {
             },
         });
 
-        let realizations = portfolio.realizations(&USD);
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
         assert_eq!(
             realizations,
             vec![Realization {
@@ -678,7 +1331,7 @@ mod test {
             },
         });
 
-        let realizations = portfolio.realizations(&USD);
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
         assert_eq!(
             realizations,
             vec![Realization {
@@ -720,7 +1373,7 @@ mod test {
             },
         });
 
-        let realizations = portfolio.realizations(&USD);
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
         assert_eq!(
             realizations,
             vec![Realization {
@@ -730,8 +1383,8 @@ mod test {
                 acquired_when: Some(Utc.ymd(2017, 1, 1).and_hms(0, 0, 0)),
                 disposed_when: Utc.ymd(2020, 1, 1).and_hms(0, 0, 0),
                 proceeds: BigDecimal::from_f32(2000.).unwrap(),
-                cost_basis: BigDecimal::from_f32(500.).unwrap(),
-                gain: BigDecimal::from_f32(1500.).unwrap(),
+                cost_basis: BigDecimal::from_f32(250.).unwrap(),
+                gain: BigDecimal::from_f32(1750.).unwrap(),
             },]
         );
     }
@@ -777,7 +1430,7 @@ mod test {
             },
         });
 
-        let realizations = portfolio.realizations(&USD);
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
         assert_eq!(
             realizations,
             vec![
@@ -827,7 +1480,7 @@ mod test {
             },
         });
 
-        let realizations = portfolio.realizations(&USD);
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
         assert_eq!(
             realizations,
             vec![Realization {
@@ -891,7 +1544,7 @@ mod test {
             },
         });
 
-        let realizations = portfolio.realizations(&USD);
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
         assert_eq!(
             realizations,
             vec![
@@ -906,7 +1559,7 @@ mod test {
                     gain: BigDecimal::from_f32(2400.).unwrap(),
                 },
                 Realization {
-                    amount: "0.0625".parse().unwrap(),
+                    amount: "0.0375".parse().unwrap(),
                     description: "BTC sold via BTC-USD pair".into(),
                     symbol: BTC,
                     acquired_when: Some(Utc.ymd(2016, 1, 2).and_hms(0, 0, 0)),
@@ -918,4 +1571,800 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn test_portfolio_sell_after_migration() {
+        let mut portfolio = Portfolio::new();
+
+        let lend = Symbol::Crypto(Crypto::LEND);
+        let aave = Symbol::Crypto(Crypto::AAVE);
+
+        portfolio.add_migration(&Migration {
+            when: Utc.ymd(2020, 10, 2).and_hms(0, 0, 0),
+            from: lend,
+            to: aave,
+            ratio: "0.01".parse().unwrap(),
+        });
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2019, 1, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: usd!(250),
+                gained: Amount {
+                    amount: "500".parse().unwrap(),
+                    symbol: lend,
+                },
+            },
+        });
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2021, 1, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: Amount {
+                    amount: "5".parse().unwrap(),
+                    symbol: aave,
+                },
+                gained: usd!(1000),
+            },
+        });
+
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
+        assert_eq!(
+            realizations,
+            vec![Realization {
+                amount: "5".parse().unwrap(),
+                symbol: aave,
+                description: "AAVE sold via AAVE-USD pair".into(),
+                acquired_when: Some(Utc.ymd(2019, 1, 1).and_hms(0, 0, 0)),
+                disposed_when: Utc.ymd(2021, 1, 1).and_hms(0, 0, 0),
+                proceeds: BigDecimal::from_f32(1000.).unwrap(),
+                cost_basis: BigDecimal::from_f32(250.).unwrap(),
+                gain: BigDecimal::from_f32(750.).unwrap(),
+            },]
+        );
+    }
+
+    #[test]
+    fn test_portfolio_sell_before_migration() {
+        let mut portfolio = Portfolio::new();
+
+        let lend = Symbol::Crypto(Crypto::LEND);
+
+        portfolio.add_migration(&Migration {
+            when: Utc.ymd(2020, 10, 2).and_hms(0, 0, 0),
+            from: lend,
+            to: Symbol::Crypto(Crypto::AAVE),
+            ratio: "0.01".parse().unwrap(),
+        });
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2019, 1, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: usd!(250),
+                gained: Amount {
+                    amount: "500".parse().unwrap(),
+                    symbol: lend,
+                },
+            },
+        });
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2020, 1, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: Amount {
+                    amount: "500".parse().unwrap(),
+                    symbol: lend,
+                },
+                gained: usd!(400),
+            },
+        });
+
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
+        assert_eq!(
+            realizations,
+            vec![Realization {
+                amount: "500".parse().unwrap(),
+                symbol: lend,
+                description: "LEND sold via LEND-USD pair".into(),
+                acquired_when: Some(Utc.ymd(2019, 1, 1).and_hms(0, 0, 0)),
+                disposed_when: Utc.ymd(2020, 1, 1).and_hms(0, 0, 0),
+                proceeds: BigDecimal::from_f32(400.).unwrap(),
+                cost_basis: BigDecimal::from_f32(250.).unwrap(),
+                gain: BigDecimal::from_f32(150.).unwrap(),
+            },]
+        );
+    }
+
+    #[test]
+    fn test_portfolio_same_token_on_different_chains() {
+        let mut portfolio = Portfolio::new();
+
+        let usdc_polygon = Symbol::OnChain(Crypto::USDC, Chain::Polygon);
+        let usdc_arbitrum = Symbol::OnChain(Crypto::USDC, Chain::Arbitrum);
+
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2022, 1, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: usd!(100),
+                gained: Amount {
+                    amount: "100".parse().unwrap(),
+                    symbol: usdc_polygon,
+                },
+            },
+        });
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2022, 2, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: usd!(99),
+                gained: Amount {
+                    amount: "100".parse().unwrap(),
+                    symbol: usdc_arbitrum,
+                },
+            },
+        });
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2022, 3, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: Amount {
+                    amount: "100".parse().unwrap(),
+                    symbol: usdc_arbitrum,
+                },
+                gained: usd!(101),
+            },
+        });
+
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
+        assert_eq!(
+            realizations,
+            vec![Realization {
+                amount: "100".parse().unwrap(),
+                symbol: usdc_arbitrum,
+                description: "USDC on Arbitrum sold via USDC-USD pair".into(),
+                acquired_when: Some(Utc.ymd(2022, 2, 1).and_hms(0, 0, 0)),
+                disposed_when: Utc.ymd(2022, 3, 1).and_hms(0, 0, 0),
+                proceeds: BigDecimal::from_f32(101.).unwrap(),
+                cost_basis: BigDecimal::from_f32(99.).unwrap(),
+                gain: BigDecimal::from_f32(2.).unwrap(),
+            },]
+        );
+    }
+
+    #[test]
+    fn test_portfolio_transfer_whole_balance_in_fees() {
+        let mut portfolio = Portfolio::new();
+
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2022, 1, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: usd!(100),
+                gained: btc!(1),
+            },
+        });
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2022, 2, 1).and_hms(0, 0, 0),
+            kind: Kind::Transfer {
+                sent: btc!(1),
+                received: btc!(0),
+            },
+        });
+
+        // Nothing is left to carry the cost of the fees, so it's realized for nothing
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
+        assert_eq!(
+            realizations,
+            vec![Realization {
+                amount: BigDecimal::from(1),
+                symbol: BTC,
+                description: "BTC paid in fees".into(),
+                acquired_when: Some(Utc.ymd(2022, 1, 1).and_hms(0, 0, 0)),
+                disposed_when: Utc.ymd(2022, 2, 1).and_hms(0, 0, 0),
+                proceeds: BigDecimal::zero(),
+                cost_basis: BigDecimal::from(100),
+                gain: BigDecimal::from(-100),
+            },]
+        );
+        let wallets = portfolio.wallets(&USD, &Wallet::new, Utc.ymd(2023, 1, 1).and_hms(0, 0, 0));
+        assert!(wallets[&BTC].count().is_zero());
+    }
+
+    #[test]
+    fn test_portfolio_sell_nft() {
+        let mut portfolio = Portfolio::new();
+
+        let punk: Symbol = "0xb47e3cd837ddf8e4c57f05d70ab865de6e193bbb#1234@ethereum"
+            .parse()
+            .unwrap();
+        let eth = Symbol::Crypto(Crypto::ETH);
+        portfolio.name_asset(punk, "CryptoPunk #1234");
+
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2021, 1, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: usd!(1000),
+                gained: eth!(1),
+            },
+        });
+        let purchase = Trade {
+            when: Utc.ymd(2021, 2, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: eth!(1),
+                gained: Amount {
+                    amount: "1".parse().unwrap(),
+                    symbol: punk,
+                },
+            },
+        };
+        let (disposal, acquisition) = purchase.via(&usd!(1500));
+        portfolio.add_trade(&disposal);
+        portfolio.add_trade(&acquisition);
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2022, 3, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: Amount {
+                    amount: "1".parse().unwrap(),
+                    symbol: punk,
+                },
+                gained: usd!(4000),
+            },
+        });
+
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
+        assert_eq!(
+            realizations,
+            vec![
+                Realization {
+                    amount: "1".parse().unwrap(),
+                    symbol: eth,
+                    description: "ETH sold via ETH-USD pair".into(),
+                    acquired_when: Some(Utc.ymd(2021, 1, 1).and_hms(0, 0, 0)),
+                    disposed_when: Utc.ymd(2021, 2, 1).and_hms(0, 0, 0),
+                    proceeds: BigDecimal::from_f32(1500.).unwrap(),
+                    cost_basis: BigDecimal::from_f32(1000.).unwrap(),
+                    gain: BigDecimal::from_f32(500.).unwrap(),
+                },
+                Realization {
+                    amount: "1".parse().unwrap(),
+                    symbol: punk,
+                    description: "CryptoPunk #1234".into(),
+                    acquired_when: Some(Utc.ymd(2021, 2, 1).and_hms(0, 0, 0)),
+                    disposed_when: Utc.ymd(2022, 3, 1).and_hms(0, 0, 0),
+                    proceeds: BigDecimal::from_f32(4000.).unwrap(),
+                    cost_basis: BigDecimal::from_f32(1500.).unwrap(),
+                    gain: BigDecimal::from_f32(2500.).unwrap(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_portfolio_sell_lifo() {
+        let mut portfolio = Portfolio::new();
+
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2020, 1, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: usd!(1000),
+                gained: btc!(1),
+            },
+        });
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2020, 2, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: usd!(2000),
+                gained: btc!(1),
+            },
+        });
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2020, 3, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: btc!(1),
+                gained: usd!(3000),
+            },
+        });
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2020, 4, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: usd!(4000),
+                gained: btc!(1),
+            },
+        });
+
+        let realizations = portfolio.realizations(&USD, Matching::Lifo);
+        assert_eq!(
+            realizations,
+            vec![Realization {
+                amount: "1".parse().unwrap(),
+                symbol: BTC,
+                description: "BTC sold via BTC-USD pair".into(),
+                acquired_when: Some(Utc.ymd(2020, 2, 1).and_hms(0, 0, 0)),
+                disposed_when: Utc.ymd(2020, 3, 1).and_hms(0, 0, 0),
+                proceeds: BigDecimal::from(3000),
+                cost_basis: BigDecimal::from(2000),
+                gain: BigDecimal::from(1000),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_portfolio_gifts_donations_and_losses() {
+        let mut portfolio = Portfolio::new();
+
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2019, 1, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: usd!(2000),
+                gained: btc!(2),
+            },
+        });
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2019, 6, 1).and_hms(0, 0, 0),
+            kind: Kind::GiftReceived {
+                gained: btc!(1),
+                cost_basis: BigDecimal::from(500),
+                acquired: Utc.ymd(2015, 1, 1).and_hms(0, 0, 0),
+            },
+        });
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2020, 2, 1).and_hms(0, 0, 0),
+            kind: Kind::Gift {
+                given: btc!(1),
+                value: BigDecimal::from(3000),
+            },
+        });
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2020, 3, 1).and_hms(0, 0, 0),
+            kind: Kind::Donation {
+                given: btc!(0.5),
+                value: BigDecimal::from(4000),
+            },
+        });
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2020, 4, 1).and_hms(0, 0, 0),
+            kind: Kind::Loss { lost: btc!(1) },
+        });
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2020, 5, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: btc!(0.5),
+                gained: usd!(5000),
+            },
+        });
+
+        let disposal = |reason, amount: &str, acquired: DateTime, disposed, proceeds, cost| {
+            (
+                reason,
+                Realization {
+                    amount: amount.parse().unwrap(),
+                    symbol: BTC,
+                    description: "BTC".into(),
+                    acquired_when: Some(acquired),
+                    disposed_when: disposed,
+                    proceeds: BigDecimal::from(proceeds),
+                    cost_basis: BigDecimal::from(cost),
+                    gain: BigDecimal::from(proceeds - cost),
+                },
+            )
+        };
+        // The gift received keeps the donor's cost and date, so it's the earliest lot held
+        assert_eq!(
+            portfolio.dispositions(&USD, Matching::Fifo),
+            vec![
+                disposal(
+                    Disposal::Gift,
+                    "1",
+                    Utc.ymd(2015, 1, 1).and_hms(0, 0, 0),
+                    Utc.ymd(2020, 2, 1).and_hms(0, 0, 0),
+                    3000,
+                    500
+                ),
+                disposal(
+                    Disposal::Donation,
+                    "0.5",
+                    Utc.ymd(2019, 1, 1).and_hms(0, 0, 0),
+                    Utc.ymd(2020, 3, 1).and_hms(0, 0, 0),
+                    4000,
+                    500
+                ),
+                disposal(
+                    Disposal::Loss,
+                    "1",
+                    Utc.ymd(2019, 1, 1).and_hms(0, 0, 0),
+                    Utc.ymd(2020, 4, 1).and_hms(0, 0, 0),
+                    0,
+                    1000
+                ),
+            ]
+        );
+
+        assert_eq!(
+            portfolio.realizations(&USD, Matching::Fifo),
+            vec![Realization {
+                amount: "0.5".parse().unwrap(),
+                symbol: BTC,
+                description: "BTC sold via BTC-USD pair".into(),
+                acquired_when: Some(Utc.ymd(2019, 1, 1).and_hms(0, 0, 0)),
+                disposed_when: Utc.ymd(2020, 5, 1).and_hms(0, 0, 0),
+                proceeds: BigDecimal::from(5000),
+                cost_basis: BigDecimal::from(500),
+                gain: BigDecimal::from(4500),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_portfolio_sell_fork() {
+        let mut portfolio = Portfolio::new();
+
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2017, 1, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: usd!(1000),
+                gained: btc!(1),
+            },
+        });
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2017, 8, 1).and_hms(0, 0, 0),
+            kind: Kind::Fork {
+                gained: Amount {
+                    amount: BigDecimal::from(1),
+                    symbol: Symbol::Crypto(Crypto::BCH),
+                },
+                cost_basis: BigDecimal::zero(),
+                income: false,
+            },
+        });
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2017, 12, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: Amount {
+                    amount: BigDecimal::from(1),
+                    symbol: Symbol::Crypto(Crypto::BCH),
+                },
+                gained: usd!(1500),
+            },
+        });
+
+        // The fork is acquired when it happened, for its basis, rather than sold from nothing
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
+        assert_eq!(
+            realizations,
+            vec![Realization {
+                amount: "1".parse().unwrap(),
+                symbol: Symbol::Crypto(Crypto::BCH),
+                description: "BCH sold via BCH-USD pair".into(),
+                acquired_when: Some(Utc.ymd(2017, 8, 1).and_hms(0, 0, 0)),
+                disposed_when: Utc.ymd(2017, 12, 1).and_hms(0, 0, 0),
+                proceeds: BigDecimal::from(1500),
+                cost_basis: BigDecimal::zero(),
+                gain: BigDecimal::from(1500),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_portfolio_audit() {
+        let mut portfolio = Portfolio::new();
+        let source = |id: &str| Source {
+            id: id.to_string(),
+            provider: "coinbase".to_string(),
+        };
+
+        portfolio.add_sourced_trade(
+            &Trade {
+                when: Utc.ymd(2020, 1, 1).and_hms(0, 0, 0),
+                kind: Kind::Trade {
+                    offered: usd!(1000),
+                    gained: btc!(2),
+                },
+            },
+            &source("a"),
+        );
+        portfolio.add_sourced_trade(
+            &Trade {
+                when: Utc.ymd(2020, 2, 1).and_hms(0, 0, 0),
+                kind: Kind::Trade {
+                    offered: btc!(1),
+                    gained: eth!(10),
+                },
+            },
+            &source("b"),
+        );
+        portfolio.add_sourced_trade(
+            &Trade {
+                when: Utc.ymd(2020, 3, 1).and_hms(0, 0, 0),
+                kind: Kind::Trade {
+                    offered: eth!(10),
+                    gained: usd!(3000),
+                },
+            },
+            &source("c"),
+        );
+        portfolio.add_sourced_trade(
+            &Trade {
+                when: Utc.ymd(2020, 4, 1).and_hms(0, 0, 0),
+                kind: Kind::Trade {
+                    offered: btc!(1),
+                    gained: usd!(1500),
+                },
+            },
+            &source("d"),
+        );
+
+        // The ETH sold is matched through the BTC it was bought with, to half of the first lot
+        let provenances = portfolio
+            .audit(&USD, Matching::Fifo)
+            .into_iter()
+            .map(|(_, provenance)| provenance)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            provenances,
+            vec![
+                Provenance {
+                    disposal: Some(source("c")),
+                    acquisitions: vec![
+                        (Some(source("b")), BigDecimal::from(1)),
+                        (Some(source("a")), "0.5".parse().unwrap()),
+                    ],
+                },
+                Provenance {
+                    disposal: Some(source("d")),
+                    acquisitions: vec![(Some(source("a")), "0.5".parse().unwrap())],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_portfolio_shortfalls() {
+        let mut portfolio = Portfolio::new();
+
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2020, 1, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: usd!(1000),
+                gained: btc!(1),
+            },
+        });
+        portfolio.add_sourced_trade(
+            &Trade {
+                when: Utc.ymd(2020, 2, 1).and_hms(0, 0, 0),
+                kind: Kind::Trade {
+                    offered: btc!(1.5),
+                    gained: eth!(15),
+                },
+            },
+            &Source {
+                id: "b".into(),
+                provider: "coinbase".into(),
+            },
+        );
+        portfolio.add_trade(&Trade {
+            when: Utc.ymd(2020, 3, 1).and_hms(0, 0, 0),
+            kind: Kind::Trade {
+                offered: eth!(20),
+                gained: usd!(4000),
+            },
+        });
+
+        assert_eq!(
+            portfolio.shortfalls(&USD),
+            vec![
+                ReportError::InsufficientLots {
+                    symbol: BTC,
+                    when: Utc.ymd(2020, 2, 1).and_hms(0, 0, 0),
+                    shortfall: "0.5".parse().unwrap(),
+                    source: Some(Source {
+                        id: "b".into(),
+                        provider: "coinbase".into(),
+                    }),
+                },
+                ReportError::InsufficientLots {
+                    symbol: Symbol::Crypto(Crypto::ETH),
+                    when: Utc.ymd(2020, 3, 1).and_hms(0, 0, 0),
+                    shortfall: BigDecimal::from(5),
+                    source: None,
+                },
+            ]
+        );
+    }
+
+    // Step is a made up trade: the days since the last one, the tokens it's in, what kind of trade
+    // it is, and numbers its amounts, values and dates are picked from
+    type Step = (i64, usize, usize, u8, u64, u64, i64);
+
+    fn steps() -> impl Strategy<Value = Vec<Step>> {
+        prop::collection::vec(
+            (
+                0..3i64,
+                0..3usize,
+                0..3usize,
+                0..12u8,
+                0..4000u64,
+                0..20u64,
+                0..365i64,
+            ),
+            1..40,
+        )
+    }
+
+    // portfolio_of makes up a portfolio from steps, buying, selling and exchanging a few tokens,
+    // disposing of them other than by sale, receiving them as gifts and forks, moving them to
+    // another network and paying all of what's sent in fees, sometimes more than is held
+    fn portfolio_of(steps: &[Step]) -> Portfolio {
+        let tokens = [BTC, Symbol::Crypto(Crypto::ETH), USDT];
+        let amount = |n: u64, symbol: Symbol| Amount {
+            amount: BigDecimal::from(n + 1) / BigDecimal::from(4),
+            symbol,
+        };
+
+        let mut portfolio = Portfolio::new();
+        let mut when = Utc.ymd(2020, 1, 1).and_hms(0, 0, 0);
+        for &(days, token, other, kind, value, units, age) in steps {
+            when += chrono::Duration::days(days);
+            let (token, other) = (tokens[token], tokens[other]);
+            let kind = match kind {
+                0..=3 => Kind::Trade {
+                    offered: amount(value, USD),
+                    gained: amount(units, token),
+                },
+                4..=5 => Kind::Trade {
+                    offered: amount(units, token),
+                    gained: amount(value, USD),
+                },
+                6 if token != other => Kind::Trade {
+                    offered: amount(units, token),
+                    gained: amount(value % 20, other),
+                },
+                6 => Kind::Loss {
+                    lost: amount(units % 8, token),
+                },
+                7 => Kind::Gift {
+                    given: amount(units % 8, token),
+                    value: BigDecimal::from(value % 1000),
+                },
+                8 => Kind::GiftReceived {
+                    gained: amount(units % 8, token),
+                    cost_basis: BigDecimal::from(value % 1000),
+                    acquired: when - chrono::Duration::days(age),
+                },
+                9 => {
+                    let sent = amount(units % 8, token);
+                    Kind::Transfer {
+                        received: Amount {
+                            amount: &sent.amount * "0.99".parse::<BigDecimal>().unwrap(),
+                            symbol: token.on_chain(Chain::Arbitrum).unwrap(),
+                        },
+                        sent,
+                    }
+                }
+                10 => Kind::Transfer {
+                    sent: amount(units % 8, token),
+                    received: Amount {
+                        amount: BigDecimal::zero(),
+                        symbol: token,
+                    },
+                },
+                _ => Kind::Fork {
+                    gained: amount(units % 8, token),
+                    cost_basis: BigDecimal::from(value % 2),
+                    income: false,
+                },
+            };
+            portfolio.add_trade(&Trade { when, kind });
+        }
+        portfolio
+    }
+
+    fn assert_close(a: &BigDecimal, b: &BigDecimal) {
+        assert!(
+            (a - b).abs() < "0.000000000000000000000001".parse().unwrap(),
+            "{} != {}",
+            a,
+            b
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_portfolio_lots_agree_with_realizations(steps in steps()) {
+            let until = Utc.ymd(2030, 1, 1).and_hms(0, 0, 0);
+            let portfolio = portfolio_of(&steps);
+
+            for method in [Matching::Fifo, Matching::Lifo] {
+                let realizations = portfolio.realizations(&USD, method);
+                let dispositions = portfolio.dispositions(&USD, method);
+                let wallets = portfolio.wallets(&USD, &|token| method.wallet(token), until);
+                let disposed = realizations
+                    .iter()
+                    .chain(dispositions.iter().map(|(_, r)| r))
+                    .collect::<Vec<_>>();
+
+                // What's held is what was acquired less what was disposed of, as far as there was
+                // anything to dispose of
+                let mut held: HashMap<Symbol, BigDecimal> = HashMap::new();
+                // and the cost of what was acquired is either realized or still held
+                let mut cost = BigDecimal::zero();
+                let mut proceeds = BigDecimal::zero();
+                for trade in portfolio.trades.iter() {
+                    let mut dispose = |given: &Amount| {
+                        let left = held.entry(given.symbol).or_insert_with(BigDecimal::zero);
+                        *left = (&*left - &given.amount).max(BigDecimal::zero());
+                    };
+                    match trade.kind {
+                        Kind::Trade {
+                            ref offered,
+                            ref gained,
+                        } => {
+                            if offered.symbol == USD {
+                                cost += &offered.amount;
+                            } else {
+                                dispose(offered);
+                            }
+                            if gained.symbol == USD {
+                                proceeds += &gained.amount;
+                            } else {
+                                *held.entry(gained.symbol).or_insert_with(BigDecimal::zero) +=
+                                    &gained.amount;
+                            }
+                        }
+                        Kind::Gift {
+                            ref given,
+                            ref value,
+                        }
+                        | Kind::Donation {
+                            ref given,
+                            ref value,
+                        } => {
+                            dispose(given);
+                            proceeds += value;
+                        }
+                        Kind::Loss { ref lost } => dispose(lost),
+                        Kind::GiftReceived {
+                            ref gained,
+                            ref cost_basis,
+                            ..
+                        }
+                        | Kind::Fork {
+                            ref gained,
+                            ref cost_basis,
+                            ..
+                        } => {
+                            cost += cost_basis;
+                            *held.entry(gained.symbol).or_insert_with(BigDecimal::zero) +=
+                                &gained.amount;
+                        }
+                        Kind::Transfer {
+                            ref sent,
+                            ref received,
+                        } => {
+                            dispose(sent);
+                            *held.entry(received.symbol).or_insert_with(BigDecimal::zero) +=
+                                &received.amount;
+                        }
+                    }
+                }
+
+                for (symbol, amount) in held.iter() {
+                    let count = wallets
+                        .get(symbol)
+                        .map_or(BigDecimal::zero(), |wallet| wallet.count());
+                    assert_close(&count, amount);
+                }
+
+                let realized_cost: BigDecimal = disposed.iter().map(|r| &r.cost_basis).sum();
+                let held_cost: BigDecimal = wallets.values().map(|w| w.cost_basis()).sum();
+                assert_close(&(realized_cost + held_cost), &cost);
+
+                let realized_proceeds: BigDecimal = disposed.iter().map(|r| &r.proceeds).sum();
+                assert_close(&realized_proceeds, &proceeds);
+
+                // Nothing is ever matched against an acquisition made after it was disposed of
+                for realization in disposed.iter() {
+                    assert_eq!(
+                        realization.gain,
+                        &realization.proceeds - &realization.cost_basis
+                    );
+                    if let Some(acquired_when) = realization.acquired_when {
+                        assert!(acquired_when <= realization.disposed_when);
+                    }
+                }
+            }
+        }
+    }
 }
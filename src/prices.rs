@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::Duration;
+
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+
+use crate::symbol::{Crypto, Symbol};
+use crate::types::DateTime;
+
+const COINGECKO_URL: &str = "https://api.coingecko.com/api/v3";
+
+// Prices looks up the daily price of a token in the denomination, caching every day fetched
+pub struct Prices {
+    client: reqwest::Client,
+    denomination: Symbol,
+    cache: HashMap<(Crypto, NaiveDate), BigDecimal>,
+}
+
+impl Prices {
+    pub fn new(denomination: Symbol) -> Prices {
+        Prices {
+            client: reqwest::Client::new(),
+            denomination,
+            cache: HashMap::new(),
+        }
+    }
+
+    pub fn denomination(&self) -> Symbol {
+        self.denomination
+    }
+
+    // price_at returns the price of one token in the denomination on the day of when
+    pub async fn price_at(
+        &mut self,
+        token: Crypto,
+        when: DateTime,
+    ) -> Result<BigDecimal, Box<dyn Error>> {
+        let day = when.naive_utc().date();
+        if let Some(price) = self.cache.get(&(token, day)) {
+            return Ok(price.clone());
+        }
+
+        let id = coingecko_id(token).ok_or(format!("No price source for {}", token))?;
+        let url = format!(
+            "{}/coins/{}/history?date={}&localization=false",
+            COINGECKO_URL,
+            id,
+            day.format("%d-%m-%Y")
+        );
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+
+        let history = self
+            .client
+            .get(&url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<History>()
+            .await?;
+        let price = history
+            .market_data
+            .and_then(|m| m.price(self.denomination))
+            .ok_or(format!("No price for {} on {}", token, day))?;
+
+        self.cache.insert((token, day), price.clone());
+        Ok(price)
+    }
+}
+
+fn coingecko_id(token: Crypto) -> Option<&'static str> {
+    match token {
+        Crypto::AVAX => Some("avalanche-2"),
+        Crypto::BNB => Some("binancecoin"),
+        Crypto::BTC => Some("bitcoin"),
+        Crypto::ETH => Some("ethereum"),
+        Crypto::MATIC => Some("matic-network"),
+        Crypto::WAVAX => Some("wrapped-avax"),
+        Crypto::WBNB => Some("wbnb"),
+        Crypto::WETH => Some("weth"),
+        Crypto::WMATIC => Some("wmatic"),
+        _ => None,
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct History {
+    market_data: Option<MarketData>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MarketData {
+    current_price: HashMap<String, serde_json::Number>,
+}
+
+impl MarketData {
+    // price reads the price in the denomination as the decimal written, rather than the binary
+    // expansion of the nearest float
+    fn price(&self, denomination: Symbol) -> Option<BigDecimal> {
+        let price = self
+            .current_price
+            .get(&denomination.symbol().to_lowercase())?;
+        price.to_string().parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::symbol::USD;
+
+    #[test]
+    fn test_market_data_price() {
+        let history: History =
+            serde_json::from_str(r#"{"market_data":{"current_price":{"usd":1234.56}}}"#).unwrap();
+        let price = history.market_data.unwrap().price(USD);
+        assert_eq!(price, Some("1234.56".parse().unwrap()));
+    }
+}
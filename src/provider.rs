@@ -0,0 +1,207 @@
+use anyhow::Result;
+use futures::future::LocalBoxFuture;
+
+use crate::coinbase::Coinbase;
+use crate::coinbase_pro::CoinbasePro;
+use crate::config::{Config, Exchange};
+use crate::esplora::{self, Esplora};
+use crate::ethereum::Ethereum;
+use crate::etherscan::Etherscan;
+use crate::symbol::Chain;
+use crate::types::{Balance, DateTime, Transaction};
+
+// Provider is an exchange or blockchain that transactions are exported from, and whose balances
+// are reconciled against them. Other crates can implement it for providers of their own, and add
+// them to a Registry.
+pub trait Provider {
+    // name is what the provider's transactions are recorded as from, e.g. coinbase
+    fn name(&self) -> &'static str;
+
+    // transactions fetches every transaction made after since, or all of them without one
+    fn transactions(&self, since: Option<DateTime>)
+        -> LocalBoxFuture<'_, Result<Vec<Transaction>>>;
+
+    // balances fetches what's held now
+    fn balances(&self) -> LocalBoxFuture<'_, Result<Vec<Balance>>>;
+}
+
+// Registry is every provider transactions are exported from
+#[derive(Default)]
+pub struct Registry {
+    providers: Vec<Box<dyn Provider>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    // from_config registers a provider for every configured exchange, those that fetch the
+    // transactions of accounts are left out when none are configured
+    pub fn from_config(config: &Config) -> Self {
+        let mut registry = Registry::new();
+
+        for exchange in &config.exchanges {
+            match exchange {
+                Exchange::CoinbasePro {
+                    ref key,
+                    ref secret,
+                    ref passphrase,
+                } => registry.register(Box::new(CoinbasePro {
+                    key: key.clone(),
+                    secret: secret.clone(),
+                    passphrase: passphrase.clone(),
+                    denomination: config.denomination(),
+                })),
+                Exchange::Coinbase {
+                    ref key,
+                    ref secret,
+                } => registry.register(Box::new(Coinbase {
+                    key: key.clone(),
+                    secret: secret.clone(),
+                })),
+                Exchange::Ethereum { ref url } => match config.accounts {
+                    Some(ref accounts) => registry.register(Box::new(Ethereum {
+                        url: url.clone(),
+                        accounts: accounts.clone(),
+                    })),
+                    None => eprintln!("Specified ethereum configuration with no accounts"),
+                },
+                Exchange::Etherscan {
+                    ref key,
+                    ref chain,
+                    ref url,
+                } => match config.accounts {
+                    Some(ref accounts) => {
+                        let chain = chain.unwrap_or(Chain::Ethereum);
+                        registry.register(Box::new(Etherscan {
+                            key: key.clone(),
+                            chain,
+                            url: url
+                                .clone()
+                                .unwrap_or_else(|| chain.explorer_url().to_string()),
+                            accounts: accounts.clone(),
+                            denomination: config.denomination(),
+                        }))
+                    }
+                    None => eprintln!("Specified etherscan configuration with no accounts"),
+                },
+                Exchange::Esplora {
+                    ref url,
+                    ref addresses,
+                    ref xpubs,
+                    network,
+                } => {
+                    let network = network.unwrap_or(bitcoin::Network::Bitcoin);
+                    registry.register(Box::new(Esplora {
+                        url: url
+                            .clone()
+                            .unwrap_or_else(|| esplora::url(network).to_string()),
+                        addresses: addresses.clone().unwrap_or_default(),
+                        xpubs: xpubs.clone().unwrap_or_default(),
+                        network,
+                        denomination: config.denomination(),
+                    }))
+                }
+            }
+        }
+
+        registry
+    }
+
+    pub fn register(&mut self, provider: Box<dyn Provider>) {
+        self.providers.push(provider);
+    }
+
+    pub fn providers(&self) -> &[Box<dyn Provider>] {
+        &self.providers
+    }
+}
+
+// after keeps the transactions made after since, for providers that can only fetch them all
+pub fn after(transactions: Vec<Transaction>, since: Option<DateTime>) -> Vec<Transaction> {
+    match since {
+        Some(since) => transactions
+            .into_iter()
+            .filter(|t| t.created_at.is_some_and(|created_at| created_at > since))
+            .collect(),
+        None => transactions,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+    use futures::executor::block_on;
+    use futures::FutureExt;
+    use pretty_assertions::assert_eq;
+
+    use crate::types::Category;
+
+    use super::*;
+
+    // Manual provides a fixed list of transactions, as another crate might
+    struct Manual {
+        transactions: Vec<Transaction>,
+    }
+
+    impl Provider for Manual {
+        fn name(&self) -> &'static str {
+            "manual"
+        }
+
+        fn transactions(
+            &self,
+            since: Option<DateTime>,
+        ) -> LocalBoxFuture<'_, Result<Vec<Transaction>>> {
+            async move { Ok(after(self.transactions.clone(), since)) }.boxed_local()
+        }
+
+        fn balances(&self) -> LocalBoxFuture<'_, Result<Vec<Balance>>> {
+            async { Ok(vec![]) }.boxed_local()
+        }
+    }
+
+    fn transaction(id: &str, day: u32) -> Transaction {
+        Transaction {
+            id: id.to_string(),
+            hash: None,
+            market: "BTC-USD".to_string(),
+            token: "BTC".to_string(),
+            amount: "1".parse().unwrap(),
+            rate: "40000".parse().unwrap(),
+            fiat_rate: "40000".parse().unwrap(),
+            fiat_amount: "40000".parse().unwrap(),
+            fiat: "USD".to_string(),
+            fee: "0".parse().unwrap(),
+            category: Category::Trade,
+            created_at: Some(Utc.ymd(2021, 1, day).and_hms(0, 0, 0)),
+            provider: "manual",
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = Registry::new();
+        registry.register(Box::new(Manual {
+            transactions: vec![
+                transaction("1", 1),
+                transaction("2", 2),
+                transaction("3", 3),
+            ],
+        }));
+
+        let provider = &registry.providers()[0];
+        assert_eq!(provider.name(), "manual");
+        let since = Utc.ymd(2021, 1, 2).and_hms(0, 0, 0);
+        let transactions = block_on(provider.transactions(Some(since))).unwrap();
+        assert_eq!(
+            transactions
+                .iter()
+                .map(|t| t.id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["3"]
+        );
+        assert_eq!(block_on(provider.transactions(None)).unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::io::{self, Read, Write};
+
+use bigdecimal::{BigDecimal, Zero};
+use chrono::Utc;
+
+use crate::config::Config;
+use crate::error::Warnings;
+use crate::portfolio::Portfolio;
+use crate::provider::Registry;
+use crate::report::{read_portfolio, read_records, Record};
+use crate::symbol::Symbol;
+use crate::types::Balance;
+use crate::wallet::Wallet;
+
+// ALL_PROVIDERS is the provider of the totals across every provider, which are compared with the
+// portfolio
+const ALL_PROVIDERS: &str = "all";
+
+// Discrepancy is a difference between what a provider reports holding of a token and what its
+// transactions add up to, which is a transaction missing from the export
+#[derive(Debug, Eq, PartialEq)]
+pub struct Discrepancy {
+    pub provider: String,
+    pub symbol: Symbol,
+    pub reported: BigDecimal,
+    pub computed: BigDecimal,
+}
+
+// reconcile fetches what every configured provider holds now, and lists each token where that
+// differs from the exported transactions, both for each provider and in total against the
+// portfolio
+pub async fn reconcile(config: &Config, registry: &Registry) -> Result<(), Box<dyn Error>> {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+
+    let denomination = config.denomination();
+    let records = read_records(input.as_bytes())?;
+    let mut warnings = Warnings::new(false);
+    let portfolio = read_portfolio(
+        input.as_bytes(),
+        &denomination,
+        &config.migrations(),
+        &config.events(),
+        config.fx_rates(),
+        config.jurisdiction()?.as_ref(),
+        &mut warnings,
+    )
+    .await?;
+    warnings.summarize();
+
+    let mut balances = Vec::new();
+    for provider in registry.providers() {
+        balances.extend(provider.balances().await?);
+    }
+    let reported = reported_balances(&balances);
+    let computed = computed_balances(&records, &portfolio, &denomination);
+    let discrepancies = discrepancies(&reported, &computed);
+
+    if discrepancies.is_empty() {
+        eprintln!("Every balance agrees with the transactions");
+    }
+    write_discrepancies(&mut io::stdout(), &portfolio, &discrepancies)
+}
+
+// reported_balances totals the balances reported by each provider for each token, and across
+// every provider. Fiat and tokens that aren't recognized are left out.
+fn reported_balances(balances: &[Balance]) -> HashMap<(String, Symbol), BigDecimal> {
+    let mut reported = HashMap::new();
+
+    for balance in balances {
+        let symbol = match Symbol::from_provider(balance.provider, &balance.token) {
+            Ok(Symbol::Fiat(_)) => continue,
+            Ok(symbol) => symbol,
+            Err(_) => {
+                if !balance.amount.is_zero() {
+                    eprintln!(
+                        "Skipping {} {} held in {}, the token isn't recognized",
+                        balance.amount, balance.token, balance.provider
+                    );
+                }
+                continue;
+            }
+        };
+
+        for provider in [balance.provider, ALL_PROVIDERS] {
+            *reported
+                .entry((provider.to_string(), symbol))
+                .or_insert_with(BigDecimal::zero) += &balance.amount;
+        }
+    }
+
+    reported
+}
+
+// computed_balances totals what each provider's transactions moved in and out of it for each
+// token, and what the portfolio holds of each token now. A trade moves both tokens of its market,
+// and the sender pays any fee on top, which the portfolio takes from its lots too. Transactions
+// that can't be read are left out, the same as from reports.
+fn computed_balances(
+    records: &[Record],
+    portfolio: &Portfolio,
+    denomination: &Symbol,
+) -> HashMap<(String, Symbol), BigDecimal> {
+    let mut computed = HashMap::new();
+
+    for record in records {
+        let Some((from, to)) = record.market.rsplit_once('-') else {
+            continue;
+        };
+        let (Ok(from), Ok(to)) = (
+            Symbol::from_provider(&record.provider, from),
+            Symbol::from_provider(&record.provider, to),
+        ) else {
+            continue;
+        };
+
+        let mut moved = vec![(from, &record.amount - &record.fee)];
+        if !record.category.is_transfer() {
+            moved.push((to, -(&record.rate * &record.amount)));
+        }
+        for (symbol, amount) in moved {
+            if let Symbol::Fiat(_) = symbol {
+                continue;
+            }
+            *computed
+                .entry((record.provider.clone(), symbol))
+                .or_insert_with(BigDecimal::zero) += amount;
+        }
+    }
+
+    let wallets = portfolio.wallets(denomination, &Wallet::new, Utc::now());
+    for (symbol, wallet) in wallets {
+        if let Symbol::Fiat(_) = symbol {
+            continue;
+        }
+        computed.insert((ALL_PROVIDERS.to_string(), symbol), wallet.count());
+    }
+
+    computed
+}
+
+// discrepancies compares the reported and computed balances of every provider that reported any,
+// ignoring differences too small to be more than rounding
+fn discrepancies(
+    reported: &HashMap<(String, Symbol), BigDecimal>,
+    computed: &HashMap<(String, Symbol), BigDecimal>,
+) -> Vec<Discrepancy> {
+    let tolerance = BigDecimal::new(1.into(), 8);
+    let providers: HashSet<&String> = reported.keys().map(|(provider, _)| provider).collect();
+
+    let keys: HashSet<&(String, Symbol)> = reported
+        .keys()
+        .chain(computed.keys())
+        .filter(|(provider, _)| providers.contains(provider))
+        .collect();
+
+    let mut discrepancies = keys
+        .into_iter()
+        .map(|key| Discrepancy {
+            provider: key.0.clone(),
+            symbol: key.1,
+            reported: reported.get(key).cloned().unwrap_or_default(),
+            computed: computed.get(key).cloned().unwrap_or_default(),
+        })
+        .filter(|d| (&d.reported - &d.computed).abs() > tolerance)
+        .collect::<Vec<_>>();
+    discrepancies.sort_by(|a, b| {
+        (a.provider == ALL_PROVIDERS)
+            .cmp(&(b.provider == ALL_PROVIDERS))
+            .then(a.provider.cmp(&b.provider))
+            .then(a.symbol.symbol().cmp(&b.symbol.symbol()))
+    });
+    discrepancies
+}
+
+// write_discrepancies writes a row for each discrepancy, a positive difference is more held than
+// the transactions account for
+fn write_discrepancies(
+    output: &mut dyn Write,
+    portfolio: &Portfolio,
+    discrepancies: &[Discrepancy],
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(output);
+    writer.write_record(["Provider", "Asset", "Reported", "Computed", "Difference"])?;
+
+    for discrepancy in discrepancies {
+        writer.write_record([
+            discrepancy.provider.clone(),
+            portfolio.name(&discrepancy.symbol),
+            discrepancy.reported.to_string(),
+            discrepancy.computed.to_string(),
+            (&discrepancy.reported - &discrepancy.computed).to_string(),
+        ])?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use futures::executor::block_on;
+    use pretty_assertions::assert_eq;
+
+    use crate::fx::FxRates;
+    use crate::jurisdiction::UnitedStates;
+    use crate::portfolio::Matching;
+    use crate::symbol::{Crypto, USD};
+
+    use super::*;
+
+    const INPUT: &str = "\
+ID,Market,Token,Amount,Rate,Fiat Rate,Fiat Amount,Fiat,Fee,Category,Created At,Provider
+1,BTC-USD,BTC,1.5,20000,20000,30000,USD,0,trade,2021-01-01T00:00:00Z,coinbase
+2,BTC-USD,BTC,-0.5,20000,20000,10000,USD,0,transfer_out,2021-02-01T00:00:00Z,coinbase
+3,BTC-USD,BTC,0.5,20000,20000,10000,USD,0,transfer_in,2021-02-01T00:00:00Z,coinbase-pro
+4,ETH-BTC,ETH,4,0.05,2000,8000,USD,0,trade,2021-03-01T00:00:00Z,coinbase-pro
+5,BTC@bitcoin-USD,BTC@bitcoin,0.2,0,0,0,USD,0,trade,2021-04-01T00:00:00Z,esplora
+6,BTC@bitcoin-USD,BTC@bitcoin,-0.1,0,0,0,USD,0.0001,trade,2021-05-01T00:00:00Z,esplora
+";
+
+    fn balance(provider: &'static str, token: &str, amount: &str) -> Balance {
+        Balance {
+            token: token.to_string(),
+            amount: amount.parse().unwrap(),
+            provider,
+        }
+    }
+
+    #[test]
+    fn test_discrepancies() {
+        let records = read_records(INPUT.as_bytes()).unwrap();
+        let us = UnitedStates {
+            method: Matching::Fifo,
+        };
+        let portfolio = block_on(read_portfolio(
+            INPUT.as_bytes(),
+            &USD,
+            &[],
+            &[],
+            FxRates::new(),
+            &us,
+            &mut Warnings::new(false),
+        ))
+        .unwrap();
+
+        // Coinbase Pro charged a fee that wasn't exported, and Coinbase holds an unrecognized
+        // token and dollars, which aren't reconciled
+        let reported = reported_balances(&[
+            balance("coinbase", "BTC", "1"),
+            balance("coinbase", "USD", "250"),
+            balance("coinbase", "NOTATOKEN", "3"),
+            balance("coinbase-pro", "BTC", "0.3"),
+            balance("coinbase-pro", "ETH", "3.99"),
+            balance("esplora", "BTC@bitcoin", "0.0999"),
+        ]);
+        let computed = computed_balances(&records, &portfolio, &USD);
+
+        assert_eq!(
+            discrepancies(&reported, &computed),
+            vec![
+                Discrepancy {
+                    provider: "coinbase-pro".to_string(),
+                    symbol: Symbol::Crypto(Crypto::ETH),
+                    reported: "3.99".parse().unwrap(),
+                    computed: "4".parse().unwrap(),
+                },
+                Discrepancy {
+                    provider: ALL_PROVIDERS.to_string(),
+                    symbol: Symbol::Crypto(Crypto::ETH),
+                    reported: "3.99".parse().unwrap(),
+                    computed: "4".parse().unwrap(),
+                },
+            ]
+        );
+    }
+}
@@ -1,42 +1,73 @@
-use std::io;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, Write};
+use std::iter;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use bigdecimal::{BigDecimal, Zero};
-use chrono::{self, Datelike};
+use chrono::{self, Duration, NaiveDate};
 
 use crate::amount::Amount;
-use crate::portfolio::{Kind, Portfolio, Trade};
-use crate::symbol::Symbol;
-use crate::types::DateTime;
+use crate::error::{ReportError, Warnings};
+use crate::fx::FxRates;
+use crate::jurisdiction::{Jurisdiction, YearStart};
+use crate::portfolio::{Disposal, Kind, Migration, Portfolio, Provenance, Source, Trade};
+use crate::symbol::{Fiat, Symbol, USD};
 use crate::types::{
-    deserialize_amount, deserialize_date, format_amount, format_amount_for_turbotax,
-    format_usd_amount,
+    deserialize_amount, deserialize_date, format_amount, format_fiat_amount, write_json, Category,
+    DateTime,
 };
 
 #[derive(Debug, Deserialize)]
-struct Record {
+pub struct Record {
     #[serde(alias = "ID")]
-    id: String,
+    pub id: String,
+    // Exports from before on-chain transfers were recorded with their hash had none
+    #[serde(alias = "Hash", default)]
+    pub hash: Option<String>,
     #[serde(alias = "Market")]
-    market: String,
+    pub market: String,
     #[serde(alias = "Token")]
-    token: String,
+    pub token: String,
     #[serde(alias = "Amount", deserialize_with = "deserialize_amount")]
-    amount: BigDecimal,
+    pub amount: BigDecimal,
     #[serde(alias = "Rate")]
-    rate: BigDecimal,
-    #[serde(alias = "USD Rate", deserialize_with = "deserialize_amount")]
-    usd_rate: BigDecimal,
-    #[serde(alias = "USD Amount", deserialize_with = "deserialize_amount")]
-    usd_amount: BigDecimal,
+    pub rate: BigDecimal,
+    #[serde(
+        alias = "Fiat Rate",
+        alias = "USD Rate",
+        deserialize_with = "deserialize_amount"
+    )]
+    pub fiat_rate: BigDecimal,
+    #[serde(
+        alias = "Fiat Amount",
+        alias = "USD Amount",
+        deserialize_with = "deserialize_amount"
+    )]
+    pub fiat_amount: BigDecimal,
+    // Exports from before amounts were recorded in their own currency were always in USD
+    #[serde(alias = "Fiat", default = "default_fiat")]
+    pub fiat: String,
+    // Exports from before fees were recorded had none
+    #[serde(alias = "Fee", default, deserialize_with = "deserialize_amount")]
+    pub fee: BigDecimal,
+    // Exports from before transactions were categorized only had trades
+    #[serde(alias = "Category", default)]
+    pub category: Category,
     #[serde(alias = "Created At", deserialize_with = "deserialize_date")]
-    created_at: DateTime,
+    pub created_at: DateTime,
     #[serde(alias = "Provider")]
-    provider: String,
+    pub provider: String,
+}
+
+fn default_fiat() -> String {
+    USD.symbol()
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
 pub struct Realization {
     pub amount: BigDecimal,
     pub description: String,
@@ -57,6 +88,21 @@ pub enum Format {
     IRS1099B,
     #[serde(alias = "turbotax")]
     TurboTax,
+    #[serde(alias = "anlageso")]
+    AnlageSO,
+    #[serde(alias = "hmrc")]
+    HMRC,
+    #[serde(alias = "sa108")]
+    SA108,
+    #[serde(alias = "schedule3")]
+    Schedule3,
+    #[serde(alias = "ato")]
+    ATO,
+    // Json and JsonLines list every realization for other tools, whatever the jurisdiction
+    #[serde(alias = "json")]
+    Json,
+    #[serde(alias = "jsonl")]
+    JsonLines,
 }
 
 impl FromStr for Format {
@@ -66,134 +112,1205 @@ impl FromStr for Format {
         match s.to_lowercase().as_ref() {
             "irs" | "irs1099b" => Ok(Format::IRS1099B),
             "turbotax" => Ok(Format::TurboTax),
+            "anlageso" => Ok(Format::AnlageSO),
+            "hmrc" => Ok(Format::HMRC),
+            "sa108" => Ok(Format::SA108),
+            "schedule3" => Ok(Format::Schedule3),
+            "ato" => Ok(Format::ATO),
+            "json" => Ok(Format::Json),
+            "jsonl" => Ok(Format::JsonLines),
             _ => Err(ParseFormatError {}),
         }
     }
 }
 
-pub fn report(year: u16, denomination: &Symbol, format: &Option<Format>) -> Result<()> {
+// report writes a report for each tax year in years, to output or stdout, reading the portfolio
+// from input or stdin. Every year is realized from one replay of the portfolio, so lots carry over
+// from one year to the next.
+#[allow(clippy::too_many_arguments)]
+pub async fn report(
+    years: RangeInclusive<u16>,
+    input: Option<&str>,
+    output: Option<&str>,
+    denomination: &Symbol,
+    migrations: &[Migration],
+    events: &[Trade],
+    fx_rates: FxRates,
+    jurisdiction: &dyn Jurisdiction,
+    year_start: Option<YearStart>,
+    format: &Option<Format>,
+    audit: bool,
+    strict: bool,
+    warnings_path: &str,
+) -> Result<()> {
+    let format = format
+        .clone()
+        .unwrap_or_else(|| jurisdiction.formats()[0].clone());
+    let json = matches!(format, Format::Json | Format::JsonLines);
+    if !json && !jurisdiction.formats().contains(&format) {
+        return Err(anyhow!(
+            "{} reports can't be formatted as {:?}",
+            jurisdiction.name(),
+            format
+        ));
+    }
+    if audit && json {
+        return Err(anyhow!("--audit is only written as CSV, not {:?}", format));
+    }
+    if years.is_empty() {
+        return Err(anyhow!(
+            "No tax years from {} to {}",
+            years.start(),
+            years.end()
+        ));
+    }
+    if output.is_none() && years.start() != years.end() {
+        return Err(anyhow!("Reports for more than one year need an --output"));
+    }
+
+    let input: Box<dyn io::Read> = match input {
+        Some(path) => Box::new(File::open(path)?),
+        None => Box::new(io::stdin()),
+    };
+    let mut warnings = Warnings::new(strict);
+    let portfolio = read_portfolio(
+        input,
+        denomination,
+        migrations,
+        events,
+        fx_rates,
+        jurisdiction,
+        &mut warnings,
+    )
+    .await?;
+    for shortfall in jurisdiction.shortfalls(&portfolio, denomination) {
+        warnings.warn(shortfall)?;
+    }
+    if !warnings.errors().is_empty() {
+        warnings.write(&mut File::create(warnings_path)?)?;
+        eprintln!(
+            "{} warnings listed in {}",
+            warnings.errors().len(),
+            warnings_path
+        );
+    }
+
+    let year_start = year_start.unwrap_or_else(|| jurisdiction.year_start());
+    let (realizations, audited, dispositions) = if audit {
+        (vec![], jurisdiction.audit(&portfolio, denomination), vec![])
+    } else {
+        (
+            jurisdiction.realizations(&portfolio, denomination),
+            vec![],
+            jurisdiction.dispositions(&portfolio, denomination),
+        )
+    };
+
+    for year in years.clone() {
+        let tax_year = year_start.tax_year(year);
+        let mut output: Box<dyn Write> = match output {
+            Some(path) => Box::new(File::create(output_path(path, year, &years))?),
+            None => Box::new(io::stdout()),
+        };
+
+        if audit {
+            let audited = audited
+                .iter()
+                .filter(|(r, _)| tax_year.contains(&r.disposed_when))
+                .cloned()
+                .collect::<Vec<_>>();
+            write_audit(&mut output, &audited, denomination)?;
+            continue;
+        }
+
+        let realizations = realizations
+            .iter()
+            .filter(|r| tax_year.contains(&r.disposed_when))
+            .cloned()
+            .collect::<Vec<_>>();
+        if json {
+            write_json(&mut output, &realizations, format == Format::JsonLines)?;
+            continue;
+        }
+        jurisdiction.write_report(&mut output, year, &realizations, denomination, &format)?;
+
+        let dispositions = dispositions
+            .iter()
+            .filter(|(_, r)| tax_year.contains(&r.disposed_when))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !dispositions.is_empty() {
+            writeln!(output)?;
+            write_dispositions(&mut output, &dispositions, jurisdiction, denomination)?;
+        }
+    }
+    if audit {
+        return Ok(());
+    }
+
+    // What's left held at the end of the last year, from the same lots the reports were realized
+    // from
+    let wallets = portfolio.wallets(
+        denomination,
+        &|token| jurisdiction.wallet(token),
+        year_start.tax_year(*years.end()).end,
+    );
+    let mut held = wallets.iter().collect::<Vec<_>>();
+    held.sort_by_key(|(symbol, _)| portfolio.name(symbol));
+    eprintln!("Portfolio:\n");
+    for (symbol, wallet) in held {
+        eprintln!(
+            "Wallet {} {} tokens remain worth {} ({:.2}/{:.2})",
+            portfolio.name(symbol),
+            wallet.count(),
+            format_fiat_amount(&wallet.cost_basis(), denomination),
+            wallet.cumulative_bought,
+            wallet.cumulative_sold
+        );
+    }
+
+    Ok(())
+}
+
+// output_path is where the report for a year is written, any {year} in path is replaced with it.
+// Without one, the reports of several years are told apart by the year before the extension,
+// e.g. report-2021.csv.
+fn output_path(path: &str, year: u16, years: &RangeInclusive<u16>) -> PathBuf {
+    if path.contains("{year}") {
+        return PathBuf::from(path.replace("{year}", &year.to_string()));
+    }
+    if years.start() == years.end() {
+        return PathBuf::from(path);
+    }
+
+    let path = Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, year, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, year),
+    };
+    path.with_file_name(name)
+}
+
+// write_audit lists every realization with the transactions behind it, the disposal and each
+// acquisition matched against it along with the fraction of the acquisition used
+pub fn write_audit(
+    output: &mut dyn Write,
+    audited: &[(Realization, Provenance)],
+    denomination: &Symbol,
+) -> Result<()> {
+    let source = |source: &Option<Source>| {
+        source.as_ref().map_or("unknown".to_string(), |s| {
+            format!("{} {}", s.provider, s.id)
+        })
+    };
+
+    let mut writer = csv::Writer::from_writer(output);
+    writer.write_record(&[
+        "Description of property",
+        "Amount",
+        "Date acquired",
+        "Date sold or disposed of",
+        "Proceeds",
+        "Cost basis",
+        "Gain or (loss)",
+        "Disposal",
+        "Acquisitions",
+    ])?;
+
+    for (realization, provenance) in audited {
+        let acquisitions = provenance
+            .acquisitions
+            .iter()
+            .map(|(s, fraction)| format!("{} ({})", source(s), format_amount(fraction)))
+            .collect::<Vec<_>>()
+            .join("; ");
+        writer.write_record(&[
+            realization.description.clone(),
+            format_amount(&realization.amount),
+            realization
+                .acquired_when
+                .map_or("".to_string(), |d| d.format("%D").to_string()),
+            realization.disposed_when.format("%D").to_string(),
+            format_fiat_amount(&realization.proceeds, denomination),
+            format_fiat_amount(&realization.cost_basis, denomination),
+            format_fiat_amount(&realization.gain, denomination),
+            source(&provenance.disposal),
+            acquisitions,
+        ])?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+// write_dispositions lists the gifts, donations and losses made in the tax year, which aren't
+// gains or losses but may be deductible, e.g. donations on Form 8283. Donations of assets held
+// long term are deductible at their value, others at no more than their cost, and losses at
+// their cost.
+pub fn write_dispositions(
+    output: &mut dyn Write,
+    dispositions: &[(Disposal, Realization)],
+    jurisdiction: &dyn Jurisdiction,
+    denomination: &Symbol,
+) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(output);
+    writer.write_record(&[
+        "Disposal",
+        "Description of property",
+        "Amount",
+        "Date acquired",
+        "Date disposed of",
+        "Cost basis",
+        "Fair market value",
+        "Deductible",
+    ])?;
+
+    for (disposal, realization) in dispositions {
+        let (name, deductible) = match disposal {
+            Disposal::Gift => ("Gift", None),
+            Disposal::Donation if jurisdiction.long_term(realization) => {
+                ("Donation", Some(realization.proceeds.clone()))
+            }
+            Disposal::Donation => (
+                "Donation",
+                Some(
+                    realization
+                        .proceeds
+                        .clone()
+                        .min(realization.cost_basis.clone()),
+                ),
+            ),
+            Disposal::Loss => ("Loss", Some(realization.cost_basis.clone())),
+        };
+        writer.write_record(&[
+            name.to_string(),
+            realization.description.clone(),
+            format_amount(&realization.amount),
+            realization
+                .acquired_when
+                .map_or("".to_string(), |d| d.format("%D").to_string()),
+            realization.disposed_when.format("%D").to_string(),
+            format_fiat_amount(&realization.cost_basis, denomination),
+            format_fiat_amount(&realization.proceeds, denomination),
+            deductible.map_or("".to_string(), |d| format_fiat_amount(&d, denomination)),
+        ])?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+// read_portfolio builds a portfolio from exported transactions and any other events, with every
+// trade in the denomination or valued in it as the jurisdiction requires
+pub async fn read_portfolio(
+    input: impl io::Read,
+    denomination: &Symbol,
+    migrations: &[Migration],
+    events: &[Trade],
+    mut fx_rates: FxRates,
+    jurisdiction: &dyn Jurisdiction,
+    warnings: &mut Warnings,
+) -> Result<Portfolio> {
     let mut portfolio = Portfolio::new();
+    for migration in migrations {
+        portfolio.add_migration(migration);
+    }
 
-    let mut rdr = csv::Reader::from_reader(io::stdin());
+    let records = read_records(input)?;
 
-    for result in rdr.deserialize() {
-        let record: Record = result?;
+    if let Symbol::Fiat(to) = denomination {
+        fetch_fx_rates(&mut fx_rates, &records, *to).await?;
+    }
 
-        let market_components = record.market.split('-').collect::<Vec<_>>();
-        let from_symbol: Symbol = market_components[0].parse().unwrap();
-        let to_symbol: Symbol = market_components[1].parse().unwrap();
+    let mut trades = Vec::new();
 
-        let trade = if record.amount >= BigDecimal::zero() {
-            Trade {
-                when: record.created_at,
-                kind: Kind::Trade {
-                    offered: Amount {
-                        amount: &record.rate * &record.amount.abs(),
-                        symbol: to_symbol,
-                    },
-                    gained: Amount {
-                        amount: record.amount.abs().clone(),
-                        symbol: from_symbol,
-                    },
+    // Tokens moved out of one account and into another keep their cost and date of purchase
+    let mut transfers = Vec::new();
+    for record in records
+        .iter()
+        .filter(|r| matches!(r.category, Category::TransferIn | Category::TransferOut))
+    {
+        let source = Source {
+            id: record.id.clone(),
+            provider: record.provider.clone(),
+        };
+        let Some((token, _)) = record.market.rsplit_once('-') else {
+            warnings.warn(ReportError::BadMarket {
+                market: record.market.clone(),
+                source,
+            })?;
+            continue;
+        };
+        match symbol(warnings, token, &source)? {
+            None | Some(Symbol::Fiat(_)) => {}
+            Some(token) => {
+                if let Symbol::Nft(_) = token {
+                    portfolio.name_asset(token, &record.token);
+                }
+                transfers.push((record, token, source));
+            }
+        }
+    }
+    let pairs = match_transfers(&transfers);
+    let paired: HashSet<usize> = pairs.iter().flat_map(|&(out, into)| [out, into]).collect();
+    // Fees are paid on top of what's sent, out of what's received, and take their cost with them
+    for (out, into) in pairs {
+        let (sent, sent_token, source) = &transfers[out];
+        let (received, received_token, _) = &transfers[into];
+        let kind = Kind::Transfer {
+            sent: Amount {
+                amount: sent.amount.abs() + &sent.fee,
+                symbol: *sent_token,
+            },
+            received: Amount {
+                amount: received.amount.abs() - &received.fee,
+                symbol: *received_token,
+            },
+        };
+        let trade = Trade {
+            when: received.created_at,
+            kind,
+        };
+        trades.push((trade, Some(source.clone())));
+    }
+
+    // Tokens moved on-chain to or from anyone else were received or spent at their value, if it's
+    // known. Those sent without a value, such as an NFT given away, are left as they are, and so
+    // are transfers made off-chain, but for any fees paid.
+    for (i, (record, token, source)) in transfers.iter().enumerate() {
+        if paired.contains(&i) {
+            continue;
+        }
+        let fee = Amount {
+            amount: record.fee.clone(),
+            symbol: *token,
+        };
+        let fees = Trade {
+            when: record.created_at,
+            kind: Kind::Transfer {
+                sent: fee.clone(),
+                received: Amount {
+                    amount: BigDecimal::zero(),
+                    symbol: *token,
                 },
+            },
+        };
+        if !matches!(token, Symbol::OnChain(..) | Symbol::Nft(_)) {
+            if !fee.amount.is_zero() {
+                trades.push((fees, Some(source.clone())));
             }
+            continue;
+        }
+        let value = if record.fiat_amount.is_zero() {
+            Some(Amount {
+                amount: BigDecimal::zero(),
+                symbol: *denomination,
+            })
         } else {
-            Trade {
-                when: record.created_at,
-                kind: Kind::Trade {
-                    offered: Amount {
-                        amount: record.amount.abs().clone(),
-                        symbol: from_symbol,
-                    },
-                    gained: Amount {
-                        amount: &record.rate * &record.amount.abs(),
-                        symbol: to_symbol,
-                    },
+            let Some(fiat) = symbol(warnings, &record.fiat, source)? else {
+                continue;
+            };
+            let value = Amount {
+                amount: record.fiat_amount.abs(),
+                symbol: fiat,
+            };
+            let when = record.created_at;
+            in_denomination(&fx_rates, value, denomination, when, warnings, source)?
+        };
+        let Some(value) = value else {
+            continue;
+        };
+        let kind = match record.category {
+            Category::TransferIn => Kind::Trade {
+                offered: value,
+                gained: Amount {
+                    amount: record.amount.abs() - &fee.amount,
+                    symbol: *token,
                 },
-            }
+            },
+            _ if !value.amount.is_zero() => Kind::Trade {
+                offered: Amount {
+                    amount: record.amount.abs() + &fee.amount,
+                    symbol: *token,
+                },
+                gained: value,
+            },
+            _ if !fee.amount.is_zero() => fees.kind,
+            _ => continue,
+        };
+        let trade = Trade {
+            when: record.created_at,
+            kind,
         };
-        portfolio.add_trade(&trade);
+        trades.push((trade, Some(source.clone())));
     }
 
-    let mut writer = csv::Writer::from_writer(io::stdout());
+    // Fiat deposits and withdrawals don't change what's owned
+    for record in records.iter().filter(|r| !r.category.is_transfer()) {
+        let source = Source {
+            id: record.id.clone(),
+            provider: record.provider.clone(),
+        };
+        let Some((from, to)) = record.market.rsplit_once('-') else {
+            warnings.warn(ReportError::BadMarket {
+                market: record.market.clone(),
+                source,
+            })?;
+            continue;
+        };
+        let (Some(from_symbol), Some(to_symbol)) = (
+            symbol(warnings, from, &source)?,
+            symbol(warnings, to, &source)?,
+        ) else {
+            continue;
+        };
 
-    match format.as_ref().unwrap_or(&Format::IRS1099B) {
-        Format::IRS1099B => {
-            writer.write_record(&[
-                "Description of property",
-                "Date acquired",
-                "Date sold or disposed of",
-                "Proceeds",
-                "Cost basis",
-                "Gain or (loss)",
-            ])?;
+        // Fees are paid in the token bought or sold, on top of what's sold or out of what's bought
+        let quote = Amount {
+            amount: &record.rate * &record.amount.abs(),
+            symbol: to_symbol,
+        };
+        let (offered, gained) = if record.amount >= BigDecimal::zero() {
+            let base = Amount {
+                amount: &record.amount - &record.fee,
+                symbol: from_symbol,
+            };
+            (quote, base)
+        } else {
+            let base = Amount {
+                amount: record.amount.abs() + &record.fee,
+                symbol: from_symbol,
+            };
+            (base, quote)
+        };
 
-            let (mut total_proceeds, mut total_cost, mut total_gain) =
-                (BigDecimal::zero(), BigDecimal::zero(), BigDecimal::zero());
-            for realization in portfolio.realizations(denomination) {
-                let year_of_sale = realization.disposed_when.year();
-                if year_of_sale != year as i32 {
-                    continue;
-                }
+        // Fiat other than the denomination is converted at the rate on the day of the trade, so
+        // that buying or selling with it is realized like any trade in the denomination
+        let when = record.created_at;
+        let (Some(offered), Some(gained)) = (
+            in_denomination(&fx_rates, offered, denomination, when, warnings, &source)?,
+            in_denomination(&fx_rates, gained, denomination, when, warnings, &source)?,
+        ) else {
+            continue;
+        };
 
-                total_proceeds += &realization.proceeds;
-                total_cost += &realization.cost_basis;
-                total_gain += &realization.gain;
+        if let Symbol::Nft(_) = from_symbol {
+            portfolio.name_asset(from_symbol, &record.token);
+        }
 
-                writer.write_record(&[
-                    realization.description,
-                    realization
-                        .acquired_when
-                        .map_or("".to_string(), |d| d.format("%D").to_string()),
-                    realization.disposed_when.format("%D").to_string(),
-                    format_usd_amount(&realization.proceeds),
-                    format_usd_amount(&realization.cost_basis),
-                    format_usd_amount(&realization.gain),
-                ])?;
+        // NFTs are valued in the denomination whenever they change hands, so that their sale is
+        // realized rather than carried through to whatever they were exchanged for. Jurisdictions
+        // that tax every exchange of one token for another value all exchanges this way.
+        let exchange = offered.symbol != *denomination && gained.symbol != *denomination;
+        let valued = matches!(from_symbol, Symbol::Nft(_)) || jurisdiction.values_exchanges();
+        let trade = Trade {
+            when: record.created_at,
+            kind: Kind::Trade { offered, gained },
+        };
+        if exchange && valued && record.fiat_amount.is_zero() {
+            warnings.warn(ReportError::MissingPrice {
+                market: record.market.clone(),
+                when: record.created_at,
+                source: source.clone(),
+            })?;
+        }
+        if exchange && valued && !record.fiat_amount.is_zero() {
+            let Some(fiat) = symbol(warnings, &record.fiat, &source)? else {
+                continue;
+            };
+            // A sale for less than the gas paid has a negative value, as it cost to make
+            let mut value = Amount {
+                amount: record.fiat_amount.abs(),
+                symbol: fiat,
+            };
+            if record.rate < BigDecimal::zero() {
+                value.amount = -value.amount;
             }
+            let Some(value) =
+                in_denomination(&fx_rates, value, denomination, when, warnings, &source)?
+            else {
+                continue;
+            };
+            let (disposal, acquisition) = trade.via(&value);
+            trades.push((disposal, Some(source.clone())));
+            trades.push((acquisition, Some(source)));
+        } else {
+            trades.push((trade, Some(source)));
+        }
+    }
+
+    // Events that aren't exported, such as gifts, are added in with the trades made around them
+    if !events.is_empty() {
+        trades.extend(events.iter().map(|e| (e.clone(), None)));
+        trades.sort_by_key(|(t, _)| t.when);
+    }
+    for (trade, source) in trades.iter() {
+        match source {
+            Some(source) => portfolio.add_sourced_trade(trade, source),
+            None => portfolio.add_trade(trade),
+        }
+    }
+
+    Ok(portfolio)
+}
+
+// Transfers into an account received within TRANSFER_WINDOW_DAYS of a transfer out of another, of
+// no more than was sent and no less than TRANSFER_FEE_PERCENT under it, are the same tokens moved
+// between them. Accounts don't all time a transfer at the same point, so it can be received up to
+// TRANSFER_SKEW_HOURS before it was sent.
+const TRANSFER_WINDOW_DAYS: i64 = 3;
+const TRANSFER_FEE_PERCENT: u32 = 2;
+const TRANSFER_SKEW_HOURS: i64 = 1;
+
+// match_transfers pairs each transfer out of an account with the earliest transfer into another of
+// the same token on any network, returning the index of each, out then in
+fn match_transfers(transfers: &[(&Record, Symbol, Source)]) -> Vec<(usize, usize)> {
+    let mut order: Vec<usize> = (0..transfers.len()).collect();
+    order.sort_by_key(|&i| transfers[i].0.created_at);
 
-            writer.write_record(&[
-                "Total",
-                "",
-                "",
-                &format_usd_amount(&total_proceeds),
-                &format_usd_amount(&total_cost),
-                &format_usd_amount(&total_gain),
-            ])?;
-        }
-        Format::TurboTax => {
-            writer.write_record(&[
-                "Amount",
-                "Currency Name",
-                "Purchase Date",
-                "Date Sold",
-                "Cost Basis",
-                "Proceeds",
-            ])?;
-
-            for realization in portfolio.realizations(denomination) {
-                let year_of_sale = realization.disposed_when.year();
-                if year_of_sale != year as i32 {
-                    continue;
+    let mut received = vec![false; transfers.len()];
+    let mut pairs = Vec::new();
+    for &out in order.iter() {
+        let (sent, token, _) = &transfers[out];
+        if sent.category != Category::TransferOut || sent.amount.is_zero() {
+            continue;
+        }
+        let most = sent.amount.abs();
+        let least = &most * BigDecimal::from(100 - TRANSFER_FEE_PERCENT) / BigDecimal::from(100);
+        let earliest = sent.created_at - Duration::hours(TRANSFER_SKEW_HOURS);
+        let latest = sent.created_at + Duration::days(TRANSFER_WINDOW_DAYS);
+
+        let found = order.iter().copied().find(|&i| {
+            let (record, other, _) = &transfers[i];
+            let amount = record.amount.abs();
+            // Moves between accounts of the same provider are told apart by network, unless
+            // both sides are the same on-chain transaction
+            let same_hash = sent.hash.is_some() && record.hash == sent.hash;
+            let elsewhere = record.provider != sent.provider || other != token || same_hash;
+            !received[i]
+                && record.category == Category::TransferIn
+                && elsewhere
+                && other.asset() == token.asset()
+                && (earliest..=latest).contains(&record.created_at)
+                && amount >= least
+                && amount <= most
+        });
+        if let Some(into) = found {
+            received[into] = true;
+            pairs.push((out, into));
+        }
+    }
+    pairs
+}
+
+// symbol reads a symbol named by a transaction's provider, warning if it isn't known
+pub fn symbol(warnings: &mut Warnings, symbol: &str, source: &Source) -> Result<Option<Symbol>> {
+    match Symbol::from_provider(&source.provider, symbol) {
+        Ok(symbol) => Ok(Some(symbol)),
+        Err(_) => {
+            warnings.warn(ReportError::UnknownSymbol {
+                symbol: symbol.to_string(),
+                source: source.clone(),
+            })?;
+            Ok(None)
+        }
+    }
+}
+
+// read_records reads every transaction from an export, either a CSV or JSON, as an array or JSON
+// Lines
+pub fn read_records(mut input: impl io::Read) -> Result<Vec<Record>> {
+    let mut contents = String::new();
+    input.read_to_string(&mut contents)?;
+    match contents.trim_start().chars().next() {
+        Some('[') => return Ok(serde_json::from_str(&contents)?),
+        Some('{') => {
+            return contents
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| Ok(serde_json::from_str(line)?))
+                .collect()
+        }
+        _ => {}
+    }
+
+    let mut rdr = csv::Reader::from_reader(contents.as_bytes());
+    let mut records = Vec::new();
+    for result in rdr.deserialize() {
+        let record: Record = result?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+// fetch_fx_rates fetches rates for every other fiat currency traded in, over the days it was traded
+// that rates haven't already been configured for. Rates that can't be fetched are only missed by
+// the amounts converted with them.
+pub async fn fetch_fx_rates(fx_rates: &mut FxRates, records: &[Record], to: Fiat) -> Result<()> {
+    let mut days: HashMap<Fiat, (NaiveDate, NaiveDate)> = HashMap::new();
+    for record in records {
+        let day = record.created_at.naive_utc().date();
+        let fiats = record
+            .market
+            .split('-')
+            .chain(iter::once(record.fiat.as_str()))
+            .filter_map(|s| match Symbol::from_provider(&record.provider, s) {
+                Ok(Symbol::Fiat(fiat)) if !fx_rates.has_rate(fiat, to, day) => Some(fiat),
+                _ => None,
+            });
+        for fiat in fiats.collect::<Vec<_>>() {
+            let range = days.entry(fiat).or_insert((day, day));
+            range.0 = range.0.min(day);
+            range.1 = range.1.max(day);
+        }
+    }
+
+    for (from, (start, end)) in days {
+        if let Err(e) = fx_rates.fetch(from, to, start, end).await {
+            eprintln!(
+                "Unable to fetch exchange rates from {} to {}: {}",
+                from, to, e
+            );
+        }
+    }
+    Ok(())
+}
+
+// in_denomination converts an amount of fiat into a fiat denomination, warning if there's no rate
+// to convert it at, any other amount is returned as is
+pub fn in_denomination(
+    fx_rates: &FxRates,
+    amount: Amount,
+    denomination: &Symbol,
+    when: DateTime,
+    warnings: &mut Warnings,
+    source: &Source,
+) -> Result<Option<Amount>> {
+    match (amount.symbol, denomination) {
+        (Symbol::Fiat(from), Symbol::Fiat(to)) if from != *to => {
+            match fx_rates.convert(&amount.amount, from, *to, when) {
+                Some(converted) => Ok(Some(Amount {
+                    amount: converted,
+                    symbol: *denomination,
+                })),
+                None => {
+                    warnings.warn(ReportError::MissingFxRate {
+                        from,
+                        to: *to,
+                        when,
+                        source: source.clone(),
+                    })?;
+                    Ok(None)
                 }
+            }
+        }
+        _ => Ok(Some(amount)),
+    }
+}
 
-                writer.write_record(&[
-                    format_amount_for_turbotax(&realization.amount),
-                    realization.symbol.symbol(),
-                    realization
-                        .acquired_when
-                        .map_or("".to_string(), |d| d.format("%D %R").to_string()),
-                    realization.disposed_when.format("%D %R").to_string(),
-                    format_amount(&realization.cost_basis),
-                    format_amount(&realization.proceeds),
-                ])?;
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+    use futures::executor::block_on;
+    use pretty_assertions::assert_eq;
+
+    use crate::jurisdiction::{UnitedKingdom, UnitedStates};
+    use crate::portfolio::Matching;
+    use crate::symbol::{Chain, Crypto, BTC};
+    use crate::types::Transaction;
+    use crate::wallet::Wallet;
+
+    use super::*;
+
+    #[test]
+    fn test_read_portfolio_categories() {
+        let export = "\
+ID,Market,Token,Amount,Rate,Fiat Rate,Fiat Amount,Fiat,Fee,Category,Created At,Provider
+1,USD-USD,USD,1000.0000,1.0000,1.0000,1000.0000,USD,0.0000,deposit,2021-01-01T00:00:00Z,coinbase
+2,BTC-USD,BTC,0.1000,5000.0000,5000.0000,500.0000,USD,0.0000,trade,2021-01-02T00:00:00Z,coinbase
+3,BTC-USD,BTC,(0.0500),6000.0000,6000.0000,(300.0000),USD,0.0000,transfer_out,2021-02-01T00:00:00Z,coinbase
+4,BTC-USD,BTC,(0.0500),8000.0000,8000.0000,(400.0000),USD,0.0000,conversion,2021-03-01T00:00:00Z,coinbase
+5,ETH-USD,ETH,0.2000,2000.0000,2000.0000,400.0000,USD,0.0000,conversion,2021-03-01T00:00:00Z,coinbase
+";
+        let us = UnitedStates {
+            method: Matching::Fifo,
+        };
+        let portfolio = block_on(read_portfolio(
+            export.as_bytes(),
+            &USD,
+            &[],
+            &[],
+            FxRates::new(),
+            &us,
+            &mut Warnings::new(true),
+        ))
+        .unwrap();
+
+        // The transfer and deposit are left out, and the conversion sells BTC for its value
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
+        assert_eq!(realizations.len(), 1);
+        assert_eq!(realizations[0].amount, "0.05".parse().unwrap());
+        assert_eq!(realizations[0].proceeds, BigDecimal::from(400));
+        assert_eq!(realizations[0].cost_basis, BigDecimal::from(250));
+    }
+
+    #[test]
+    fn test_read_portfolio_transfers() {
+        let export = "\
+ID,Market,Token,Amount,Rate,Fiat Rate,Fiat Amount,Fiat,Fee,Category,Created At,Provider
+1,BTC-USD,BTC,1.0000,9800.0000,9800.0000,9800.0000,USD,0.0000,trade,2021-01-01T00:00:00Z,coinbase
+2,BTC-USD,BTC,(1.0000),20000.0000,20000.0000,(20000.0000),USD,0.0000,transfer_out,2021-02-01T00:00:00Z,coinbase
+3,BTC@bitcoin-USD,BTC,0.9800,0.0000,0.0000,0.0000,USD,0.0000,transfer_in,2021-02-02T00:00:00Z,esplora
+4,BTC@bitcoin-USD,BTC,(0.9800),30000.0000,30000.0000,(29400.0000),USD,0.0000,transfer_out,2021-03-01T00:00:00Z,esplora
+5,BTC@bitcoin-USD,BTC,0.5000,0.0000,0.0000,0.0000,USD,0.0000,transfer_in,2021-03-10T00:00:00Z,esplora
+";
+        let us = UnitedStates {
+            method: Matching::Fifo,
+        };
+        let portfolio = block_on(read_portfolio(
+            export.as_bytes(),
+            &USD,
+            &[],
+            &[],
+            FxRates::new(),
+            &us,
+            &mut Warnings::new(true),
+        ))
+        .unwrap();
+
+        // The withdrawal from Coinbase carries its cost and date over to the wallet, less fees, and
+        // the BTC spent from the wallet with nowhere it was received is disposed of for its value
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
+        assert_eq!(realizations.len(), 1);
+        assert_eq!(realizations[0].amount, "0.98".parse().unwrap());
+        assert_eq!(
+            realizations[0].acquired_when,
+            Some(Utc.ymd(2021, 1, 1).and_hms(0, 0, 0))
+        );
+        assert_eq!(realizations[0].proceeds, BigDecimal::from(29400));
+        assert_eq!(realizations[0].cost_basis, BigDecimal::from(9800));
+
+        // and BTC received from anyone else is acquired for its value, nothing if it isn't known
+        let wallets = portfolio.wallets(&USD, &Wallet::new, Utc.ymd(2022, 1, 1).and_hms(0, 0, 0));
+        let wallet = &wallets[&Symbol::OnChain(Crypto::BTC, Chain::Bitcoin)];
+        assert_eq!(wallet.count(), "0.5".parse().unwrap());
+        assert!(wallet.cost_basis().is_zero());
+        assert!(wallets[&BTC].count().is_zero());
+    }
+
+    #[test]
+    fn test_read_portfolio_configured_fx_rates() {
+        let export = "\
+ID,Market,Token,Amount,Rate,Fiat Rate,Fiat Amount,Fiat,Fee,Category,Created At,Provider
+1,BTC-EUR,BTC,1.0000,8000.0000,8000.0000,8000.0000,EUR,0.0000,trade,2021-01-04T00:00:00Z,coinbase
+2,BTC-EUR,BTC,(1.0000),9000.0000,9000.0000,(9000.0000),EUR,0.0000,trade,2021-01-09T00:00:00Z,coinbase
+";
+        let mut fx_rates = FxRates::new();
+        for (day, rate) in [(4, "1.25"), (8, "1.20")] {
+            fx_rates.add_rate(
+                Fiat::EUR,
+                Fiat::USD,
+                NaiveDate::from_ymd(2021, 1, day),
+                rate.parse().unwrap(),
+            );
+        }
+        let us = UnitedStates {
+            method: Matching::Fifo,
+        };
+        let portfolio = block_on(read_portfolio(
+            export.as_bytes(),
+            &USD,
+            &[],
+            &[],
+            fx_rates,
+            &us,
+            &mut Warnings::new(true),
+        ))
+        .unwrap();
+
+        // Configured rates cover both days, so none are fetched
+        let realizations = portfolio.realizations(&USD, Matching::Fifo);
+        assert_eq!(realizations[0].cost_basis, BigDecimal::from(10000));
+        assert_eq!(realizations[0].proceeds, BigDecimal::from(10800));
+    }
+
+    #[test]
+    fn test_in_denomination_missing_fx_rate() {
+        let when = Utc.ymd(2021, 1, 4).and_hms(0, 0, 0);
+        let source = Source {
+            id: "1".into(),
+            provider: "coinbase".into(),
+        };
+        let convert = |warnings: &mut Warnings| {
+            in_denomination(
+                &FxRates::new(),
+                Amount {
+                    amount: BigDecimal::from(100),
+                    symbol: Symbol::Fiat(Fiat::EUR),
+                },
+                &USD,
+                when,
+                warnings,
+                &source,
+            )
+        };
+        let missing = ReportError::MissingFxRate {
+            from: Fiat::EUR,
+            to: Fiat::USD,
+            when,
+            source: source.clone(),
+        };
+
+        // Strict reports fail without a rate, others leave the transaction out and warn
+        let err = convert(&mut Warnings::new(true)).unwrap_err();
+        assert_eq!(err.downcast::<ReportError>().unwrap(), missing);
+
+        let mut warnings = Warnings::new(false);
+        assert_eq!(convert(&mut warnings).unwrap(), None);
+        assert_eq!(warnings.errors(), &[missing]);
+    }
+
+    #[test]
+    fn test_read_records_usd_headers() {
+        let export = "\
+ID,Market,Token,Amount,Rate,USD Rate,USD Amount,Created At,Provider
+1,BTC-USD,BTC,(0.5000),5000.0000,$5000.0000,($2500.0000),2021-03-01T00:00:00Z,coinbase
+";
+        let records = read_records(export.as_bytes()).unwrap();
+        assert_eq!(records[0].fiat_rate, BigDecimal::from(5000));
+        assert_eq!(records[0].fiat_amount, BigDecimal::from(-2500));
+        assert_eq!(records[0].fiat, "USD");
+        assert_eq!(records[0].category, Category::Trade);
+    }
+
+    #[test]
+    fn test_read_portfolio_missing_price() {
+        let export = "\
+ID,Market,Token,Amount,Rate,Fiat Rate,Fiat Amount,Fiat,Fee,Category,Created At,Provider
+1,ETH-BTC,ETH,2.0000,0.0500,0.0000,0.0000,GBP,0.0000,trade,2021-01-02T00:00:00Z,coinbase
+";
+        // Exchanges are valued in the UK, so one without a price fails a strict report
+        let err = block_on(read_portfolio(
+            export.as_bytes(),
+            &Symbol::Fiat(Fiat::GBP),
+            &[],
+            &[],
+            FxRates::new(),
+            &UnitedKingdom {},
+            &mut Warnings::new(true),
+        ))
+        .unwrap_err();
+        assert_eq!(
+            err.downcast::<ReportError>().unwrap(),
+            ReportError::MissingPrice {
+                market: "ETH-BTC".into(),
+                when: Utc.ymd(2021, 1, 2).and_hms(0, 0, 0),
+                source: Source {
+                    id: "1".into(),
+                    provider: "coinbase".into(),
+                },
             }
+        );
+    }
+
+    #[test]
+    fn test_read_portfolio_warnings() {
+        let export = "\
+ID,Market,Token,Amount,Rate,Fiat Rate,Fiat Amount,Fiat,Fee,Category,Created At,Provider
+1,BTC-USD,BTC,0.1000,5000.0000,5000.0000,500.0000,USD,0.0000,trade,2021-01-02T00:00:00Z,coinbase
+2,BTCUSD,BTC,0.1000,5000.0000,5000.0000,500.0000,USD,0.0000,trade,2021-01-03T00:00:00Z,coinbase
+3,XYZ-USD,XYZ,1.0000,1.0000,1.0000,1.0000,USD,0.0000,trade,2021-01-04T00:00:00Z,coinbase
+4,BTC-USD,BTC,(0.2000),6000.0000,6000.0000,(1200.0000),USD,0.0000,trade,2021-02-01T00:00:00Z,coinbase
+5,XYZ-A-USD,XYZ-A,1.0000,1.0000,1.0000,1.0000,USD,0.0000,trade,2021-02-02T00:00:00Z,coinbase
+";
+        let us = UnitedStates {
+            method: Matching::Fifo,
+        };
+        let read = |warnings: &mut Warnings| {
+            block_on(read_portfolio(
+                export.as_bytes(),
+                &USD,
+                &[],
+                &[],
+                FxRates::new(),
+                &us,
+                warnings,
+            ))
+        };
+
+        // Strict reports fail on the first error
+        let err = read(&mut Warnings::new(true)).unwrap_err();
+        assert_eq!(
+            err.downcast::<ReportError>().unwrap(),
+            ReportError::BadMarket {
+                market: "BTCUSD".into(),
+                source: Source {
+                    id: "2".into(),
+                    provider: "coinbase".into(),
+                },
+            }
+        );
+
+        // others leave out what they can't read, and warn about it along with what was oversold
+        let mut warnings = Warnings::new(false);
+        let portfolio = read(&mut warnings).unwrap();
+        for shortfall in portfolio.shortfalls(&USD) {
+            warnings.warn(shortfall).unwrap();
         }
+        assert_eq!(
+            warnings
+                .errors()
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "Bad market BTCUSD in coinbase transaction 2",
+                "Unknown symbol XYZ in coinbase transaction 3",
+                "Unknown symbol XYZ-A in coinbase transaction 5",
+                "0.1000 BTC more than was held was disposed of on 2021-02-01, it has no cost basis",
+            ]
+        );
     }
 
-    writer.flush()?;
+    #[test]
+    fn test_read_records_json() {
+        let transaction = Transaction {
+            id: "1".into(),
+            hash: None,
+            market: "BTC-USD".into(),
+            token: "BTC".into(),
+            amount: "-3.14275".parse().unwrap(),
+            rate: "41000.5".parse().unwrap(),
+            fiat_rate: "41000.5".parse().unwrap(),
+            fiat_amount: "-128854.8126375".parse().unwrap(),
+            fiat: "USD".into(),
+            fee: "0.0001".parse().unwrap(),
+            category: Category::TransferOut,
+            created_at: Some(Utc.ymd(2021, 3, 1).and_hms(12, 30, 0)),
+            provider: "coinbase",
+        };
 
-    eprintln!("Portfolio:\n\n{:#?}\n", &portfolio);
+        let mut output = Vec::new();
+        write_json(
+            &mut output,
+            &[transaction.clone(), transaction.clone()],
+            true,
+        )
+        .unwrap();
+        let lines = String::from_utf8(output).unwrap();
+        assert_eq!(
+            lines.lines().next().unwrap(),
+            "{\"id\":\"1\",\"market\":\"BTC-USD\",\"token\":\"BTC\",\"amount\":\"-3.14275\",\
+             \"rate\":\"41000.5\",\"fiat_rate\":\"41000.5\",\"fiat_amount\":\"-128854.8126375\",\
+             \"fiat\":\"USD\",\"fee\":\"0.0001\",\"category\":\"transfer_out\",\
+             \"created_at\":\"2021-03-01T12:30:00Z\",\"provider\":\"coinbase\"}"
+        );
 
-    Ok(())
+        let mut output = Vec::new();
+        write_json(&mut output, &[transaction], false).unwrap();
+        for input in [lines, String::from_utf8(output).unwrap()] {
+            let records = read_records(input.as_bytes()).unwrap();
+            let record = &records[0];
+            assert_eq!(record.amount, "-3.14275".parse().unwrap());
+            assert_eq!(record.fiat_amount, "-128854.8126375".parse().unwrap());
+            assert_eq!(record.fee, "0.0001".parse().unwrap());
+            assert_eq!(record.category, Category::TransferOut);
+            assert_eq!(record.created_at, Utc.ymd(2021, 3, 1).and_hms(12, 30, 0));
+        }
+    }
+
+    #[test]
+    fn test_write_realizations_json() {
+        let realization = Realization {
+            amount: "0.5".parse().unwrap(),
+            description: "USDC sold via USDC@polygon-USD pair".into(),
+            symbol: Symbol::OnChain(crate::symbol::Crypto::USDC, crate::symbol::Chain::Polygon),
+            acquired_when: None,
+            disposed_when: Utc.ymd(2021, 12, 1).and_hms(0, 0, 0),
+            proceeds: "0.5".parse().unwrap(),
+            cost_basis: BigDecimal::zero(),
+            gain: "0.5".parse().unwrap(),
+        };
+
+        let mut output = Vec::new();
+        write_json(&mut output, &[realization], true).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"amount\":\"0.5\",\"description\":\"USDC sold via USDC@polygon-USD pair\",\
+             \"symbol\":\"USDC@polygon\",\"acquired_when\":null,\
+             \"disposed_when\":\"2021-12-01T00:00:00Z\",\"proceeds\":\"0.5\",\"cost_basis\":\"0\",\
+             \"gain\":\"0.5\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_output_path() {
+        assert_eq!(
+            output_path("report.csv", 2021, &(2021..=2021)),
+            PathBuf::from("report.csv")
+        );
+        assert_eq!(
+            output_path("reports/report.csv", 2021, &(2020..=2021)),
+            PathBuf::from("reports/report-2021.csv")
+        );
+        assert_eq!(
+            output_path("{year}/8949", 2020, &(2020..=2021)),
+            PathBuf::from("2020/8949")
+        );
+    }
+
+    #[test]
+    fn test_report_audit_json() {
+        let us = UnitedStates {
+            method: Matching::Fifo,
+        };
+
+        let err = block_on(report(
+            2020..=2020,
+            None,
+            None,
+            &USD,
+            &[],
+            &[],
+            FxRates::new(),
+            &us,
+            None,
+            &Some(Format::JsonLines),
+            true,
+            false,
+            "warnings.csv",
+        ))
+        .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "--audit is only written as CSV, not JsonLines"
+        );
+    }
+
+    #[test]
+    fn test_report_years() {
+        let export = "\
+ID,Market,Token,Amount,Rate,Fiat Rate,Fiat Amount,Fiat,Fee,Category,Created At,Provider
+1,BTC-USD,BTC,1.0000,1000.0000,1000.0000,1000.0000,USD,0.0000,trade,2019-06-01T00:00:00Z,coinbase
+2,BTC-USD,BTC,1.0000,2000.0000,2000.0000,2000.0000,USD,0.0000,trade,2020-06-01T00:00:00Z,coinbase
+3,BTC-USD,BTC,(1.5000),4000.0000,4000.0000,(6000.0000),USD,0.0000,trade,2020-09-01T00:00:00Z,coinbase
+4,BTC-USD,BTC,(0.5000),5000.0000,5000.0000,(2500.0000),USD,0.0000,trade,2021-03-01T00:00:00Z,coinbase
+";
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("transactions.csv");
+        std::fs::write(&input, export).unwrap();
+        let output = dir.path().join("report.csv");
+        let us = UnitedStates {
+            method: Matching::Fifo,
+        };
+
+        block_on(report(
+            2020..=2021,
+            input.to_str(),
+            output.to_str(),
+            &USD,
+            &[],
+            &[],
+            FxRates::new(),
+            &us,
+            None,
+            &None,
+            false,
+            false,
+            dir.path().join("warnings.csv").to_str().unwrap(),
+        ))
+        .unwrap();
+
+        // The lot bought in 2020 is half sold that year and the rest the next
+        let read = |year| std::fs::read_to_string(dir.path().join(format!("report-{}.csv", year)));
+        assert_eq!(
+            read(2020).unwrap(),
+            "Description of property,Date acquired,Date sold or disposed of,Proceeds,Cost basis,Gain or (loss),Short-term or long-term\n\
+             BTC sold via BTC-USD pair,06/01/19,09/01/20,$4000.00,$1000.00,$3000.00,Long-term\n\
+             BTC sold via BTC-USD pair,06/01/20,09/01/20,$2000.00,$1000.00,$1000.00,Short-term\n\
+             Total,,,$6000.00,$2000.00,$4000.00,\n"
+        );
+        assert_eq!(
+            read(2021).unwrap(),
+            "Description of property,Date acquired,Date sold or disposed of,Proceeds,Cost basis,Gain or (loss),Short-term or long-term\n\
+             BTC sold via BTC-USD pair,06/01/20,03/01/21,$2500.00,$1000.00,$1500.00,Short-term\n\
+             Total,,,$2500.00,$1000.00,$1500.00,\n"
+        );
+        // and with nothing to warn about, there's no warnings file
+        assert!(!dir.path().join("warnings.csv").exists());
+    }
+
+    #[test]
+    fn test_write_dispositions() {
+        let realization = |acquired: (i32, u32, u32), proceeds, cost_basis| Realization {
+            amount: BigDecimal::from(1),
+            description: "Ethereum".into(),
+            symbol: Symbol::Crypto(crate::symbol::Crypto::ETH),
+            acquired_when: Some(Utc.ymd(acquired.0, acquired.1, acquired.2).and_hms(0, 0, 0)),
+            disposed_when: Utc.ymd(2021, 12, 1).and_hms(0, 0, 0),
+            proceeds: BigDecimal::from(proceeds),
+            cost_basis: BigDecimal::from(cost_basis),
+            gain: BigDecimal::from(proceeds - cost_basis),
+        };
+        let dispositions = vec![
+            (Disposal::Gift, realization((2021, 1, 1), 4000, 1000)),
+            (Disposal::Donation, realization((2019, 1, 1), 4000, 200)),
+            (Disposal::Donation, realization((2021, 6, 1), 4000, 2500)),
+            (Disposal::Loss, realization((2021, 1, 1), 0, 1000)),
+        ];
+        let us = UnitedStates {
+            method: Matching::Fifo,
+        };
+
+        let mut output = Vec::new();
+        write_dispositions(&mut output, &dispositions, &us, &USD).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Disposal,Description of property,Amount,Date acquired,Date disposed of,Cost basis,Fair market value,Deductible\n\
+             Gift,Ethereum,1.0000,01/01/21,12/01/21,$1000.00,$4000.00,\n\
+             Donation,Ethereum,1.0000,01/01/19,12/01/21,$200.00,$4000.00,$4000.00\n\
+             Donation,Ethereum,1.0000,06/01/21,12/01/21,$2500.00,$4000.00,$2500.00\n\
+             Loss,Ethereum,1.0000,01/01/21,12/01/21,$1000.00,$0.00,$1000.00\n"
+        );
+    }
+
+    #[test]
+    fn test_write_audit() {
+        let realization = Realization {
+            amount: BigDecimal::from(1),
+            description: "BTC sold via BTC-USD pair".into(),
+            symbol: Symbol::Crypto(crate::symbol::Crypto::BTC),
+            acquired_when: Some(Utc.ymd(2021, 1, 1).and_hms(0, 0, 0)),
+            disposed_when: Utc.ymd(2021, 12, 1).and_hms(0, 0, 0),
+            proceeds: BigDecimal::from(4000),
+            cost_basis: BigDecimal::from(1000),
+            gain: BigDecimal::from(3000),
+        };
+        let provenance = Provenance {
+            disposal: Some(Source {
+                id: "3".into(),
+                provider: "coinbase".into(),
+            }),
+            acquisitions: vec![
+                (
+                    Some(Source {
+                        id: "1".into(),
+                        provider: "coinbase-pro".into(),
+                    }),
+                    "0.25".parse().unwrap(),
+                ),
+                (None, BigDecimal::from(1)),
+            ],
+        };
+
+        let mut output = Vec::new();
+        write_audit(&mut output, &[(realization, provenance)], &USD).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Description of property,Amount,Date acquired,Date sold or disposed of,Proceeds,Cost basis,Gain or (loss),Disposal,Acquisitions\n\
+             BTC sold via BTC-USD pair,1.0000,01/01/21,12/01/21,$4000.00,$1000.00,$3000.00,coinbase 3,coinbase-pro 1 (0.2500); unknown (1.0000)\n"
+        );
+    }
 }
@@ -2,15 +2,41 @@ use std::str::FromStr;
 
 use std::fmt::{Debug, Display, Error, Formatter};
 
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+use web3::types::{H160, U256};
+
 pub const USD: Symbol = Symbol::Fiat(Fiat::USD);
 pub const BTC: Symbol = Symbol::Crypto(Crypto::BTC);
 pub const LINK: Symbol = Symbol::Crypto(Crypto::LINK);
 pub const USDT: Symbol = Symbol::Crypto(Crypto::USDT);
+pub const USDC: Symbol = Symbol::Crypto(Crypto::USDC);
+
+// ALIASES maps alternative tickers onto their canonical Symbol. An alias with no provider applies
+// everywhere, otherwise it only applies to tickers reported by that provider.
+const ALIASES: &[(Option<&str>, &str, Symbol)] = &[
+    (None, "CGLD", Symbol::Crypto(Crypto::CELO)),
+    (None, "XBT", Symbol::Crypto(Crypto::BTC)),
+    (Some("kraken"), "XXBT", Symbol::Crypto(Crypto::BTC)),
+    (Some("kraken"), "XETH", Symbol::Crypto(Crypto::ETH)),
+    (Some("kraken"), "XLTC", Symbol::Crypto(Crypto::LTC)),
+    (Some("kraken"), "XXLM", Symbol::Crypto(Crypto::XLM)),
+    (Some("kraken"), "ZCAD", Symbol::Fiat(Fiat::CAD)),
+    (Some("kraken"), "ZEUR", Symbol::Fiat(Fiat::EUR)),
+    (Some("kraken"), "ZGBP", Symbol::Fiat(Fiat::GBP)),
+    (Some("kraken"), "ZJPY", Symbol::Fiat(Fiat::JPY)),
+    (Some("kraken"), "ZUSD", Symbol::Fiat(Fiat::USD)),
+];
 
+// Symbol identifies an asset. Crypto held on an exchange is not tied to any network, whereas
+// crypto held on a specific network, e.g. USDC on Polygon, is a distinct asset written as
+// USDC@polygon. Each NFT is an asset of its own, written as <contract>#<token id>@<network>.
 #[derive(Clone, Copy, Eq, Hash, PartialEq)]
 pub enum Symbol {
     Fiat(Fiat),
     Crypto(Crypto),
+    OnChain(Crypto, Chain),
+    Nft(Nft),
 }
 
 impl Symbol {
@@ -18,8 +44,60 @@ impl Symbol {
         match self {
             Symbol::Fiat(ref symbol) => symbol.symbol(),
             Symbol::Crypto(ref symbol) => symbol.symbol(),
+            Symbol::OnChain(ref symbol, _) => symbol.symbol(),
+            Symbol::Nft(ref nft) => nft.symbol(),
         }
     }
+
+    // on_chain returns the same asset held on the given network, fiat has no network
+    pub fn on_chain(&self, chain: Chain) -> Option<Symbol> {
+        match self {
+            Symbol::Fiat(_) => None,
+            Symbol::Crypto(symbol) | Symbol::OnChain(symbol, _) => {
+                Some(Symbol::OnChain(*symbol, chain))
+            }
+            Symbol::Nft(nft) => Some(Symbol::Nft(Nft { chain, ..*nft })),
+        }
+    }
+
+    // asset is the token whichever network it's held on, identical to the same token held on
+    // any other
+    pub fn asset(&self) -> Symbol {
+        match self {
+            Symbol::OnChain(symbol, _) => Symbol::Crypto(*symbol),
+            symbol => *symbol,
+        }
+    }
+
+    // description names the asset for reports, including the network it is held on
+    pub fn description(&self) -> String {
+        match self {
+            Symbol::OnChain(ref symbol, ref chain) => {
+                format!("{} on {}", symbol.symbol(), chain.name())
+            }
+            Symbol::Nft(ref nft) => format!("{} on {}", nft.symbol(), nft.chain.name()),
+            _ => self.symbol(),
+        }
+    }
+
+    // from_provider parses a ticker as reported by the given provider, resolving any aliases
+    // specific to that provider before falling back to the canonical tickers.
+    pub fn from_provider(provider: &str, s: &str) -> Result<Self, ParseSymbolError> {
+        if let Some((token, chain)) = s.split_once('@') {
+            if token.contains('#') {
+                return s.parse();
+            }
+            return Symbol::from_provider(provider, token)?
+                .on_chain(chain.parse()?)
+                .ok_or(ParseSymbolError {});
+        }
+
+        ALIASES
+            .iter()
+            .find(|(p, alias, _)| *p == Some(provider) && *alias == s)
+            .map(|(_, _, symbol)| Ok(*symbol))
+            .unwrap_or_else(|| s.parse())
+    }
 }
 
 impl Display for Symbol {
@@ -27,16 +105,31 @@ impl Display for Symbol {
         match self {
             Symbol::Fiat(ref symbol) => f.write_fmt(format_args!("{:}", &symbol))?,
             Symbol::Crypto(ref symbol) => f.write_fmt(format_args!("{:}", &symbol))?,
+            Symbol::OnChain(ref symbol, ref chain) => {
+                f.write_fmt(format_args!("{:}@{:}", &symbol, &chain))?
+            }
+            Symbol::Nft(ref nft) => f.write_fmt(format_args!("{:}", &nft))?,
         }
         Ok(())
     }
 }
 
+// Symbols are serialized as they're written, e.g. USDC@polygon
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
 impl Debug for Symbol {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         match self {
             Symbol::Fiat(ref symbol) => f.write_fmt(format_args!("Fiat({:#})", &symbol))?,
             Symbol::Crypto(ref symbol) => f.write_fmt(format_args!("Crypto({:#})", &symbol))?,
+            Symbol::OnChain(ref symbol, ref chain) => {
+                f.write_fmt(format_args!("OnChain({:#}, {:#})", &symbol, &chain))?
+            }
+            Symbol::Nft(ref nft) => f.write_fmt(format_args!("Nft({:})", &nft))?,
         }
         Ok(())
     }
@@ -49,6 +142,19 @@ impl FromStr for Symbol {
     type Err = ParseSymbolError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((token, chain)) = s.split_once('@') {
+            if let Some((contract, token_id)) = token.split_once('#') {
+                return Ok(Symbol::Nft(Nft {
+                    chain: chain.parse()?,
+                    contract: contract.parse().map_err(|_| ParseSymbolError {})?,
+                    token_id: U256::from_dec_str(token_id).map_err(|_| ParseSymbolError {})?,
+                }));
+            }
+            return Symbol::from_str(token)?
+                .on_chain(chain.parse()?)
+                .ok_or(ParseSymbolError {});
+        }
+
         match s {
             "AED" => Ok(Symbol::Fiat(Fiat::AED)),
             "AFN" => Ok(Symbol::Fiat(Fiat::AFN)),
@@ -216,28 +322,42 @@ impl FromStr for Symbol {
             "ZMW" => Ok(Symbol::Fiat(Fiat::ZMW)),
             "ZWB" => Ok(Symbol::Fiat(Fiat::ZWB)),
 
+            "AAVE" => Ok(Symbol::Crypto(Crypto::AAVE)),
             "ALGO" => Ok(Symbol::Crypto(Crypto::ALGO)),
+            "AVAX" => Ok(Symbol::Crypto(Crypto::AVAX)),
             "BAND" => Ok(Symbol::Crypto(Crypto::BAND)),
             "BAT" => Ok(Symbol::Crypto(Crypto::BAT)),
             "BCH" => Ok(Symbol::Crypto(Crypto::BCH)),
+            "BNB" => Ok(Symbol::Crypto(Crypto::BNB)),
             "BSV" => Ok(Symbol::Crypto(Crypto::BSV)),
             "BTC" => Ok(Symbol::Crypto(Crypto::BTC)),
-            "CGLD" => Ok(Symbol::Crypto(Crypto::CGLD)),
+            "CELO" => Ok(Symbol::Crypto(Crypto::CELO)),
             "COMP" => Ok(Symbol::Crypto(Crypto::COMP)),
             "DAI" => Ok(Symbol::Crypto(Crypto::DAI)),
             "EOS" => Ok(Symbol::Crypto(Crypto::EOS)),
             "ETH" => Ok(Symbol::Crypto(Crypto::ETH)),
             "GRT" => Ok(Symbol::Crypto(Crypto::GRT)),
+            "LEND" => Ok(Symbol::Crypto(Crypto::LEND)),
             "LINK" => Ok(Symbol::Crypto(Crypto::LINK)),
             "LTC" => Ok(Symbol::Crypto(Crypto::LTC)),
+            "MATIC" => Ok(Symbol::Crypto(Crypto::MATIC)),
             "MKR" => Ok(Symbol::Crypto(Crypto::MKR)),
             "NMR" => Ok(Symbol::Crypto(Crypto::NMR)),
             "NU" => Ok(Symbol::Crypto(Crypto::NU)),
             "OXT" => Ok(Symbol::Crypto(Crypto::OXT)),
+            "USDC" => Ok(Symbol::Crypto(Crypto::USDC)),
             "USDT" => Ok(Symbol::Crypto(Crypto::USDT)),
+            "WAVAX" => Ok(Symbol::Crypto(Crypto::WAVAX)),
+            "WBNB" => Ok(Symbol::Crypto(Crypto::WBNB)),
+            "WETH" => Ok(Symbol::Crypto(Crypto::WETH)),
+            "WMATIC" => Ok(Symbol::Crypto(Crypto::WMATIC)),
             "XLM" => Ok(Symbol::Crypto(Crypto::XLM)),
 
-            _ => Err(ParseSymbolError {}),
+            _ => ALIASES
+                .iter()
+                .find(|(provider, alias, _)| provider.is_none() && *alias == s)
+                .map(|(_, _, symbol)| *symbol)
+                .ok_or(ParseSymbolError {}),
         }
     }
 }
@@ -758,30 +878,94 @@ impl Fiat {
             // Fiat::Other(ref symbol) => f.write_str(&symbol)?,
         }
     }
+
+    // sign is the prefix used when formatting an amount of the currency, currencies without a
+    // widely recognized sign are prefixed by their code
+    pub fn sign(&self) -> String {
+        match self {
+            Fiat::AUD => "A$".to_string(),
+            Fiat::BRL => "R$".to_string(),
+            Fiat::CAD => "CA$".to_string(),
+            Fiat::CNY => "CN¥".to_string(),
+            Fiat::EUR => "€".to_string(),
+            Fiat::GBP => "£".to_string(),
+            Fiat::HKD => "HK$".to_string(),
+            Fiat::ILS => "₪".to_string(),
+            Fiat::INR => "₹".to_string(),
+            Fiat::JPY => "¥".to_string(),
+            Fiat::KRW => "₩".to_string(),
+            Fiat::MXN => "MX$".to_string(),
+            Fiat::NGN => "₦".to_string(),
+            Fiat::NZD => "NZ$".to_string(),
+            Fiat::PHP => "₱".to_string(),
+            Fiat::RUB => "₽".to_string(),
+            Fiat::SGD => "S$".to_string(),
+            Fiat::THB => "฿".to_string(),
+            Fiat::TRY => "₺".to_string(),
+            Fiat::UAH => "₴".to_string(),
+            Fiat::USD => "$".to_string(),
+            Fiat::VND => "₫".to_string(),
+            _ => format!("{} ", self.symbol()),
+        }
+    }
+
+    // decimals is the number of minor units of the currency as given by ISO 4217
+    pub fn decimals(&self) -> usize {
+        match self {
+            Fiat::BIF
+            | Fiat::CLP
+            | Fiat::DJF
+            | Fiat::GNF
+            | Fiat::ISK
+            | Fiat::JPY
+            | Fiat::KMF
+            | Fiat::KRW
+            | Fiat::PYG
+            | Fiat::RWF
+            | Fiat::UGX
+            | Fiat::VND
+            | Fiat::VUV
+            | Fiat::XAF
+            | Fiat::XOF
+            | Fiat::XPF => 0,
+            Fiat::BHD | Fiat::IQD | Fiat::JOD | Fiat::KWD | Fiat::LYD | Fiat::OMR | Fiat::TND => 3,
+            _ => 2,
+        }
+    }
 }
 
 #[allow(dead_code)]
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum Crypto {
+    AAVE,
     ALGO,
+    AVAX,
     BAND,
     BAT,
     BCH,
+    BNB,
     BSV,
     BTC,
-    CGLD,
+    CELO,
     COMP,
     DAI,
     EOS,
     ETH,
     GRT,
+    LEND,
     LINK,
     LTC,
+    MATIC,
     MKR,
     NMR,
     NU,
     OXT,
+    USDC,
     USDT,
+    WAVAX,
+    WBNB,
+    WETH,
+    WMATIC,
     XLM,
     // Other(String),
 }
@@ -789,25 +973,35 @@ pub enum Crypto {
 impl Display for Crypto {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         match self {
+            Crypto::AAVE => f.write_str("AAVE")?,
             Crypto::ALGO => f.write_str("ALGO")?,
+            Crypto::AVAX => f.write_str("AVAX")?,
             Crypto::BAND => f.write_str("BAND")?,
             Crypto::BAT => f.write_str("BAT")?,
             Crypto::BCH => f.write_str("BCH")?,
+            Crypto::BNB => f.write_str("BNB")?,
             Crypto::BSV => f.write_str("BSV")?,
             Crypto::BTC => f.write_str("BTC")?,
-            Crypto::CGLD => f.write_str("CGLD")?,
+            Crypto::CELO => f.write_str("CELO")?,
             Crypto::COMP => f.write_str("COMP")?,
             Crypto::DAI => f.write_str("DAI")?,
             Crypto::EOS => f.write_str("EOS")?,
             Crypto::ETH => f.write_str("ETH")?,
             Crypto::GRT => f.write_str("GRT")?,
+            Crypto::LEND => f.write_str("LEND")?,
             Crypto::LINK => f.write_str("LINK")?,
             Crypto::LTC => f.write_str("LTC")?,
+            Crypto::MATIC => f.write_str("MATIC")?,
             Crypto::MKR => f.write_str("MKR")?,
             Crypto::NMR => f.write_str("NMR")?,
             Crypto::NU => f.write_str("NU")?,
             Crypto::OXT => f.write_str("OXT")?,
+            Crypto::USDC => f.write_str("USDC")?,
             Crypto::USDT => f.write_str("USDT")?,
+            Crypto::WAVAX => f.write_str("WAVAX")?,
+            Crypto::WBNB => f.write_str("WBNB")?,
+            Crypto::WETH => f.write_str("WETH")?,
+            Crypto::WMATIC => f.write_str("WMATIC")?,
             Crypto::XLM => f.write_str("XLM")?,
             // Crypto::Other(ref symbol) => f.write_str(&symbol)?,
         }
@@ -820,3 +1014,233 @@ impl Crypto {
         format!("{}", self)
     }
 }
+
+// Nft is a non-fungible token, or one token id of a multi token (ERC-1155) contract
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Nft {
+    pub chain: Chain,
+    pub contract: H160,
+    pub token_id: U256,
+}
+
+impl Nft {
+    pub fn symbol(&self) -> String {
+        format!("{:#x}#{}", self.contract, self.token_id)
+    }
+}
+
+impl Display for Nft {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        f.write_fmt(format_args!("{:}@{:}", self.symbol(), self.chain))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Chain {
+    Arbitrum,
+    Avalanche,
+    Base,
+    Bitcoin,
+    BSC,
+    Ethereum,
+    Optimism,
+    Polygon,
+}
+
+impl Chain {
+    // name is the human readable name of the network
+    pub fn name(&self) -> &'static str {
+        match self {
+            Chain::Arbitrum => "Arbitrum",
+            Chain::Avalanche => "Avalanche",
+            Chain::Base => "Base",
+            Chain::Bitcoin => "Bitcoin",
+            Chain::BSC => "BNB Smart Chain",
+            Chain::Ethereum => "Ethereum",
+            Chain::Optimism => "Optimism",
+            Chain::Polygon => "Polygon",
+        }
+    }
+
+    // native_token is the token used to pay for gas on the network
+    pub fn native_token(&self) -> Crypto {
+        match self {
+            Chain::Avalanche => Crypto::AVAX,
+            Chain::Bitcoin => Crypto::BTC,
+            Chain::BSC => Crypto::BNB,
+            Chain::Polygon => Crypto::MATIC,
+            Chain::Arbitrum | Chain::Base | Chain::Ethereum | Chain::Optimism => Crypto::ETH,
+        }
+    }
+
+    // wrapped_native_token is the ERC-20 wrapping of the native token that NFTs are usually paid
+    // for in, Bitcoin has none
+    pub fn wrapped_native_token(&self) -> Option<Crypto> {
+        match self {
+            Chain::Avalanche => Some(Crypto::WAVAX),
+            Chain::Bitcoin => None,
+            Chain::BSC => Some(Crypto::WBNB),
+            Chain::Polygon => Some(Crypto::WMATIC),
+            Chain::Arbitrum | Chain::Base | Chain::Ethereum | Chain::Optimism => Some(Crypto::WETH),
+        }
+    }
+
+    // explorer_name is the block explorer transactions on the network are fetched from, and the
+    // provider they're reported under
+    pub fn explorer_name(&self) -> &'static str {
+        match self {
+            Chain::Arbitrum => "arbiscan",
+            Chain::Avalanche => "snowtrace",
+            Chain::Base => "basescan",
+            Chain::Bitcoin => "esplora",
+            Chain::BSC => "bscscan",
+            Chain::Ethereum => "etherscan",
+            Chain::Optimism => "optimistic-etherscan",
+            Chain::Polygon => "polygonscan",
+        }
+    }
+
+    // explorer_url is the API of the network's block explorer, Esplora for Bitcoin and
+    // Etherscan compatible for everything else
+    pub fn explorer_url(&self) -> &'static str {
+        match self {
+            Chain::Arbitrum => "https://api.arbiscan.io/api",
+            Chain::Avalanche => "https://api.snowtrace.io/api",
+            Chain::Base => "https://api.basescan.org/api",
+            Chain::Bitcoin => "https://blockstream.info/api",
+            Chain::BSC => "https://api.bscscan.com/api",
+            Chain::Ethereum => "https://api.etherscan.io/api",
+            Chain::Optimism => "https://api-optimistic.etherscan.io/api",
+            Chain::Polygon => "https://api.polygonscan.com/api",
+        }
+    }
+}
+
+impl Display for Chain {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            Chain::Arbitrum => f.write_str("arbitrum")?,
+            Chain::Avalanche => f.write_str("avalanche")?,
+            Chain::Base => f.write_str("base")?,
+            Chain::Bitcoin => f.write_str("bitcoin")?,
+            Chain::BSC => f.write_str("bsc")?,
+            Chain::Ethereum => f.write_str("ethereum")?,
+            Chain::Optimism => f.write_str("optimism")?,
+            Chain::Polygon => f.write_str("polygon")?,
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Chain {
+    type Err = ParseSymbolError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "arbitrum" => Ok(Chain::Arbitrum),
+            "avalanche" => Ok(Chain::Avalanche),
+            "base" => Ok(Chain::Base),
+            "bitcoin" => Ok(Chain::Bitcoin),
+            "bsc" => Ok(Chain::BSC),
+            "ethereum" => Ok(Chain::Ethereum),
+            "optimism" => Ok(Chain::Optimism),
+            "polygon" | "matic" => Ok(Chain::Polygon),
+            _ => Err(ParseSymbolError {}),
+        }
+    }
+}
+
+// Chain is configured by the same names it's parsed from
+impl<'de> Deserialize<'de> for Chain {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let input = String::deserialize(deserializer)?;
+        input
+            .parse()
+            .map_err(|_e| de::Error::invalid_value(de::Unexpected::Str(&input), &"a network"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_symbol() {
+        assert_eq!("BTC".parse(), Ok(BTC));
+        assert_eq!("USD".parse(), Ok(USD));
+        assert_eq!("XBT".parse(), Ok(BTC));
+        assert_eq!("CGLD".parse(), Ok(Symbol::Crypto(Crypto::CELO)));
+        assert_eq!("XXBT".parse::<Symbol>(), Err(ParseSymbolError {}));
+    }
+
+    #[test]
+    fn test_parse_symbol_on_chain() {
+        let usdc_polygon = Symbol::OnChain(Crypto::USDC, Chain::Polygon);
+
+        assert_eq!("USDC@polygon".parse(), Ok(usdc_polygon));
+        assert_eq!("USDC@Polygon".parse(), Ok(usdc_polygon));
+        assert_ne!(usdc_polygon, "USDC@arbitrum".parse().unwrap());
+        assert_ne!(usdc_polygon, USDC);
+        assert_eq!("USD@polygon".parse::<Symbol>(), Err(ParseSymbolError {}));
+        assert_eq!("USDC@moon".parse::<Symbol>(), Err(ParseSymbolError {}));
+
+        assert_eq!(format!("{}", usdc_polygon), "USDC@polygon");
+        assert_eq!(usdc_polygon.symbol(), "USDC");
+        assert_eq!(usdc_polygon.description(), "USDC on Polygon");
+        assert_eq!(
+            Symbol::from_provider("kraken", "XETH@arbitrum"),
+            Ok(Symbol::OnChain(Crypto::ETH, Chain::Arbitrum))
+        );
+    }
+
+    #[test]
+    fn test_parse_symbol_nft() {
+        let punk = "0xb47e3cd837ddf8e4c57f05d70ab865de6e193bbb#1234@ethereum";
+        let symbol: Symbol = punk.parse().unwrap();
+
+        assert_eq!(
+            symbol,
+            Symbol::Nft(Nft {
+                chain: Chain::Ethereum,
+                contract: "b47e3cd837ddf8e4c57f05d70ab865de6e193bbb".parse().unwrap(),
+                token_id: 1234.into(),
+            })
+        );
+        assert_eq!(format!("{}", symbol), punk);
+        assert_eq!(Symbol::from_provider("etherscan", punk), Ok(symbol));
+        assert_ne!(
+            symbol,
+            "0xb47e3cd837ddf8e4c57f05d70ab865de6e193bbb#1235@ethereum"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(
+            "0xb47e#1234@ethereum".parse::<Symbol>(),
+            Err(ParseSymbolError {})
+        );
+    }
+
+    #[test]
+    fn test_deserialize_chain() {
+        let chain: Chain = serde_json::from_str("\"MATIC\"").unwrap();
+        assert_eq!(chain, Chain::Polygon);
+        let chain: Chain = serde_json::from_str("\"Ethereum\"").unwrap();
+        assert_eq!(chain, Chain::Ethereum);
+        assert!(serde_json::from_str::<Chain>("\"solana\"").is_err());
+    }
+
+    #[test]
+    fn test_symbol_from_provider() {
+        assert_eq!(Symbol::from_provider("kraken", "XXBT"), Ok(BTC));
+        assert_eq!(Symbol::from_provider("kraken", "ZUSD"), Ok(USD));
+        assert_eq!(Symbol::from_provider("kraken", "XBT"), Ok(BTC));
+        assert_eq!(
+            Symbol::from_provider("coinbase", "CGLD"),
+            Ok(Symbol::Crypto(Crypto::CELO))
+        );
+        assert_eq!(
+            Symbol::from_provider("coinbase", "XXBT"),
+            Err(ParseSymbolError {})
+        );
+    }
+}
@@ -1,26 +1,114 @@
 use std::cmp::Ordering;
+use std::fmt;
+use std::io::Write;
 
 use bigdecimal::{BigDecimal, FromPrimitive, ParseBigDecimalError, Zero};
 use chrono::ParseError;
 use regex::Regex;
 use serde::de::{self, Deserializer};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+use crate::symbol::Symbol;
 
 pub type DateTime = chrono::DateTime<chrono::Utc>;
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Debug, Serialize)]
 pub struct Transaction {
     pub id: String,
+    // hash is the on-chain transaction that made a transfer, shared by every account it moved
+    // tokens between, none for transactions made off-chain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
     pub market: String,
     pub token: String,
     pub amount: BigDecimal,
     pub rate: BigDecimal,
-    pub usd_rate: BigDecimal,
-    pub usd_amount: BigDecimal,
+    // fiat_rate and fiat_amount are the value of the transaction in the fiat currency it was
+    // recorded in, which may differ from the reporting currency
+    pub fiat_rate: BigDecimal,
+    pub fiat_amount: BigDecimal,
+    pub fiat: String,
+    // fee is the network fee paid by the sender, in units of token
+    pub fee: BigDecimal,
+    pub category: Category,
     pub created_at: Option<DateTime>,
     pub provider: &'static str,
 }
 
+// Balance is how much of a token a provider reports holding now, named as the provider names it
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Balance {
+    pub token: String,
+    pub amount: BigDecimal,
+    pub provider: &'static str,
+}
+
+// Category is what kind of transaction it was, income is acquired at its market value when
+// received and is taxed as ordinary income rather than as a capital gain
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    #[default]
+    Trade,
+    Staking,
+    Mining,
+    Interest,
+    Reward,
+    // a new token credited to holders of another when its chain split, e.g. BCH from BTC
+    Fork,
+    // a crypto to crypto conversion, recorded as a sale of one token and a purchase of the other
+    // at their market value
+    Conversion,
+    // tokens moved between accounts, which are neither bought nor sold
+    TransferIn,
+    TransferOut,
+    // fiat paid into or out of an exchange
+    Deposit,
+    Withdrawal,
+}
+
+impl Category {
+    pub fn is_income(&self) -> bool {
+        matches!(
+            self,
+            Category::Staking
+                | Category::Mining
+                | Category::Interest
+                | Category::Reward
+                | Category::Fork
+        )
+    }
+
+    // is_transfer returns true if the transaction only moved funds, without changing what's owned
+    pub fn is_transfer(&self) -> bool {
+        matches!(
+            self,
+            Category::TransferIn | Category::TransferOut | Category::Deposit | Category::Withdrawal
+        )
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Category::Trade => "trade",
+            Category::Staking => "staking",
+            Category::Mining => "mining",
+            Category::Interest => "interest",
+            Category::Reward => "reward",
+            Category::Fork => "fork",
+            Category::Conversion => "conversion",
+            Category::TransferIn => "transfer_in",
+            Category::TransferOut => "transfer_out",
+            Category::Deposit => "deposit",
+            Category::Withdrawal => "withdrawal",
+        };
+        f.write_str(name)
+    }
+}
+
 impl PartialEq for Transaction {
     fn eq(&self, other: &Self) -> bool {
         self.id == other.id && self.created_at == other.created_at
@@ -47,11 +135,21 @@ impl Ord for Transaction {
     }
 }
 
-pub fn format_usd_amount(amount: &BigDecimal) -> String {
+// format_fiat_amount formats an amount of the denomination, with the currency sign and minor units
+// of fiat denominations
+pub fn format_fiat_amount(amount: &BigDecimal, denomination: &Symbol) -> String {
+    let formatted = match denomination {
+        Symbol::Fiat(fiat) => {
+            let decimals = fiat.decimals();
+            let rounded = amount.abs().round(decimals as i64);
+            format!("{}{:.*}", fiat.sign(), decimals, rounded)
+        }
+        _ => format!("{:.8} {}", amount.abs(), denomination.symbol()),
+    };
     if amount < &BigDecimal::zero() {
-        format!("(${:.4})", amount.abs())
+        format!("({})", formatted)
     } else {
-        format!("${:.4}", amount)
+        formatted
     }
 }
 
@@ -63,6 +161,16 @@ pub fn format_amount(amount: &BigDecimal) -> String {
     }
 }
 
+// format_exact_amount formats an amount with every decimal it has, for amounts that are read back
+// in such as fees of a few satoshis
+pub fn format_exact_amount(amount: &BigDecimal) -> String {
+    if amount < &BigDecimal::zero() {
+        format!("({})", amount.abs())
+    } else {
+        amount.to_string()
+    }
+}
+
 pub fn format_amount_for_turbotax(amount: &BigDecimal) -> String {
     if amount < &BigDecimal::zero() {
         format!("({:.8})", amount.abs())
@@ -97,6 +205,25 @@ pub fn parse_date(input: &str) -> Result<DateTime, ParseError> {
     Ok(chrono::DateTime::parse_from_rfc3339(input)?.with_timezone(&chrono::Utc))
 }
 
+// write_json writes values as a JSON array, or as JSON Lines with one value to each line.
+// Amounts are written as exact decimal strings and dates in RFC 3339.
+pub fn write_json<T: Serialize>(
+    output: &mut dyn Write,
+    values: &[T],
+    lines: bool,
+) -> anyhow::Result<()> {
+    if lines {
+        for value in values {
+            serde_json::to_writer(&mut *output, value)?;
+            writeln!(output)?;
+        }
+    } else {
+        serde_json::to_writer_pretty(&mut *output, values)?;
+        writeln!(output)?;
+    }
+    Ok(())
+}
+
 #[test]
 fn test_parse_amount() {
     assert_eq!(parse_amount("0"), Ok(BigDecimal::from_f32(0.0).unwrap()));
@@ -114,3 +241,25 @@ fn test_parse_amount() {
 
     assert!(parse_amount("").is_err());
 }
+
+#[test]
+fn test_format_fiat_amount() {
+    use crate::symbol::{Fiat, BTC, USD};
+
+    let amount: BigDecimal = "1234.5678".parse().unwrap();
+    assert_eq!(format_fiat_amount(&amount, &USD), "$1234.57");
+    assert_eq!(format_fiat_amount(&-&amount, &USD), "($1234.57)");
+    assert_eq!(
+        format_fiat_amount(&amount, &Symbol::Fiat(Fiat::EUR)),
+        "€1234.57"
+    );
+    assert_eq!(
+        format_fiat_amount(&amount, &Symbol::Fiat(Fiat::JPY)),
+        "¥1235"
+    );
+    assert_eq!(
+        format_fiat_amount(&amount, &Symbol::Fiat(Fiat::KWD)),
+        "KWD 1234.568"
+    );
+    assert_eq!(format_fiat_amount(&amount, &BTC), "1234.56780000 BTC");
+}
@@ -2,17 +2,47 @@ use std::fmt;
 
 use bigdecimal::{BigDecimal, Zero};
 
+use crate::portfolio::Acquisition;
 use crate::symbol::Symbol;
 use crate::types::DateTime;
 
 #[derive(Debug)]
 pub struct Lot {
     // amount represents a count of items in a lot
-    amount: BigDecimal,
+    pub amount: BigDecimal,
     // unit_cost represents the cost of each item in a lot
-    unit_cost: BigDecimal,
+    pub unit_cost: BigDecimal,
     // date_of_purchase represents the date at which the lot was acquired
-    date_of_purchase: DateTime,
+    pub date_of_purchase: DateTime,
+    // acquisitions are the transactions the lot was acquired through, the latest first, with the
+    // share of each that's still in the lot
+    pub acquisitions: Vec<Acquisition>,
+}
+
+impl Lot {
+    // the total cost of every item in the lot
+    pub fn cost_basis(&self) -> BigDecimal {
+        &self.amount * &self.unit_cost
+    }
+
+    // split takes some items out of the lot, along with their share of each acquisition
+    fn split(&mut self, amount: &BigDecimal) -> Lot {
+        let fraction = amount / &self.amount;
+        let rest = BigDecimal::from(1) - &fraction;
+        let taken = Lot {
+            amount: amount.clone(),
+            unit_cost: self.unit_cost.clone(),
+            date_of_purchase: self.date_of_purchase,
+            acquisitions: self
+                .acquisitions
+                .iter()
+                .map(|a| a.scale(&fraction))
+                .collect(),
+        };
+        self.acquisitions = self.acquisitions.iter().map(|a| a.scale(&rest)).collect();
+        self.amount -= amount;
+        taken
+    }
 }
 
 #[derive(Debug)]
@@ -21,6 +51,8 @@ pub struct Sale {
     pub cost_basis: BigDecimal,
     // when was the first purchase of tokens made
     pub date_of_purchase: Option<DateTime>,
+    // the transactions the tokens were acquired through, with the share of each that was sold
+    pub acquisitions: Vec<Acquisition>,
 }
 
 pub struct Wallet {
@@ -28,6 +60,10 @@ pub struct Wallet {
     pub cumulative_bought: BigDecimal,
     pub cumulative_sold: BigDecimal,
     lots: Vec<Lot>,
+    // average pools every lot into one at their average unit cost, rather than keeping each
+    average: bool,
+    // lifo sells the most recently bought lots first
+    lifo: bool,
 }
 
 impl Wallet {
@@ -37,25 +73,98 @@ impl Wallet {
             cumulative_bought: BigDecimal::zero(),
             cumulative_sold: BigDecimal::zero(),
             lots: Vec::new(),
+            average: false,
+            lifo: false,
+        }
+    }
+
+    // average creates a wallet that holds a single lot at the average cost of everything bought,
+    // the adjusted cost base of identical properties as required in Canada
+    pub fn average(token: &Symbol) -> Wallet {
+        Wallet {
+            average: true,
+            ..Wallet::new(token)
+        }
+    }
+
+    // lifo creates a wallet that sells the last lot bought first
+    pub fn lifo(token: &Symbol) -> Wallet {
+        Wallet {
+            lifo: true,
+            ..Wallet::new(token)
         }
     }
 
     // add_lot adds a purchase of some unit of an item, with a count and a total cost
     pub fn add_lot(&mut self, amount: &BigDecimal, unit_cost: &BigDecimal, date: DateTime) {
-        self.cumulative_bought += amount;
-        self.lots.push(Lot {
+        self.add(Lot {
             amount: amount.clone(),
             unit_cost: unit_cost.clone(),
             date_of_purchase: date,
+            acquisitions: Vec::new(),
         });
     }
 
+    // add adds a lot along with the transactions it was acquired through
+    pub fn add(&mut self, mut lot: Lot) {
+        self.cumulative_bought += &lot.amount;
+        if let (true, Some(pool)) = (self.average, self.lots.first_mut()) {
+            let total = &pool.amount + &lot.amount;
+            pool.unit_cost = (pool.cost_basis() + lot.cost_basis()) / &total;
+            pool.amount = total;
+            pool.acquisitions.append(&mut lot.acquisitions);
+            return;
+        }
+        // Lots are sold from the front, in order of purchase, so the latest goes first when
+        // selling last in, first out. Lots carried over from other tokens can be older than those
+        // already held.
+        let position = if self.lifo {
+            self.lots
+                .iter()
+                .position(|held| held.date_of_purchase <= lot.date_of_purchase)
+        } else {
+            self.lots
+                .iter()
+                .position(|held| held.date_of_purchase > lot.date_of_purchase)
+        };
+        self.lots.insert(position.unwrap_or(self.lots.len()), lot);
+    }
+
+    // migrate moves every lot into another wallet, each token becoming ratio tokens of the other,
+    // keeping the date of purchase and total cost of each lot
+    pub fn migrate(&mut self, into: &mut Wallet, ratio: &BigDecimal) {
+        for lot in self.lots.drain(..) {
+            into.add(Lot {
+                amount: &lot.amount * ratio,
+                unit_cost: &lot.unit_cost / ratio,
+                date_of_purchase: lot.date_of_purchase,
+                acquisitions: lot.acquisitions,
+            });
+        }
+    }
+
+    // add_cost adds to the cost of every token held, spread evenly across them, returning false if
+    // there are none to add it to
+    pub fn add_cost(&mut self, cost: &BigDecimal) -> bool {
+        let count = self.count();
+        if count.is_zero() {
+            return false;
+        }
+        let unit_cost = cost / count;
+        for lot in self.lots.iter_mut() {
+            lot.unit_cost += &unit_cost;
+        }
+        true
+    }
+
+    // the lots held, in the order they'll be sold
+    pub fn lots(&self) -> &[Lot] {
+        &self.lots
+    }
+
     // the total cost basis of everything in this wallet
     pub fn cost_basis(&self) -> BigDecimal {
-        self.lots
-            .iter()
-            .map(|lot| &lot.amount * &lot.unit_cost)
-            .sum()
+        self.lots.iter().map(|lot| lot.cost_basis()).sum()
     }
 
     // the number of tokens stored in this wallet
@@ -63,47 +172,40 @@ impl Wallet {
         self.lots.iter().map(|lot| &lot.amount).sum()
     }
 
-    // sell some tokens, returning the Sale, remove any lots that were completely consumed
-    pub fn sell(&mut self, amount: &BigDecimal) -> Sale {
+    // take removes some tokens, returning the lots, or parts of lots, they were taken from and
+    // however many couldn't be taken as there weren't enough lots left
+    pub fn take(&mut self, amount: &BigDecimal) -> (Vec<Lot>, BigDecimal) {
         self.cumulative_sold += amount;
 
-        let mut date_of_purchase: Option<DateTime> = None;
-
-        let mut total_cost = BigDecimal::zero();
-        let mut lots_consumed = 0;
+        let mut taken = Vec::new();
         let mut amount_to_consume = amount.clone();
+        while amount_to_consume > BigDecimal::zero() {
+            let Some(lot) = self.lots.first_mut() else {
+                break;
+            };
 
-        for lot in self.lots.iter_mut() {
-            if date_of_purchase.is_none() {
-                date_of_purchase = Some(lot.date_of_purchase);
-            }
-
-            if &amount_to_consume < &lot.amount {
-                lot.amount -= &amount_to_consume;
-                total_cost += &amount_to_consume * &lot.unit_cost;
+            if amount_to_consume < lot.amount {
+                taken.push(lot.split(&amount_to_consume));
                 amount_to_consume = BigDecimal::zero();
                 break;
             }
 
-            total_cost += &lot.amount * &lot.unit_cost;
             amount_to_consume -= &lot.amount;
-            lots_consumed += 1;
+            taken.push(self.lots.remove(0));
         }
 
-        if amount_to_consume > BigDecimal::zero() {
-            eprintln!(
-                "Sale of {:.2} could not be satisfied, amount to consume = {:.2} (only {:.2} was sold)",
-                self.token,
-                amount_to_consume,
-                amount - &amount_to_consume
-            );
-        }
+        (taken, amount_to_consume)
+    }
 
-        self.lots.drain(..lots_consumed);
+    // sell some tokens, returning the Sale, remove any lots that were completely consumed. Any more
+    // than is held is sold at no cost, Portfolio::shortfalls finds those sales.
+    pub fn sell(&mut self, amount: &BigDecimal) -> Sale {
+        let (taken, _) = self.take(amount);
 
         Sale {
-            cost_basis: total_cost,
-            date_of_purchase,
+            cost_basis: taken.iter().map(|lot| lot.cost_basis()).sum(),
+            date_of_purchase: taken.first().map(|lot| lot.date_of_purchase),
+            acquisitions: taken.into_iter().flat_map(|lot| lot.acquisitions).collect(),
         }
     }
 }
@@ -113,10 +215,11 @@ impl fmt::Debug for Wallet {
         for lot in self.lots.iter() {
             write!(
                 f,
-                "lot {} units at {} = {}, ",
+                "lot {} {} at {} = {}, ",
                 lot.amount,
+                self.token.symbol(),
                 lot.unit_cost,
-                &lot.amount * &lot.unit_cost
+                lot.cost_basis()
             )?;
         }
         Ok(())
@@ -129,7 +232,8 @@ mod test {
     use chrono::offset::TimeZone;
     use chrono::Utc;
 
-    use crate::symbol::BTC;
+    use crate::amount::Amount;
+    use crate::symbol::{Crypto, Fiat, Symbol, BTC};
 
     use super::*;
 
@@ -226,4 +330,169 @@ mod test {
 
         assert!(wallet.count().is_zero());
     }
+
+    #[test]
+    fn test_wallet_migrate() {
+        let mut lend = Wallet::new(&Symbol::Crypto(Crypto::LEND));
+        let mut aave = Wallet::new(&Symbol::Crypto(Crypto::AAVE));
+
+        lend.add_lot(
+            &BigDecimal::from_f32(500.0).unwrap(),
+            &BigDecimal::from_f32(0.5).unwrap(),
+            Utc.ymd(2019, 1, 1).and_hms(0, 0, 0),
+        );
+        lend.migrate(&mut aave, &"0.01".parse().unwrap());
+
+        assert!(lend.count().is_zero());
+        assert_eq!(aave.count(), BigDecimal::from_f32(5.0).unwrap());
+        assert_eq!(aave.cost_basis(), BigDecimal::from_f32(250.0).unwrap());
+
+        let sale = aave.sell(&BigDecimal::from_f32(5.0).unwrap());
+        assert_eq!(sale.cost_basis, BigDecimal::from_f32(250.0).unwrap());
+        assert_eq!(
+            sale.date_of_purchase,
+            Some(Utc.ymd(2019, 1, 1).and_hms(0, 0, 0))
+        );
+    }
+
+    #[test]
+    fn test_wallet_migrate_in_order() {
+        let mut lend = Wallet::new(&Symbol::Crypto(Crypto::LEND));
+        lend.add_lot(
+            &BigDecimal::from(100),
+            &BigDecimal::from(1),
+            Utc.ymd(2019, 1, 1).and_hms(0, 0, 0),
+        );
+
+        // Migrated lots are sold in order of purchase among those already held
+        let mut aave = Wallet::lifo(&Symbol::Crypto(Crypto::AAVE));
+        aave.add_lot(
+            &BigDecimal::from(1),
+            &BigDecimal::from(200),
+            Utc.ymd(2020, 1, 1).and_hms(0, 0, 0),
+        );
+        lend.migrate(&mut aave, &"0.01".parse().unwrap());
+        assert_eq!(aave.cumulative_bought, BigDecimal::from(2));
+        let sale = aave.sell(&BigDecimal::from(1));
+        assert_eq!(sale.cost_basis, BigDecimal::from(200));
+
+        // and pooled with them at their average cost
+        lend.add_lot(
+            &BigDecimal::from(100),
+            &BigDecimal::from(1),
+            Utc.ymd(2019, 1, 1).and_hms(0, 0, 0),
+        );
+        let mut aave = Wallet::average(&Symbol::Crypto(Crypto::AAVE));
+        aave.add_lot(
+            &BigDecimal::from(1),
+            &BigDecimal::from(200),
+            Utc.ymd(2020, 1, 1).and_hms(0, 0, 0),
+        );
+        lend.migrate(&mut aave, &"0.01".parse().unwrap());
+        assert_eq!(aave.lots().len(), 1);
+        let sale = aave.sell(&BigDecimal::from(1));
+        assert_eq!(sale.cost_basis, BigDecimal::from(150));
+    }
+
+    #[test]
+    fn test_wallet_average() {
+        let mut wallet = Wallet::average(&BTC);
+
+        wallet.add_lot(
+            &BigDecimal::from_f32(10.0).unwrap(),
+            &BigDecimal::from_f32(1.0).unwrap(),
+            Utc.ymd(2018, 1, 1).and_hms(0, 0, 0),
+        );
+        wallet.add_lot(
+            &BigDecimal::from_f32(30.0).unwrap(),
+            &BigDecimal::from_f32(3.0).unwrap(),
+            Utc.ymd(2018, 2, 1).and_hms(0, 0, 0),
+        );
+
+        let sale = wallet.sell(&BigDecimal::from_f32(20.0).unwrap());
+        assert_eq!(sale.cost_basis, BigDecimal::from_f32(50.0).unwrap());
+        assert_eq!(
+            sale.date_of_purchase,
+            Some(Utc.ymd(2018, 1, 1).and_hms(0, 0, 0))
+        );
+
+        assert!(wallet.add_cost(&BigDecimal::from_f32(10.0).unwrap()));
+        assert_eq!(wallet.cost_basis(), BigDecimal::from_f32(60.0).unwrap());
+
+        wallet.sell(&BigDecimal::from_f32(20.0).unwrap());
+        assert!(!wallet.add_cost(&BigDecimal::from_f32(10.0).unwrap()));
+    }
+
+    #[test]
+    fn test_wallet_lifo() {
+        let mut wallet = Wallet::lifo(&BTC);
+
+        wallet.add_lot(
+            &BigDecimal::from_f32(10.0).unwrap(),
+            &BigDecimal::from_f32(1.0).unwrap(),
+            Utc.ymd(2018, 1, 1).and_hms(0, 0, 0),
+        );
+        wallet.add_lot(
+            &BigDecimal::from_f32(10.0).unwrap(),
+            &BigDecimal::from_f32(3.0).unwrap(),
+            Utc.ymd(2018, 2, 1).and_hms(0, 0, 0),
+        );
+
+        let sale = wallet.sell(&BigDecimal::from_f32(15.0).unwrap());
+        assert_eq!(sale.cost_basis, BigDecimal::from_f32(35.0).unwrap());
+        assert_eq!(
+            sale.date_of_purchase,
+            Some(Utc.ymd(2018, 2, 1).and_hms(0, 0, 0))
+        );
+        assert_eq!(wallet.count(), BigDecimal::from_f32(5.0).unwrap());
+        assert_eq!(
+            wallet.lots()[0].date_of_purchase,
+            Utc.ymd(2018, 1, 1).and_hms(0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_wallet_take() {
+        let mut wallet = Wallet::new(&BTC);
+        let acquisition = |amount: u32, cost: u32| Acquisition {
+            source: None,
+            when: Utc.ymd(2018, 1, 1).and_hms(0, 0, 0),
+            amount: Amount {
+                amount: BigDecimal::from(amount),
+                symbol: BTC,
+            },
+            fraction: BigDecimal::from(1),
+            cost: Amount {
+                amount: BigDecimal::from(cost),
+                symbol: Symbol::Fiat(Fiat::USD),
+            },
+        };
+
+        wallet.add(Lot {
+            amount: BigDecimal::from(4),
+            unit_cost: BigDecimal::from(100),
+            date_of_purchase: Utc.ymd(2018, 1, 1).and_hms(0, 0, 0),
+            acquisitions: vec![acquisition(4, 400)],
+        });
+
+        // Each part of a lot taken keeps its share of the acquisitions, and so does what's left
+        let (taken, remainder) = wallet.take(&BigDecimal::from(1));
+        assert!(remainder.is_zero());
+        assert_eq!(taken.len(), 1);
+        assert_eq!(taken[0].cost_basis(), BigDecimal::from(100));
+        assert_eq!(
+            taken[0].acquisitions,
+            vec![acquisition(4, 400).scale(&"0.25".parse().unwrap())]
+        );
+        assert_eq!(
+            wallet.lots()[0].acquisitions,
+            vec![acquisition(4, 400).scale(&"0.75".parse().unwrap())]
+        );
+
+        let (taken, remainder) = wallet.take(&BigDecimal::from(5));
+        assert_eq!(remainder, BigDecimal::from(2));
+        assert_eq!(taken[0].amount, BigDecimal::from(3));
+        assert_eq!(taken[0].acquisitions[0].fraction, "0.75".parse().unwrap());
+        assert!(wallet.lots().is_empty());
+    }
 }